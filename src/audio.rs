@@ -0,0 +1,290 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, FormatReader, Track};
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::resample::{ResampleQuality, Resampler};
+
+/// Sample rate Whisper expects its input to be in.
+pub const WHISPER_SAMPLE_RATE: u32 = 16000;
+
+/// whisper.cpp skips audio shorter than a second, so short clips are padded
+/// with silence up to this many samples.
+pub(crate) const MIN_WHISPER_SAMPLES: usize = WHISPER_SAMPLE_RATE as usize * 11 / 10;
+
+/// Path that stands for standard input, as in `--input -`.
+pub const STDIN_PATH: &str = "-";
+
+/// Whether `path` means standard input rather than a file.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN_PATH)
+}
+
+/// Decode an audio file, or standard input if `path` is `-`, into mono
+/// f32 samples at [`WHISPER_SAMPLE_RATE`].
+pub fn load_audio_file(path: &Path) -> Result<Vec<f32>> {
+    load_audio_track(path, None, ResampleQuality::default())
+}
+
+/// Like [`load_audio_file`], decoding the track with id `track` instead of
+/// the first audio track if given, and resampling at `quality`.
+pub fn load_audio_track(path: &Path, track: Option<u32>, quality: ResampleQuality) -> Result<Vec<f32>> {
+    if is_stdin(path) {
+        println!("Loading audio from stdin");
+    } else {
+        println!("Loading audio file: {}", path.display());
+    }
+
+    let mut decoder = AudioDecoder::open_track(path, track)?;
+    let sample_rate = decoder.sample_rate();
+    println!("Sample rate: {} Hz", sample_rate);
+
+    // Decode all samples
+    let mut samples = Vec::new();
+    let mut frame_count = 0;
+
+    while let Some(block) = decoder.next_block()? {
+        // Streams such as broadcast recordings can change their channel
+        // layout between packets
+        samples.extend(mix_to_mono(&block, decoder.channels() as usize));
+
+        frame_count += 1;
+        if frame_count % 100 == 0 {
+            print!("\rDecoded {} frames...", frame_count);
+            std::io::stdout().flush().unwrap();
+        }
+    }
+
+    println!("\rDecoded {} frames, {} samples", frame_count, samples.len());
+
+    // Resample to 16kHz if needed (Whisper expects 16kHz)
+    if sample_rate != WHISPER_SAMPLE_RATE {
+        println!("Resampling from {} Hz to {} Hz...", sample_rate, WHISPER_SAMPLE_RATE);
+        samples = resample_with_quality(&samples, sample_rate, WHISPER_SAMPLE_RATE, quality);
+    }
+
+    Ok(samples)
+}
+
+/// Decodes the first audio track of a file packet by packet, for reading
+/// audio incrementally instead of all at once.
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    buffer: Option<SampleBuffer<f32>>,
+    // A block decoded early to learn the channel count
+    pending: Option<Vec<f32>>,
+}
+
+impl AudioDecoder {
+    /// Open `path` in any format symphonia can decode. A `path` of `-`
+    /// reads standard input.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::open_track(path, None)
+    }
+
+    /// Like [`open`](Self::open), decoding the track with id `track`
+    /// instead of the first audio track if given.
+    pub fn open_track(path: &Path, track: Option<u32>) -> Result<Self> {
+        if is_stdin(path) {
+            let mss = MediaSourceStream::new(Box::new(ReadOnlySource::new(io::stdin())), Default::default());
+            return Self::from_stream(mss, &Hint::new(), track);
+        }
+
+        // Open the media source
+        let src = File::open(path)
+            .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+        let mss = MediaSourceStream::new(Box::new(src), Default::default());
+
+        // Create a probe hint using the file extension
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension() {
+            if let Some(ext_str) = extension.to_str() {
+                hint.with_extension(ext_str);
+            }
+        }
+
+        Self::from_stream(mss, &hint, track)
+    }
+
+    /// Decode a container read from a stream that can't seek, such as a
+    /// pipe. The format is identified from the data alone.
+    pub fn from_reader(reader: impl Read + Send + Sync + 'static) -> Result<Self> {
+        let mss = MediaSourceStream::new(Box::new(ReadOnlySource::new(reader)), Default::default());
+        Self::from_stream(mss, &Hint::new(), None)
+    }
+
+    fn from_stream(mss: MediaSourceStream, hint: &Hint, track: Option<u32>) -> Result<Self> {
+        // Use the default probe to identify the format
+        let meta_opts: MetadataOptions = Default::default();
+        let fmt_opts: FormatOptions = Default::default();
+
+        let probed = symphonia::default::get_probe()
+            .format(hint, mss, &fmt_opts, &meta_opts)
+            .with_context(|| "Failed to probe audio format")?;
+
+        let format = probed.format;
+        let track = match track {
+            Some(id) => format.tracks().iter().find(|t| t.id == id).with_context(|| {
+                let ids: Vec<String> = format.tracks().iter().map(describe_track).collect();
+                format!("No track with id {}. Tracks: {}", id, ids.join(", "))
+            })?,
+            None => format
+                .tracks()
+                .iter()
+                .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+                .with_context(|| "No supported audio tracks found")?,
+        };
+
+        let track_id = track.id;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .with_context(|| "Failed to create decoder")?;
+
+        let sample_rate = track.codec_params.sample_rate
+            .with_context(|| "Sample rate not specified")?;
+        let channels = track.codec_params.channels.map(|c| c.count() as u16);
+        // Lossy codecs decode to float
+        let bits_per_sample = track.codec_params.bits_per_sample.map_or(32, |bits| bits as u16);
+
+        let mut audio = Self {
+            format,
+            decoder,
+            track_id,
+            sample_rate,
+            channels: channels.unwrap_or(1),
+            bits_per_sample,
+            buffer: None,
+            pending: None,
+        };
+        // Some containers only reveal the channel count once decoding starts
+        if channels.is_none() {
+            audio.pending = audio.decode_next()?;
+        }
+        Ok(audio)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Channel count of the block last returned by
+    /// [`next_block`](Self::next_block), or of the first one before that.
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Bits per sample of the encoded audio, 32 for codecs that decode to
+    /// float.
+    pub fn bits_per_sample(&self) -> u16 {
+        self.bits_per_sample
+    }
+
+    /// The next decoded packet as interleaved samples, or `None` at the end
+    /// of the stream.
+    pub fn next_block(&mut self) -> Result<Option<Vec<f32>>> {
+        match self.pending.take() {
+            Some(block) => Ok(Some(block)),
+            None => self.decode_next(),
+        }
+    }
+
+    fn decode_next(&mut self) -> Result<Option<Vec<f32>>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(None)
+                }
+                Err(SymphoniaError::ResetRequired) => return Ok(None),
+                Err(e) => return Err(e).context("Failed to read audio packet"),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::DecodeError(e)) => {
+                    // A corrupt packet only loses its own audio
+                    eprintln!("\nDecode error: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e).context("Failed to decode audio"),
+            };
+
+            // Convert decoded buffer to interleaved f32
+            let spec = *decoded.spec();
+            self.channels = spec.channels.count() as u16;
+            let duration = decoded.capacity() as u64;
+            let buffer = match self.buffer {
+                Some(ref mut buffer) if buffer.capacity() >= duration as usize * spec.channels.count() => buffer,
+                _ => self.buffer.insert(SampleBuffer::new(duration, spec)),
+            };
+            buffer.copy_interleaved_ref(decoded);
+            return Ok(Some(buffer.samples().to_vec()));
+        }
+    }
+}
+
+// e.g. `2 (aac, 2 ch, 48000 Hz)`
+fn describe_track(track: &Track) -> String {
+    let params = &track.codec_params;
+    let codec = symphonia::default::get_codecs()
+        .get_codec(params.codec)
+        .map_or("unknown codec", |codec| codec.short_name);
+    let mut details = vec![codec.to_string()];
+    if let Some(channels) = params.channels {
+        details.push(format!("{} ch", channels.count()));
+    }
+    if let Some(rate) = params.sample_rate {
+        details.push(format!("{} Hz", rate));
+    }
+    format!("{} ({})", track.id, details.join(", "))
+}
+
+/// Average interleaved samples with `channels` channels down to mono.
+pub fn mix_to_mono(samples: &[f32], channels: usize) -> Vec<f32> {
+    if channels <= 1 {
+        return samples.to_vec();
+    }
+    samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Resample mono samples from `from_rate` to `to_rate` with a band-limited
+/// [`Resampler`] of the default quality.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    resample_with_quality(samples, from_rate, to_rate, ResampleQuality::default())
+}
+
+/// Resample mono samples from `from_rate` to `to_rate` at the given quality.
+pub fn resample_with_quality(
+    samples: &[f32],
+    from_rate: u32,
+    to_rate: u32,
+    quality: ResampleQuality,
+) -> Vec<f32> {
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+
+    let mut resampler = Resampler::new(from_rate, to_rate, quality);
+    let mut resampled = Vec::with_capacity((samples.len() as u64 * to_rate as u64 / from_rate as u64) as usize + 1);
+    resampler.process(samples, &mut resampled);
+    resampler.finish(&mut resampled);
+    resampled
+}
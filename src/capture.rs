@@ -0,0 +1,243 @@
+//! Moving captured audio from the device callback to the transcriber.
+//!
+//! The audio callback writes interleaved samples into a lock-free
+//! [`ring_buffer`] and never blocks or allocates. A consumer thread drains
+//! it into a [`Timeline`]: the capture mixed to mono and converted to
+//! 16 kHz by a band-limited [`Resampler`], keeping only the most recent
+//! stretch so memory stays bounded however long the session runs.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::audio::WHISPER_SAMPLE_RATE;
+use crate::resample::{ResampleQuality, Resampler};
+
+struct Shared {
+    // f32 samples stored as bits, so slots can be atomics and no unsafe
+    // code is needed
+    slots: Box<[AtomicU32]>,
+    // Total samples ever written and read; slot = count % capacity
+    written: AtomicUsize,
+    read: AtomicUsize,
+    // Samples the producer had to discard because the buffer was full
+    dropped: AtomicUsize,
+    // Samples per interleaved frame, so only whole frames are stored
+    channels: usize,
+}
+
+/// Writing half of a [`ring_buffer`], for the audio callback.
+pub struct Producer {
+    shared: Arc<Shared>,
+}
+
+/// Reading half of a [`ring_buffer`].
+pub struct Consumer {
+    shared: Arc<Shared>,
+}
+
+/// Create a single-producer, single-consumer ring buffer holding up to
+/// `capacity` samples (rounded up to a power of two) of audio interleaved
+/// with `channels` channels.
+pub fn ring_buffer(capacity: usize, channels: usize) -> (Producer, Consumer) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        dropped: AtomicUsize::new(0),
+        channels: channels.max(1),
+    });
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+impl Producer {
+    /// Append as many of `samples`, whole interleaved frames, as fit,
+    /// returning how many did. The rest are counted as dropped.
+    pub fn push(&mut self, samples: &[f32]) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        let written = shared.written.load(Ordering::Relaxed);
+        let read = shared.read.load(Ordering::Acquire);
+        let free = capacity - written.wrapping_sub(read);
+
+        // A frame that doesn't fit is dropped whole, so the channels of
+        // every later frame stay in place
+        let count = if samples.len() <= free {
+            samples.len()
+        } else {
+            free - free % shared.channels
+        };
+        for (i, &sample) in samples[..count].iter().enumerate() {
+            let slot = written.wrapping_add(i) & (capacity - 1);
+            shared.slots[slot].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        shared.written.store(written.wrapping_add(count), Ordering::Release);
+
+        if count < samples.len() {
+            shared.dropped.fetch_add(samples.len() - count, Ordering::Relaxed);
+        }
+        count
+    }
+}
+
+impl Consumer {
+    /// Move every available sample to the end of `out`, returning how many.
+    pub fn pop_into(&mut self, out: &mut Vec<f32>) -> usize {
+        let shared = &*self.shared;
+        let capacity = shared.slots.len();
+        let read = shared.read.load(Ordering::Relaxed);
+        let written = shared.written.load(Ordering::Acquire);
+
+        let count = written.wrapping_sub(read);
+        out.reserve(count);
+        for i in 0..count {
+            let slot = read.wrapping_add(i) & (capacity - 1);
+            out.push(f32::from_bits(shared.slots[slot].load(Ordering::Relaxed)));
+        }
+        shared.read.store(read.wrapping_add(count), Ordering::Release);
+        count
+    }
+
+    /// Number of samples dropped since the last call, because the consumer
+    /// fell behind.
+    pub fn take_dropped(&mut self) -> usize {
+        self.shared.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// The capture as 16 kHz mono, with two cursors: how many native-rate
+/// frames have been consumed and how many 16 kHz samples produced. Only the
+/// last `max_seconds` are kept; positions are absolute from the start of the
+/// capture either way.
+///
+/// The 16 kHz end trails the native frames by the resampler's
+/// [`latency`](Resampler::latency), a couple of milliseconds, until
+/// [`finish`](Self::finish) is called.
+pub struct Timeline {
+    channels: usize,
+    // 16 kHz samples; `samples[0]` is sample `start` of the capture
+    samples: VecDeque<f32>,
+    start: u64,
+    max_len: usize,
+    // Native-rate cursor: frames consumed so far
+    native_frames: u64,
+    resampler: Resampler,
+    // Mono native frames waiting to be resampled
+    mono: Vec<f32>,
+    resampled: Vec<f32>,
+    // Interleaved samples of a frame split across pushes
+    partial_frame: Vec<f32>,
+}
+
+impl Timeline {
+    pub fn new(native_rate: u32, channels: u16, max_seconds: u32) -> Self {
+        Self::with_quality(native_rate, channels, max_seconds, ResampleQuality::default())
+    }
+
+    /// Like [`new`](Self::new), resampling at the given quality.
+    pub fn with_quality(native_rate: u32, channels: u16, max_seconds: u32, quality: ResampleQuality) -> Self {
+        let max_len = max_seconds as usize * WHISPER_SAMPLE_RATE as usize;
+        Self {
+            channels: channels.max(1) as usize,
+            samples: VecDeque::with_capacity(max_len.min(WHISPER_SAMPLE_RATE as usize * 60)),
+            start: 0,
+            max_len,
+            native_frames: 0,
+            resampler: Resampler::new(native_rate, WHISPER_SAMPLE_RATE, quality),
+            mono: Vec::new(),
+            resampled: Vec::new(),
+            partial_frame: Vec::new(),
+        }
+    }
+
+    /// Mix interleaved native-rate samples down to mono and append them,
+    /// converted to 16 kHz.
+    pub fn push_interleaved(&mut self, samples: &[f32]) {
+        let mut samples = samples;
+        // Complete a frame left over from the last push
+        if !self.partial_frame.is_empty() {
+            let needed = (self.channels - self.partial_frame.len()).min(samples.len());
+            self.partial_frame.extend_from_slice(&samples[..needed]);
+            samples = &samples[needed..];
+            if self.partial_frame.len() < self.channels {
+                return;
+            }
+            let frame = std::mem::take(&mut self.partial_frame);
+            self.push_frame(&frame);
+        }
+
+        let whole = samples.len() - samples.len() % self.channels;
+        for frame in samples[..whole].chunks(self.channels) {
+            self.push_frame(frame);
+        }
+        self.partial_frame.extend_from_slice(&samples[whole..]);
+        self.resample_pending();
+    }
+
+    /// Append `frames` native-rate frames of silence, e.g. in place of
+    /// samples that were dropped, so later audio keeps its timestamps.
+    pub fn push_silence(&mut self, frames: usize) {
+        let frame = vec![0.0; self.channels];
+        for _ in 0..frames {
+            self.push_frame(&frame);
+        }
+        self.resample_pending();
+    }
+
+    /// Native-rate frames consumed so far.
+    pub fn native_frames(&self) -> u64 {
+        self.native_frames
+    }
+
+    /// 16 kHz samples produced so far, i.e. the end of the timeline.
+    pub fn end(&self) -> u64 {
+        self.start + self.samples.len() as u64
+    }
+
+    /// The earliest 16 kHz sample still held.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Length of the capture in milliseconds.
+    pub fn duration_ms(&self) -> i64 {
+        (self.end() * 1000 / WHISPER_SAMPLE_RATE as u64) as i64
+    }
+
+    /// The samples from absolute position `from` to the end, and the
+    /// position they actually start at: `from`, or the start of the
+    /// retained history if `from` has already been discarded.
+    pub fn samples_from(&self, from: u64) -> (u64, Vec<f32>) {
+        let from = from.clamp(self.start, self.end());
+        let offset = (from - self.start) as usize;
+        (from, self.samples.range(offset..).copied().collect())
+    }
+
+    /// The capture has ended: resample the last native frames, which were
+    /// waiting for audio after them. Nothing more may be pushed.
+    pub fn finish(&mut self) {
+        self.resample_pending();
+        self.resampler.finish(&mut self.resampled);
+        self.append_resampled();
+    }
+
+    fn push_frame(&mut self, frame: &[f32]) {
+        let sum: f32 = frame.iter().sum();
+        self.mono.push(sum / frame.len() as f32);
+        self.native_frames += 1;
+    }
+
+    fn resample_pending(&mut self) {
+        self.resampler.process(&self.mono, &mut self.resampled);
+        self.mono.clear();
+        self.append_resampled();
+    }
+
+    fn append_resampled(&mut self) {
+        self.samples.extend(self.resampled.drain(..));
+        let excess = self.samples.len().saturating_sub(self.max_len);
+        self.samples.drain(..excess);
+        self.start += excess as u64;
+    }
+}
@@ -0,0 +1,256 @@
+//! Clustering of speaker embeddings into speakers.
+
+use nalgebra::{DMatrix, SymmetricEigen};
+
+/// How speaker embeddings are grouped into speakers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ClusteringMethod {
+    /// Average-linkage agglomerative clustering, merging until the closest
+    /// clusters are further apart than the threshold
+    #[default]
+    Agglomerative,
+    /// Spectral clustering, choosing the number of speakers from the
+    /// largest eigengap
+    Spectral,
+}
+
+/// Upper bound on speakers when spectral clustering picks the count itself.
+const DEFAULT_MAX_SPEAKERS: usize = 20;
+
+/// Group `embeddings` into speakers. Returns one label per embedding,
+/// numbered in order of first appearance.
+///
+/// `min_speakers` and `max_speakers` bound the number of clusters; pass the
+/// same value for both to force an exact count. `threshold` is the cosine
+/// distance above which agglomerative clustering stops merging.
+pub fn cluster_embeddings(
+    embeddings: &[Vec<f32>],
+    method: ClusteringMethod,
+    threshold: f32,
+    min_speakers: Option<usize>,
+    max_speakers: Option<usize>,
+) -> Vec<usize> {
+    let n = embeddings.len();
+    if n == 0 {
+        return Vec::new();
+    }
+
+    let default_max = match method {
+        ClusteringMethod::Agglomerative => n,
+        ClusteringMethod::Spectral => DEFAULT_MAX_SPEAKERS,
+    };
+    let max = max_speakers.unwrap_or(default_max).clamp(1, n);
+    let min = min_speakers.unwrap_or(1).clamp(1, max);
+
+    // Too few embeddings for a meaningful eigengap
+    let labels = if method == ClusteringMethod::Agglomerative || n <= 2 {
+        agglomerative(embeddings, threshold as f64, min, max)
+    } else {
+        spectral(embeddings, min, max)
+    };
+
+    relabel_by_first_appearance(&labels)
+}
+
+/// Cosine similarity between two vectors, 0 if either is all zeros.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let mut dot = 0.0f64;
+    let mut norm_a = 0.0f64;
+    let mut norm_b = 0.0f64;
+    for (&x, &y) in a.iter().zip(b) {
+        dot += x as f64 * y as f64;
+        norm_a += x as f64 * x as f64;
+        norm_b += y as f64 * y as f64;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    (dot / (norm_a.sqrt() * norm_b.sqrt())) as f32
+}
+
+/// Average-linkage agglomerative clustering on cosine distance. Merging
+/// continues past `threshold` while there are more than `max` clusters and
+/// stops at `min` clusters.
+pub fn agglomerative(embeddings: &[Vec<f32>], threshold: f64, min: usize, max: usize) -> Vec<usize> {
+    let n = embeddings.len();
+    let mut distances = vec![vec![0.0f64; n]; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let d = 1.0 - cosine_similarity(&embeddings[i], &embeddings[j]) as f64;
+            distances[i][j] = d;
+            distances[j][i] = d;
+        }
+    }
+
+    let mut sizes = vec![1usize; n];
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut labels: Vec<usize> = (0..n).collect();
+
+    while active.len() > min {
+        // Find the closest pair of active clusters
+        let mut best = (0, 0, f64::INFINITY);
+        for (ai, &i) in active.iter().enumerate() {
+            for &j in &active[ai + 1..] {
+                if distances[i][j] < best.2 {
+                    best = (i, j, distances[i][j]);
+                }
+            }
+        }
+        let (i, j, distance) = best;
+        if distance > threshold && active.len() <= max {
+            break;
+        }
+
+        // Merge j into i, updating average-linkage distances (Lance-Williams)
+        let (size_i, size_j) = (sizes[i] as f64, sizes[j] as f64);
+        for &k in &active {
+            if k != i && k != j {
+                let d = (size_i * distances[i][k] + size_j * distances[j][k]) / (size_i + size_j);
+                distances[i][k] = d;
+                distances[k][i] = d;
+            }
+        }
+        sizes[i] += sizes[j];
+        active.retain(|&k| k != j);
+        for label in labels.iter_mut() {
+            if *label == j {
+                *label = i;
+            }
+        }
+    }
+
+    labels
+}
+
+/// Spectral clustering on the cosine affinity matrix. The number of
+/// clusters is the one in `min..=max` with the largest eigengap of the
+/// normalized Laplacian.
+pub fn spectral(embeddings: &[Vec<f32>], min: usize, max: usize) -> Vec<usize> {
+    let n = embeddings.len();
+    if n <= 2 || max <= 1 {
+        return vec![0; n];
+    }
+
+    // Affinity matrix with negative similarities clipped
+    let mut affinity = DMatrix::<f64>::zeros(n, n);
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let a = cosine_similarity(&embeddings[i], &embeddings[j]).max(0.0) as f64;
+            affinity[(i, j)] = a;
+            affinity[(j, i)] = a;
+        }
+    }
+
+    // Symmetric normalized Laplacian: I - D^-1/2 A D^-1/2
+    let inv_sqrt_degree: Vec<f64> = (0..n)
+        .map(|i| {
+            let degree: f64 = affinity.row(i).sum();
+            if degree > 0.0 { 1.0 / degree.sqrt() } else { 0.0 }
+        })
+        .collect();
+    let mut laplacian = DMatrix::<f64>::identity(n, n);
+    for i in 0..n {
+        for j in 0..n {
+            laplacian[(i, j)] -= inv_sqrt_degree[i] * affinity[(i, j)] * inv_sqrt_degree[j];
+        }
+    }
+
+    let eigen = SymmetricEigen::new(laplacian);
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| eigen.eigenvalues[a].total_cmp(&eigen.eigenvalues[b]));
+
+    // k clusters are separated by the gap between eigenvalues k-1 and k
+    let max = max.min(n - 1);
+    let min = min.min(max);
+    let k = if min == max {
+        min
+    } else {
+        (min..=max)
+            .max_by(|&a, &b| {
+                let gap_a = eigen.eigenvalues[order[a]] - eigen.eigenvalues[order[a - 1]];
+                let gap_b = eigen.eigenvalues[order[b]] - eigen.eigenvalues[order[b - 1]];
+                gap_a.total_cmp(&gap_b)
+            })
+            .unwrap_or(1)
+    };
+    if k <= 1 {
+        return vec![0; n];
+    }
+
+    // Embed each point as its row of the first k eigenvectors, normalized
+    let points: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            let row: Vec<f64> = order[..k].iter().map(|&c| eigen.eigenvectors[(i, c)]).collect();
+            let norm = row.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 0.0 { row.iter().map(|x| x / norm).collect() } else { row }
+        })
+        .collect();
+
+    kmeans(&points, k)
+}
+
+/// Lloyd's k-means with deterministic farthest-point initialisation.
+fn kmeans(points: &[Vec<f64>], k: usize) -> Vec<usize> {
+    let squared_distance =
+        |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f64>();
+
+    let mut centroids = vec![points[0].clone()];
+    while centroids.len() < k {
+        let farthest = points
+            .iter()
+            .max_by(|a, b| {
+                let da = centroids.iter().map(|c| squared_distance(a, c)).fold(f64::INFINITY, f64::min);
+                let db = centroids.iter().map(|c| squared_distance(b, c)).fold(f64::INFINITY, f64::min);
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        centroids.push(farthest.clone());
+    }
+
+    let mut labels = vec![0; points.len()];
+    for _ in 0..100 {
+        let mut changed = false;
+        for (label, point) in labels.iter_mut().zip(points) {
+            let nearest = (0..k)
+                .min_by(|&a, &b| {
+                    squared_distance(point, &centroids[a]).total_cmp(&squared_distance(point, &centroids[b]))
+                })
+                .unwrap();
+            if *label != nearest {
+                *label = nearest;
+                changed = true;
+            }
+        }
+
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&Vec<f64>> = points.iter().zip(&labels).filter(|(_, &l)| l == c).map(|(p, _)| p).collect();
+            if members.is_empty() {
+                continue;
+            }
+            for (d, value) in centroid.iter_mut().enumerate() {
+                *value = members.iter().map(|p| p[d]).sum::<f64>() / members.len() as f64;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    labels
+}
+
+fn relabel_by_first_appearance(labels: &[usize]) -> Vec<usize> {
+    let mut mapping: Vec<(usize, usize)> = Vec::new();
+    labels
+        .iter()
+        .map(|&label| match mapping.iter().find(|(from, _)| *from == label) {
+            Some(&(_, to)) => to,
+            None => {
+                let to = mapping.len();
+                mapping.push((label, to));
+                to
+            }
+        })
+        .collect()
+}
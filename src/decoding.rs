@@ -0,0 +1,132 @@
+//! Whisper decoding settings.
+//!
+//! [`DecodingOptions`] builds the whisper.cpp parameters for every decode,
+//! so whole files, live windows, the final pass over a live recording and
+//! segments decoded again by the hallucination filter all use the same
+//! settings.
+
+use whisper_rs::{FullParams, SamplingStrategy};
+
+/// How Whisper searches for the text.
+#[derive(Debug, Clone)]
+pub struct DecodingOptions {
+    /// Keep this many candidate texts with beam search instead of decoding
+    /// greedily.
+    pub beam_size: Option<usize>,
+    /// Candidates sampled per window when decoding greedily at a
+    /// temperature above 0.
+    pub best_of: usize,
+    /// Sampling temperature of the first attempt at each window; 0 always
+    /// picks the most likely token.
+    pub temperature: f32,
+    /// Whisper decodes a window again this much hotter when the result
+    /// fails the thresholds below, up to 1.0. 0 disables this fallback.
+    pub temperature_increment: f32,
+    /// Threads to decode with (default: up to 4).
+    pub threads: Option<usize>,
+    /// Tokens of previously decoded text Whisper is conditioned on (0 for
+    /// none, default: as much as fits).
+    pub max_text_context: Option<usize>,
+    /// Token entropy above which a window is decoded again, which catches
+    /// repetitive output.
+    pub entropy_threshold: f32,
+    /// Mean token log-probability below which a window is decoded again.
+    pub logprob_threshold: f32,
+    /// No-speech probability above which a window Whisper was unsure of is
+    /// treated as silence.
+    pub no_speech_threshold: f32,
+    /// Text to prompt Whisper with before the audio, e.g. names and terms
+    /// that come up.
+    pub initial_prompt: Option<String>,
+}
+
+impl Default for DecodingOptions {
+    // whisper.cpp's own defaults, with a single greedy candidate
+    fn default() -> Self {
+        Self {
+            beam_size: None,
+            best_of: 1,
+            temperature: 0.0,
+            temperature_increment: 0.2,
+            threads: None,
+            max_text_context: None,
+            entropy_threshold: 2.4,
+            logprob_threshold: -1.0,
+            no_speech_threshold: 0.6,
+            initial_prompt: None,
+        }
+    }
+}
+
+impl DecodingOptions {
+    /// Build the parameters for one decode in `language` (`None`
+    /// auto-detects). `prompt`, e.g. the text transcribed just before the
+    /// audio, goes before the initial prompt: Whisper drops the start of a
+    /// prompt that is too long, so the fixed part is kept.
+    pub fn full_params<'a>(
+        &self,
+        language: Option<&'a str>,
+        prompt: Option<&str>,
+        token_timestamps: bool,
+        print_progress: bool,
+    ) -> FullParams<'a, 'a> {
+        let strategy = match self.beam_size {
+            Some(beam_size) => SamplingStrategy::BeamSearch {
+                beam_size: beam_size.max(1) as i32,
+                // Not used by whisper.cpp
+                patience: -1.0,
+            },
+            None => SamplingStrategy::Greedy {
+                best_of: self.best_of.max(1) as i32,
+            },
+        };
+        let mut params = FullParams::new(strategy);
+
+        // Set language if provided, otherwise auto-detect
+        params.set_language(language);
+
+        params.set_translate(false);
+        params.set_print_progress(print_progress);
+        params.set_print_special(false);
+        params.set_print_realtime(false);
+        params.set_suppress_blank(true);
+        params.set_suppress_non_speech_tokens(false);
+        params.set_single_segment(false);
+        params.set_token_timestamps(token_timestamps);
+
+        params.set_temperature(self.temperature);
+        params.set_temperature_inc(self.temperature_increment);
+        params.set_entropy_thold(self.entropy_threshold);
+        params.set_logprob_thold(self.logprob_threshold);
+        params.set_no_speech_thold(self.no_speech_threshold);
+        params.set_n_threads(self.thread_count() as i32);
+        if let Some(max_text_context) = self.max_text_context {
+            params.set_n_max_text_ctx(max_text_context as i32);
+        }
+
+        let prompt = self.prompt(prompt);
+        if !prompt.is_empty() {
+            params.set_initial_prompt(&prompt);
+        }
+        params
+    }
+
+    /// The prompt for one decode: `prompt` followed by the initial prompt.
+    pub fn prompt(&self, prompt: Option<&str>) -> String {
+        [prompt, self.initial_prompt.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+            .replace('\0', "")
+    }
+
+    /// Threads to decode with: `threads`, or like whisper.cpp as many as
+    /// there are cores, up to 4.
+    pub fn thread_count(&self) -> usize {
+        self.threads
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |cores| cores.get()).min(4))
+            .max(1)
+    }
+}
@@ -0,0 +1,276 @@
+//! Finding, listing and opening audio input devices.
+
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, SupportedStreamConfig, SupportedStreamConfigRange};
+
+use crate::audio::WHISPER_SAMPLE_RATE;
+
+/// Capture sample formats, most preferred first.
+const SUPPORTED_FORMATS: [SampleFormat; 6] = [
+    SampleFormat::F32,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::F64,
+    SampleFormat::I8,
+    SampleFormat::U16,
+];
+
+/// Frames converted to f32 at a time in the audio callback, whose buffer is
+/// allocated up front so the callback never allocates.
+const CONVERSION_FRAMES: usize = 1024;
+
+/// Print every audio host with its input devices and their supported
+/// configurations. Device indices match those accepted by
+/// [`find_input_device`].
+pub fn print_devices() -> Result<()> {
+    let default_host = cpal::default_host().id();
+
+    for host_id in cpal::available_hosts() {
+        let marker = if host_id == default_host { " (default)" } else { "" };
+        println!("Host: {}{}", host_id.name(), marker);
+
+        let host = match cpal::host_from_id(host_id) {
+            Ok(host) => host,
+            Err(e) => {
+                println!("  Unavailable: {}", e);
+                continue;
+            }
+        };
+        let default_device = host.default_input_device().and_then(|d| d.name().ok());
+
+        let devices = match host.input_devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                println!("  Failed to list devices: {}", e);
+                continue;
+            }
+        };
+        let mut found = false;
+        for (index, device) in devices.enumerate() {
+            found = true;
+            let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+            let marker = if Some(&name) == default_device.as_ref() { " (default)" } else { "" };
+            println!("  [{}] {}{}", index, name, marker);
+
+            match device.supported_input_configs() {
+                Ok(configs) => {
+                    for config in configs {
+                        println!("      {}", describe_config_range(&config));
+                    }
+                }
+                Err(e) => println!("      Failed to query configs: {}", e),
+            }
+        }
+        if !found {
+            println!("  No input devices");
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// The audio host called `name` (case-insensitive), or the default host.
+pub fn find_host(name: Option<&str>) -> Result<cpal::Host> {
+    let Some(name) = name else {
+        return Ok(cpal::default_host());
+    };
+
+    let available = cpal::available_hosts();
+    let host_id = available
+        .iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .with_context(|| {
+            let names: Vec<&str> = available.iter().map(|id| id.name()).collect();
+            format!("Unknown audio host '{}'. Available hosts: {}", name, names.join(", "))
+        })?;
+
+    cpal::host_from_id(*host_id)
+        .with_context(|| format!("Audio host {} is unavailable", host_id.name()))
+}
+
+/// The input device matching `selector` on `host`: its index as listed by
+/// `devices`, its exact name, or a unique part of its name. Without a
+/// selector the host's default input device is used.
+pub fn find_input_device(host: &cpal::Host, selector: Option<&str>) -> Result<cpal::Device> {
+    let Some(selector) = selector else {
+        return host.default_input_device().context("No input device available");
+    };
+
+    let devices: Vec<(String, cpal::Device)> = host
+        .input_devices()
+        .context("Failed to list input devices")?
+        .map(|device| (device.name().unwrap_or_default(), device))
+        .collect();
+
+    if let Ok(index) = selector.parse::<usize>() {
+        if let Some((_, device)) = devices.into_iter().nth(index) {
+            return Ok(device);
+        }
+        anyhow::bail!("No input device with index {}. Run the `devices` command to list them.", index);
+    }
+
+    let needle = selector.to_lowercase();
+    let mut matches: Vec<usize> = devices
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, _))| name.to_lowercase().contains(&needle))
+        .map(|(i, _)| i)
+        .collect();
+    // An exact name wins over partial matches
+    if let Some(&exact) = matches.iter().find(|&&i| devices[i].0 == selector) {
+        matches = vec![exact];
+    }
+
+    match matches.as_slice() {
+        [index] => Ok(devices.into_iter().nth(*index).unwrap().1),
+        [] => {
+            let names: Vec<&str> = devices.iter().map(|(name, _)| name.as_str()).collect();
+            anyhow::bail!(
+                "No input device matches '{}'. Available devices: {}",
+                selector,
+                names.join(", ")
+            )
+        }
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|&i| devices[i].0.as_str()).collect();
+            anyhow::bail!(
+                "'{}' matches several input devices: {}. Use a longer name or an index.",
+                selector,
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// Describe a supported config range, e.g. `2 ch, 44100-48000 Hz, f32`.
+pub fn describe_config_range(config: &cpal::SupportedStreamConfigRange) -> String {
+    let (min, max) = (config.min_sample_rate().0, config.max_sample_rate().0);
+    let rates = if min == max { format!("{} Hz", min) } else { format!("{}-{} Hz", min, max) };
+    format!("{} ch, {}, {}", config.channels(), rates, config.sample_format())
+}
+
+/// Pick the input config closest to what Whisper wants: 16 kHz (or the
+/// nearest rate above it, so nothing is lost to resampling), as few
+/// channels as possible, and a sample format we can convert.
+///
+/// Fails with a list of everything the device supports if none fit.
+pub fn negotiate_input_config(device: &cpal::Device) -> Result<SupportedStreamConfig> {
+    let ranges: Vec<SupportedStreamConfigRange> = device
+        .supported_input_configs()
+        .context("Failed to query the device's supported configs")?
+        .collect();
+
+    if let Some(config) = choose_config(&ranges) {
+        return Ok(config);
+    }
+
+    let supported: Vec<String> = ranges.iter().map(|r| format!("  {}", describe_config_range(r))).collect();
+    let formats: Vec<String> = SUPPORTED_FORMATS.iter().map(|f| f.to_string()).collect();
+    anyhow::bail!(
+        "No usable input config on this device. It supports:\n{}\n\
+         Sample formats that can be recorded: {}",
+        if supported.is_empty() { "  (nothing)".to_string() } else { supported.join("\n") },
+        formats.join(", ")
+    )
+}
+
+/// The best of `ranges`, see [`negotiate_input_config`].
+pub fn choose_config(ranges: &[SupportedStreamConfigRange]) -> Option<SupportedStreamConfig> {
+    ranges
+        .iter()
+        .filter_map(|range| {
+            let format_rank = SUPPORTED_FORMATS.iter().position(|&f| f == range.sample_format())?;
+            let (min, max) = (range.min_sample_rate().0, range.max_sample_rate().0);
+            let rate = WHISPER_SAMPLE_RATE.clamp(min, max);
+            // Rates below 16 kHz lose speech detail, so rank them after all others
+            let rate_penalty = if rate >= WHISPER_SAMPLE_RATE {
+                (rate - WHISPER_SAMPLE_RATE) as u64
+            } else {
+                u32::MAX as u64 + (WHISPER_SAMPLE_RATE - rate) as u64
+            };
+            let rank = (rate_penalty, range.channels(), format_rank);
+            Some((rank, (*range).with_sample_rate(cpal::SampleRate(rate))))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, config)| config)
+}
+
+/// Open an input stream for `config`, converting whatever sample format
+/// the device delivers to interleaved f32 before calling `on_samples`,
+/// possibly several times per device buffer, always with whole frames.
+pub fn build_input_stream<F>(
+    device: &cpal::Device,
+    config: &SupportedStreamConfig,
+    on_samples: F,
+) -> Result<cpal::Stream>
+where
+    F: FnMut(&[f32]) + Send + 'static,
+{
+    let stream_config = config.config();
+    match config.sample_format() {
+        SampleFormat::F32 => build_f32_stream(device, &stream_config, on_samples),
+        SampleFormat::I16 => build_converting_stream::<i16, F>(device, &stream_config, on_samples),
+        SampleFormat::I32 => build_converting_stream::<i32, F>(device, &stream_config, on_samples),
+        SampleFormat::F64 => build_converting_stream::<f64, F>(device, &stream_config, on_samples),
+        SampleFormat::I8 => build_converting_stream::<i8, F>(device, &stream_config, on_samples),
+        SampleFormat::U16 => build_converting_stream::<u16, F>(device, &stream_config, on_samples),
+        other => anyhow::bail!("Unsupported sample format: {}", other),
+    }
+}
+
+// The device's own buffer is passed on as it is
+fn build_f32_stream<F>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_samples: F,
+) -> Result<cpal::Stream>
+where
+    F: FnMut(&[f32]) + Send + 'static,
+{
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[f32], _: &cpal::InputCallbackInfo| on_samples(data),
+        move |err| eprintln!("Audio stream error: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
+
+fn build_converting_stream<T, F>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut on_samples: F,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    F: FnMut(&[f32]) + Send + 'static,
+{
+    let mut converted = vec![0.0f32; CONVERSION_FRAMES * config.channels.max(1) as usize];
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            for chunk in data.chunks(converted.len()) {
+                let converted = &mut converted[..chunk.len()];
+                convert_samples(chunk, converted);
+                on_samples(converted);
+            }
+        },
+        move |err| eprintln!("Audio stream error: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Convert `input` to f32 samples in `output`, which must be as long.
+pub fn convert_samples<T>(input: &[T], output: &mut [f32])
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    for (out, &sample) in output.iter_mut().zip(input) {
+        *out = sample.to_sample::<f32>();
+    }
+}
@@ -0,0 +1,517 @@
+use anyhow::Result;
+use pyannote_rs::{get_segments, EmbeddingExtractor, Segment as SpeechSegment};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::clustering::{cluster_embeddings, ClusteringMethod};
+use crate::profiles::{match_profiles, ProfileStore};
+use crate::transcript::{Segment, Token};
+
+/// Segments shorter than this (in seconds) are not embedded; they take the
+/// speaker of the nearest longer segment instead.
+const MIN_EMBEDDING_SECONDS: f64 = 0.3;
+
+/// Options for speaker diarization.
+#[derive(Debug, Clone)]
+pub struct DiarizationOptions {
+    pub clustering: ClusteringMethod,
+    /// Cosine distance above which agglomerative clustering stops merging.
+    pub threshold: f32,
+    /// Exact number of speakers, if known.
+    pub num_speakers: Option<usize>,
+    pub min_speakers: Option<usize>,
+    pub max_speakers: Option<usize>,
+    /// Segmentation model; searched for in `models/` if unset.
+    pub segmentation_model: Option<PathBuf>,
+    /// Speaker embedding model; searched for in `models/` if unset.
+    pub embedding_model: Option<PathBuf>,
+    /// Directory of enrolled voice profiles to name speakers from.
+    pub profiles_dir: Option<PathBuf>,
+    /// Minimum cosine similarity for a speaker to match a profile.
+    pub profile_threshold: f32,
+    /// Split segments where the speaker changes, using word timings.
+    pub split_on_speaker_change: bool,
+}
+
+impl Default for DiarizationOptions {
+    fn default() -> Self {
+        Self {
+            clustering: ClusteringMethod::default(),
+            threshold: 0.7,
+            num_speakers: None,
+            min_speakers: None,
+            max_speakers: None,
+            segmentation_model: None,
+            embedding_model: None,
+            profiles_dir: None,
+            profile_threshold: 0.5,
+            split_on_speaker_change: false,
+        }
+    }
+}
+
+impl DiarizationOptions {
+    /// Lower and upper bound on the number of speakers, with
+    /// `num_speakers` taking precedence over the separate bounds.
+    pub fn speaker_bounds(&self) -> (Option<usize>, Option<usize>) {
+        match self.num_speakers {
+            Some(n) => (Some(n), Some(n)),
+            None => (self.min_speakers, self.max_speakers),
+        }
+    }
+}
+
+/// A stretch of audio attributed to a single speaker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpeakerTurn {
+    /// Start of the turn in seconds.
+    pub start: f64,
+    /// End of the turn in seconds.
+    pub end: f64,
+    /// Zero-based speaker index.
+    pub speaker: usize,
+}
+
+/// Locate the pyannote segmentation model in the usual places.
+pub fn find_pyannote_model() -> Result<PathBuf> {
+    let models_dir = PathBuf::from("models");
+    
+    // Try different possible model file names/locations
+    let possible_paths = vec![
+        models_dir.join("segmentation.onnx"),
+        models_dir.join("segmentation-3.0.onnx"),
+        models_dir.join("pyannote-segmentation.onnx"),
+        PathBuf::from("segmentation.onnx"),
+    ];
+    
+    for path in &possible_paths {
+        if path.exists() {
+            println!("Found model file: {}", path.display());
+            return Ok(path.clone());
+        }
+    }
+    
+    // Model not found - provide helpful error message
+    anyhow::bail!(
+        "Speaker diarization model not found.\n\
+         \n\
+         To enable speaker diarization, you need to download the model:\n\
+         \n\
+         1. Visit: https://huggingface.co/pyannote/segmentation-3.0\n\
+         2. Accept the model terms (you may need a Hugging Face account)\n\
+         3. Download the ONNX model file\n\
+         4. Place it in the 'models' directory with one of these names:\n\
+            - segmentation.onnx\n\
+            - segmentation-3.0.onnx\n\
+            - pyannote-segmentation.onnx\n\
+         \n\
+         Note: The model file is typically ~50-100MB.\n\
+         \n\
+         Alternatively, you can run without speaker diarization by removing the --speaker-diarization flag."
+    )
+}
+
+/// Locate the speaker embedding model in the usual places.
+pub fn find_embedding_model() -> Result<PathBuf> {
+    let models_dir = PathBuf::from("models");
+
+    let possible_paths = vec![
+        models_dir.join("wespeaker_en_voxceleb_CAM++.onnx"),
+        models_dir.join("embedding.onnx"),
+        PathBuf::from("wespeaker_en_voxceleb_CAM++.onnx"),
+    ];
+
+    for path in &possible_paths {
+        if path.exists() {
+            println!("Found model file: {}", path.display());
+            return Ok(path.clone());
+        }
+    }
+
+    anyhow::bail!(
+        "Speaker embedding model not found.\n\
+         \n\
+         Speaker diarization needs a speaker embedding model to tell voices apart:\n\
+         \n\
+         1. Download wespeaker_en_voxceleb_CAM++.onnx from:\n\
+            https://github.com/thewh1teagle/pyannote-rs/releases/tag/v0.1.0\n\
+         2. Place it in the 'models' directory with one of these names:\n\
+            - wespeaker_en_voxceleb_CAM++.onnx\n\
+            - embedding.onnx\n\
+         \n\
+         Alternatively, pass its location with --embedding-model."
+    )
+}
+
+/// Speaker turns found by diarization, plus display names for speakers
+/// matched against enrolled voice profiles.
+#[derive(Debug, Clone, Default)]
+pub struct Diarization {
+    pub turns: Vec<SpeakerTurn>,
+    /// Name of each speaker index, empty if no profiles were enrolled.
+    pub speaker_names: Vec<String>,
+}
+
+/// Split the audio into speech turns and assign a speaker to each by
+/// clustering voice embeddings. Speakers are named after matching enrolled
+/// voice profiles when `options.profiles_dir` has any.
+pub fn perform_speaker_diarization(
+    audio_samples: &[f32],
+    sample_rate: u32,
+    options: &DiarizationOptions,
+) -> Result<Diarization> {
+    println!("Performing speaker diarization...");
+    println!("Audio length: {:.2} seconds, {} samples at {} Hz", 
+             audio_samples.len() as f64 / sample_rate as f64,
+             audio_samples.len(),
+             sample_rate);
+
+    let segments = detect_speech(audio_samples, sample_rate, options)?;
+    
+    println!("Successfully extracted {} speech segments", segments.len());
+    
+    // Embed each speech segment so the same voice can be recognised
+    // across the whole recording
+    let mut extractor = load_embedding_extractor(options)?;
+    let embeddings = embed_segments(&mut extractor, &segments);
+
+    let embedded: Vec<Vec<f32>> = embeddings.iter().flatten().cloned().collect();
+    if embedded.is_empty() {
+        anyhow::bail!("No speech segments were long enough to identify speakers");
+    }
+
+    let (min_speakers, max_speakers) = options.speaker_bounds();
+    let labels = cluster_embeddings(
+        &embedded,
+        options.clustering,
+        options.threshold,
+        min_speakers,
+        max_speakers,
+    );
+    let speaker_names = name_speakers(&embedded, &labels, options)?;
+
+    let mut labels = labels.into_iter();
+    let mut speaker_turns: Vec<Option<SpeakerTurn>> = segments
+        .iter()
+        .zip(&embeddings)
+        .map(|(segment, embedding)| {
+            embedding.as_ref().map(|_| SpeakerTurn {
+                start: segment.start,
+                end: segment.end,
+                speaker: labels.next().unwrap(),
+            })
+        })
+        .collect();
+
+    // Segments without an embedding take the speaker of the nearest turn
+    let labelled: Vec<SpeakerTurn> = speaker_turns.iter().flatten().copied().collect();
+    for (turn, segment) in speaker_turns.iter_mut().zip(&segments) {
+        if turn.is_none() {
+            *turn = Some(SpeakerTurn {
+                start: segment.start,
+                end: segment.end,
+                speaker: nearest_speaker(&labelled, segment.start, segment.end).unwrap(),
+            });
+        }
+    }
+    let speaker_turns: Vec<SpeakerTurn> = speaker_turns.into_iter().flatten().collect();
+
+    let unique_speakers = speaker_turns.iter().map(|t| t.speaker).max().unwrap_or(0) + 1;
+    println!("Found {} speech segments, identified {} speakers",
+             speaker_turns.len(), unique_speakers);
+
+    Ok(Diarization {
+        turns: speaker_turns,
+        speaker_names,
+    })
+}
+
+/// Compute a single voice embedding for a reference clip of one person, as
+/// used for enrollment. Embeddings of all speech in the clip are averaged.
+pub fn extract_voice_embedding(
+    audio_samples: &[f32],
+    sample_rate: u32,
+    options: &DiarizationOptions,
+) -> Result<Vec<f32>> {
+    let segments = detect_speech(audio_samples, sample_rate, options)?;
+    let mut extractor = load_embedding_extractor(options)?;
+    let embeddings: Vec<Vec<f32>> = embed_segments(&mut extractor, &segments)
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if embeddings.is_empty() {
+        anyhow::bail!("The clip has no speech long enough to build a voice profile");
+    }
+
+    Ok(mean_embedding(embeddings.iter()))
+}
+
+// Run pyannote segmentation to find the speech in the audio
+fn detect_speech(
+    audio_samples: &[f32],
+    sample_rate: u32,
+    options: &DiarizationOptions,
+) -> Result<Vec<SpeechSegment>> {
+    // Convert f32 samples to i16 for pyannote-rs
+    let samples_i16: Vec<i16> = audio_samples
+        .iter()
+        .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
+        .collect();
+    
+    println!("Converted to i16 format: {} samples", samples_i16.len());
+    
+    // Find the model file
+    let model_path = match options.segmentation_model {
+        Some(ref path) => path.clone(),
+        None => find_pyannote_model()?,
+    };
+    
+    println!("Using model: {}", model_path.display());
+    
+    // Get speech segments
+    let segments_iter = get_segments(&samples_i16, sample_rate, &model_path)
+        .map_err(|e| anyhow::anyhow!("Failed to get segments: {}", e))?;
+    
+    let mut segments: Vec<SpeechSegment> = Vec::new();
+    let mut segment_count = 0;
+    for segment_result in segments_iter {
+        match segment_result {
+            Ok(segment) => {
+                segments.push(segment);
+                segment_count += 1;
+                if segment_count % 10 == 0 {
+                    print!("\r  Processed {} segments...", segment_count);
+                    std::io::stdout().flush().unwrap();
+                }
+            }
+            Err(e) => {
+                eprintln!("\nSegment processing error: {}", e);
+                // Continue processing other segments
+            }
+        }
+    }
+    println!("\r  Processed {} segments total", segment_count);
+    
+    if segments.is_empty() {
+        anyhow::bail!("No speech segments found - the audio may not contain speech or the model failed to process it");
+    }
+
+    Ok(segments)
+}
+
+fn load_embedding_extractor(options: &DiarizationOptions) -> Result<EmbeddingExtractor> {
+    let embedding_model_path = match options.embedding_model {
+        Some(ref path) => path.clone(),
+        None => find_embedding_model()?,
+    };
+    println!("Using embedding model: {}", embedding_model_path.display());
+    EmbeddingExtractor::new(&embedding_model_path)
+        .map_err(|e| anyhow::anyhow!("Failed to load embedding model: {}", e))
+}
+
+// One embedding per segment, `None` where the segment couldn't be embedded
+fn embed_segments(extractor: &mut EmbeddingExtractor, segments: &[SpeechSegment]) -> Vec<Option<Vec<f32>>> {
+    let mut embeddings: Vec<Option<Vec<f32>>> = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.iter().enumerate() {
+        // Very short segments don't carry enough voice to embed reliably
+        let embedding = if segment.end - segment.start < MIN_EMBEDDING_SECONDS {
+            None
+        } else {
+            match extractor.compute(&segment.samples) {
+                Ok(embedding) => Some(embedding.collect::<Vec<f32>>()),
+                Err(e) => {
+                    eprintln!("\nEmbedding error for segment {}: {}", i + 1, e);
+                    None
+                }
+            }
+        };
+        embeddings.push(embedding);
+        if (i + 1) % 10 == 0 {
+            print!("\r  Embedded {} of {} segments...", i + 1, segments.len());
+            std::io::stdout().flush().unwrap();
+        }
+    }
+    println!("\r  Embedded {} segments total", embeddings.iter().flatten().count());
+
+    embeddings
+}
+
+// Name each cluster after the enrolled profile it matches, or "Unknown N"
+fn name_speakers(
+    embeddings: &[Vec<f32>],
+    labels: &[usize],
+    options: &DiarizationOptions,
+) -> Result<Vec<String>> {
+    let profiles = match options.profiles_dir {
+        Some(ref dir) => ProfileStore::new(dir).load_all()?,
+        None => Vec::new(),
+    };
+    if profiles.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let speaker_count = labels.iter().max().map_or(0, |&max| max + 1);
+    let centroids: Vec<Vec<f32>> = (0..speaker_count)
+        .map(|speaker| {
+            mean_embedding(
+                embeddings
+                    .iter()
+                    .zip(labels)
+                    .filter(|(_, &label)| label == speaker)
+                    .map(|(embedding, _)| embedding),
+            )
+        })
+        .collect();
+
+    let mut unknown_count = 0;
+    let names = match_profiles(&centroids, &profiles, options.profile_threshold)
+        .into_iter()
+        .map(|matched| match matched {
+            Some(profile_index) => profiles[profile_index].name.clone(),
+            None => {
+                unknown_count += 1;
+                format!("Unknown {}", unknown_count)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    println!("Matched {} of {} speakers to enrolled voice profiles",
+             names.len() - unknown_count, names.len());
+
+    Ok(names)
+}
+
+// Mean of length-normalised embeddings
+fn mean_embedding<'a>(embeddings: impl Iterator<Item = &'a Vec<f32>>) -> Vec<f32> {
+    let mut sum: Vec<f32> = Vec::new();
+    let mut count = 0;
+    for embedding in embeddings {
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt().max(f32::EPSILON);
+        if sum.is_empty() {
+            sum = vec![0.0; embedding.len()];
+        }
+        for (total, &value) in sum.iter_mut().zip(embedding) {
+            *total += value / norm;
+        }
+        count += 1;
+    }
+    sum.iter().map(|total| total / count.max(1) as f32).collect()
+}
+
+/// Label each segment with the speaker whose turns overlap it the most,
+/// or the speaker of the nearest turn when none overlap it.
+///
+/// With `split_on_speaker_change`, a segment whose words are spoken by
+/// different speakers is split into one segment per speaker. This needs
+/// word timings; segments without words are labelled as a whole.
+pub fn assign_speakers(
+    segments: Vec<Segment>,
+    turns: &[SpeakerTurn],
+    split_on_speaker_change: bool,
+) -> Vec<Segment> {
+    let mut assigned = Vec::with_capacity(segments.len());
+    for mut segment in segments {
+        if split_on_speaker_change && !segment.words.is_empty() {
+            assigned.extend(split_by_speaker(segment, turns));
+        } else {
+            segment.speaker = speaker_for_interval(turns, ms_to_secs(segment.start_ms), ms_to_secs(segment.end_ms));
+            assigned.push(segment);
+        }
+    }
+    assigned
+}
+
+/// The speaker with the most speaking time within `start..end` (seconds),
+/// or the speaker of the nearest turn if no turn overlaps it.
+pub fn speaker_for_interval(turns: &[SpeakerTurn], start: f64, end: f64) -> Option<usize> {
+    let mut overlap_by_speaker: Vec<f64> = Vec::new();
+    for turn in turns {
+        let overlap = end.min(turn.end) - start.max(turn.start);
+        if overlap > 0.0 {
+            if overlap_by_speaker.len() <= turn.speaker {
+                overlap_by_speaker.resize(turn.speaker + 1, 0.0);
+            }
+            overlap_by_speaker[turn.speaker] += overlap;
+        }
+    }
+
+    overlap_by_speaker
+        .iter()
+        .enumerate()
+        .filter(|(_, &overlap)| overlap > 0.0)
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(speaker, _)| speaker)
+        .or_else(|| nearest_speaker(turns, start, end))
+}
+
+// Split a segment into runs of consecutive words by the same speaker
+fn split_by_speaker(segment: Segment, turns: &[SpeakerTurn]) -> Vec<Segment> {
+    let word_speakers: Vec<Option<usize>> = segment
+        .words
+        .iter()
+        .map(|word| speaker_for_interval(turns, ms_to_secs(word.start_ms), ms_to_secs(word.end_ms)))
+        .collect();
+
+    // Index of the first word of each run
+    let mut run_starts = vec![0];
+    for i in 1..word_speakers.len() {
+        if word_speakers[i] != word_speakers[i - 1] {
+            run_starts.push(i);
+        }
+    }
+    if run_starts.len() == 1 {
+        let mut segment = segment;
+        segment.speaker = word_speakers[0];
+        return vec![segment];
+    }
+
+    // Tokens go to the run whose words they fall in, judged by start time
+    let boundaries: Vec<i64> = run_starts[1..].iter().map(|&i| segment.words[i].start_ms).collect();
+    let mut run_tokens: Vec<Vec<Token>> = vec![Vec::new(); run_starts.len()];
+    for token in &segment.tokens {
+        let start_ms = token.start_ms.unwrap_or(segment.start_ms);
+        let run = boundaries.iter().filter(|&&boundary| start_ms >= boundary).count();
+        run_tokens[run].push(token.clone());
+    }
+
+    let mut parts = Vec::with_capacity(run_starts.len());
+    for (run, tokens) in run_tokens.into_iter().enumerate() {
+        let first = run_starts[run];
+        let end = run_starts.get(run + 1).copied().unwrap_or(segment.words.len());
+        let words = &segment.words[first..end];
+
+        // The outer parts keep the segment's own start and end
+        let start_ms = if run == 0 { segment.start_ms } else { words[0].start_ms };
+        let end_ms = if end == segment.words.len() { segment.end_ms } else { words[words.len() - 1].end_ms };
+        let text = words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ");
+
+        let mut part = Segment::new(start_ms, end_ms, text).with_tokens(tokens);
+        part.speaker = word_speakers[first];
+        part.no_speech_prob = segment.no_speech_prob;
+        part.language = segment.language.clone();
+        part.words = words.to_vec();
+        // A translation can't be split by word, so it goes with the first part
+        if run == 0 {
+            part.translation = segment.translation.clone();
+        }
+        parts.push(part);
+    }
+    parts
+}
+
+fn nearest_speaker(turns: &[SpeakerTurn], start: f64, end: f64) -> Option<usize> {
+    turns
+        .iter()
+        .min_by(|a, b| gap_between(a, start, end).total_cmp(&gap_between(b, start, end)))
+        .map(|turn| turn.speaker)
+}
+
+// Seconds between `turn` and the interval `start..end`, 0 if they overlap
+fn gap_between(turn: &SpeakerTurn, start: f64, end: f64) -> f64 {
+    (turn.start - end).max(start - turn.end).max(0.0)
+}
+
+fn ms_to_secs(ms: i64) -> f64 {
+    ms as f64 / 1000.0
+}
@@ -0,0 +1,256 @@
+//! Catching Whisper hallucinations.
+//!
+//! Whisper sometimes loops on a phrase or invents text over silence. Each
+//! segment is checked for the signs: low confidence (mean token
+//! log-probability), text that compresses too well, which means it is
+//! repetitive, and phrases repeated over and over. Suspect segments are
+//! decoded again at higher temperatures; segments that are most likely
+//! silence are dropped, and loops that remain are cut down to one
+//! occurrence.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+use crate::audio::WHISPER_SAMPLE_RATE;
+use crate::streaming::normalize_word;
+use crate::transcript::Segment;
+use crate::vad::SpeechRegion;
+
+/// Longest phrase, in words, looked for when detecting loops.
+const MAX_LOOP_WORDS: usize = 8;
+
+/// Thresholds for judging Whisper's output.
+#[derive(Debug, Clone)]
+pub struct FilterOptions {
+    /// Segments more likely than this to contain no speech are dropped if
+    /// their mean log-probability is also below `logprob_threshold`.
+    pub no_speech_threshold: f32,
+    /// Segments whose tokens have a lower mean log-probability are decoded
+    /// again.
+    pub logprob_threshold: f32,
+    /// Segments whose text compresses (zlib) by a higher ratio than this are
+    /// decoded again.
+    pub compression_ratio_threshold: f32,
+    /// A phrase, or a whole segment, repeated this many times in a row is a
+    /// loop.
+    pub repetition_threshold: usize,
+    /// Suspect segments are decoded again at this temperature, then at
+    /// multiples of it up to 1.0. 0 disables decoding again.
+    pub temperature_increment: f32,
+}
+
+impl Default for FilterOptions {
+    fn default() -> Self {
+        Self {
+            no_speech_threshold: 0.6,
+            logprob_threshold: -1.0,
+            compression_ratio_threshold: 2.4,
+            repetition_threshold: 4,
+            temperature_increment: 0.2,
+        }
+    }
+}
+
+impl FilterOptions {
+    /// Whether `segment` looks unreliable enough to decode again.
+    pub fn needs_fallback(&self, segment: &Segment) -> bool {
+        (!segment.tokens.is_empty() && segment.avg_logprob < self.logprob_threshold)
+            || compression_ratio(&segment.text) > self.compression_ratio_threshold
+            || !find_loops(&words(&segment.text), self.repetition_threshold).is_empty()
+    }
+
+    /// Whether `segment` is most likely text invented over silence, given
+    /// its probability of containing no speech.
+    pub fn is_silence(&self, segment: &Segment, no_speech_prob: f32) -> bool {
+        no_speech_prob > self.no_speech_threshold && segment.avg_logprob < self.logprob_threshold
+    }
+
+    /// Temperatures to decode a suspect segment again at, in order.
+    pub fn fallback_temperatures(&self) -> Vec<f32> {
+        if self.temperature_increment <= 0.0 {
+            return Vec::new();
+        }
+        (1..)
+            .map(|step| step as f32 * self.temperature_increment)
+            .take_while(|&temperature| temperature <= 1.0 + 1e-3)
+            .collect()
+    }
+}
+
+/// How much better than 1:1 `text` compresses with zlib. Natural text is
+/// around 1.5 to 2; loops compress far better.
+pub fn compression_ratio(text: &str) -> f32 {
+    if text.is_empty() {
+        return 0.0;
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let compressed = encoder
+        .write_all(text.as_bytes())
+        .and_then(|_| encoder.finish())
+        .map_or(text.len(), |compressed| compressed.len());
+    text.len() as f32 / compressed.max(1) as f32
+}
+
+/// Runs of a phrase repeated at least `threshold` times in a row among
+/// `words`, as (first word, words in the phrase, repetitions).
+pub fn find_loops(words: &[String], threshold: usize) -> Vec<(usize, usize, usize)> {
+    let mut loops = Vec::new();
+    if threshold < 2 {
+        return loops;
+    }
+    let mut start = 0;
+    while start < words.len() {
+        let found = (1..=MAX_LOOP_WORDS).find_map(|length| {
+            let phrase = words.get(start..start + length)?;
+            if phrase.iter().all(String::is_empty) {
+                return None;
+            }
+            let repeats = 1 + (1..)
+                .take_while(|&k| words.get(start + k * length..start + (k + 1) * length) == Some(phrase))
+                .count();
+            (repeats >= threshold).then_some((start, length, repeats))
+        });
+        match found {
+            Some(run @ (_, length, repeats)) => {
+                loops.push(run);
+                start += length * repeats;
+            }
+            None => start += 1,
+        }
+    }
+    loops
+}
+
+/// Cut every phrase `segment` repeats `threshold` or more times in a row
+/// down to a single occurrence, along with its words and tokens.
+pub fn remove_loops(mut segment: Segment, threshold: usize) -> Segment {
+    let text_words: Vec<String> = segment.text.split_whitespace().map(str::to_string).collect();
+    let loops = find_loops(&words(&segment.text), threshold);
+    if loops.is_empty() {
+        return segment;
+    }
+    let removed = |index: usize| {
+        loops
+            .iter()
+            .any(|&(start, length, repeats)| index >= start + length && index < start + length * repeats)
+    };
+
+    segment.text = text_words
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| !removed(i))
+        .map(|(_, word)| word.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if segment.words.len() == text_words.len() {
+        let mut index = 0;
+        segment.words.retain(|_| {
+            index += 1;
+            !removed(index - 1)
+        });
+    }
+
+    // A token starting with whitespace begins the next word, as in
+    // `group_words`
+    let mut word = 0;
+    let token_words: Vec<usize> = segment
+        .tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            if i > 0 && token.text.starts_with(char::is_whitespace) {
+                word += 1;
+            }
+            word
+        })
+        .collect();
+    if token_words.last().map_or(0, |&last| last + 1) == text_words.len() {
+        let tokens = std::mem::take(&mut segment.tokens)
+            .into_iter()
+            .zip(token_words)
+            .filter(|&(_, word)| !removed(word))
+            .map(|(token, _)| token)
+            .collect();
+        segment = segment.with_tokens(tokens);
+    }
+    if let (Some(first), Some(last)) = (segment.words.first(), segment.words.last()) {
+        segment.start_ms = segment.start_ms.max(first.start_ms);
+        segment.end_ms = segment.end_ms.min(last.end_ms).max(segment.start_ms);
+    }
+    segment
+}
+
+/// Keep only the first of `threshold` or more consecutive segments with the
+/// same text.
+pub fn remove_repeated_segments(segments: Vec<Segment>, threshold: usize) -> Vec<Segment> {
+    let mut kept: Vec<Segment> = Vec::with_capacity(segments.len());
+    let mut run: Vec<Segment> = Vec::new();
+    let flush = |run: &mut Vec<Segment>, kept: &mut Vec<Segment>| {
+        if threshold >= 2 && run.len() >= threshold {
+            run.truncate(1);
+        }
+        kept.append(run);
+    };
+    for segment in segments {
+        let same = run
+            .first()
+            .is_some_and(|first| !segment.text.is_empty() && words(&first.text) == words(&segment.text));
+        if !same {
+            flush(&mut run, &mut kept);
+        }
+        run.push(segment);
+    }
+    flush(&mut run, &mut kept);
+    kept
+}
+
+/// Join the segments Whisper produced for one stretch of audio into one.
+pub fn merge_segments(segments: Vec<Segment>) -> Option<Segment> {
+    let start_ms = segments.first()?.start_ms;
+    let end_ms = segments.iter().map(|segment| segment.end_ms).max()?;
+    let text = segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let no_speech_prob = segments
+        .iter()
+        .filter_map(|segment| segment.no_speech_prob)
+        .reduce(f32::min);
+    let mut tokens = Vec::new();
+    let mut words = Vec::new();
+    for segment in segments {
+        tokens.extend(segment.tokens);
+        words.extend(segment.words);
+    }
+    let mut merged = Segment::new(start_ms, end_ms, text).with_tokens(tokens);
+    merged.words = words;
+    merged.no_speech_prob = no_speech_prob;
+    Some(merged)
+}
+
+/// Share of `segment` that `regions` of 16 kHz audio don't cover, as an
+/// estimate of its probability of containing no speech.
+pub fn no_speech_estimate(segment: &Segment, regions: &[SpeechRegion]) -> f32 {
+    let duration = segment.end_ms - segment.start_ms;
+    if duration <= 0 {
+        return 0.0;
+    }
+    let to_ms = |position: u64| (position * 1000 / WHISPER_SAMPLE_RATE as u64) as i64;
+    let speech: i64 = regions
+        .iter()
+        .map(|region| {
+            let start = to_ms(region.start).max(segment.start_ms);
+            let end = to_ms(region.end).min(segment.end_ms);
+            (end - start).max(0)
+        })
+        .sum();
+    1.0 - speech as f32 / duration as f32
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(normalize_word).collect()
+}
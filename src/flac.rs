@@ -0,0 +1,348 @@
+//! A small streaming FLAC encoder for 16- and 24-bit PCM.
+//!
+//! Each frame is written as soon as its block of samples is complete, so a
+//! recording cut short by a crash is still a playable file. Frames use
+//! whichever fixed predictor (order 0-4) codes smallest, falling back to
+//! verbatim samples.
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Samples per channel in each frame.
+const BLOCK_SIZE: usize = 4096;
+/// Largest 4-bit Rice parameter; 15 is reserved as the escape code.
+const MAX_RICE_PARAMETER: u32 = 14;
+/// Largest 5-bit Rice parameter, for residuals that need more.
+const MAX_RICE2_PARAMETER: u32 = 30;
+/// Byte offset of the STREAMINFO block, after the marker and block header.
+const STREAMINFO_OFFSET: u64 = 8;
+
+/// Writes interleaved 16- or 24-bit samples as a FLAC stream.
+pub struct FlacWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    // Interleaved samples not yet written as a frame
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_samples: u64,
+    min_frame_size: usize,
+    max_frame_size: usize,
+}
+
+impl<W: Write + Seek> FlacWriter<W> {
+    /// Start a stream with the given format. Supports 1 to 8 channels of
+    /// 16 or 24 bits.
+    pub fn new(writer: W, sample_rate: u32, channels: u16, bits_per_sample: u32) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("FLAC supports 1 to 8 channels, not {}", channels),
+            ));
+        }
+        if bits_per_sample != 16 && bits_per_sample != 24 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Only 16 and 24 bits per sample are supported, not {}", bits_per_sample),
+            ));
+        }
+
+        let mut flac = Self {
+            writer,
+            sample_rate,
+            channels: channels as usize,
+            bits_per_sample,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: 0,
+            max_frame_size: 0,
+        };
+
+        let mut header = BitWriter::default();
+        header.write_bytes(b"fLaC");
+        // Last metadata block, type STREAMINFO, 34 bytes long
+        header.write(1, 1);
+        header.write(0, 7);
+        header.write(34, 24);
+        flac.writer.write_all(&header.into_bytes())?;
+        // Frame sizes and sample count are unknown (0) until `finalize`
+        let streaminfo = flac.streaminfo();
+        flac.writer.write_all(&streaminfo)?;
+
+        Ok(flac)
+    }
+
+    /// Append interleaved samples, writing every frame that fills up.
+    /// Samples must be in the range of the stream's bits per sample.
+    pub fn write_samples(&mut self, samples: &[i32]) -> io::Result<()> {
+        let block_len = BLOCK_SIZE * self.channels;
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() == block_len {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Write the last partial frame and fill in the stream's length.
+    pub fn finalize(mut self) -> io::Result<W> {
+        // Drop an incomplete trailing sample frame
+        let whole = self.pending.len() - self.pending.len() % self.channels;
+        self.pending.truncate(whole);
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+
+        let streaminfo = self.streaminfo();
+        self.writer.seek(SeekFrom::Start(STREAMINFO_OFFSET))?;
+        self.writer.write_all(&streaminfo)?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn streaminfo(&self) -> Vec<u8> {
+        let mut info = BitWriter::default();
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(self.min_frame_size as u64, 24);
+        info.write(self.max_frame_size as u64, 24);
+        info.write(self.sample_rate as u64, 20);
+        info.write(self.channels as u64 - 1, 3);
+        info.write(self.bits_per_sample as u64 - 1, 5);
+        info.write(self.total_samples, 36);
+        // No MD5 signature
+        info.write_bytes(&[0; 16]);
+        info.into_bytes()
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let block_size = self.pending.len() / self.channels;
+        let mut frame = BitWriter::default();
+
+        // Sync code and fixed-blocksize strategy; the block size follows the
+        // header (code 0b0111) and the sample rate comes from STREAMINFO
+        frame.write(0b11_1111_1111_1110, 14);
+        frame.write(0, 1);
+        frame.write(0, 1);
+        frame.write(0b0111, 4);
+        frame.write(0b0000, 4);
+        // Independent channels, 16 or 24 bits per sample
+        frame.write(self.channels as u64 - 1, 4);
+        frame.write(if self.bits_per_sample == 16 { 0b100 } else { 0b110 }, 3);
+        frame.write(0, 1);
+        frame.write_utf8(self.frame_number);
+        frame.write(block_size as u64 - 1, 16);
+        let crc = crc8(frame.bytes());
+        frame.write(crc as u64, 8);
+
+        let mut channel = Vec::with_capacity(block_size);
+        for c in 0..self.channels {
+            channel.clear();
+            channel.extend(self.pending.iter().skip(c).step_by(self.channels));
+            write_subframe(&mut frame, &channel, self.bits_per_sample);
+        }
+
+        frame.align();
+        let crc = crc16(frame.bytes());
+        frame.write(crc as u64, 16);
+
+        let bytes = frame.into_bytes();
+        self.writer.write_all(&bytes)?;
+        if self.frame_number == 0 {
+            self.min_frame_size = bytes.len();
+        }
+        self.min_frame_size = self.min_frame_size.min(bytes.len());
+        self.max_frame_size = self.max_frame_size.max(bytes.len());
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+// Write one channel of a frame using the cheapest fixed predictor, or
+// verbatim if prediction doesn't help
+fn write_subframe(frame: &mut BitWriter, samples: &[i32], bits_per_sample: u32) {
+    let verbatim_bits = samples.len() as u64 * bits_per_sample as u64;
+    let best = (0..=4usize.min(samples.len() - 1))
+        .map(|order| {
+            let residuals = fixed_residuals(samples, order);
+            let (parameter, bits) = best_rice_parameter(&residuals);
+            let total = order as u64 * bits_per_sample as u64 + 10 + bits;
+            (order, residuals, parameter, total)
+        })
+        .min_by_key(|&(_, _, _, total)| total);
+
+    match best {
+        Some((order, residuals, parameter, total)) if total < verbatim_bits => {
+            // Zero padding bit, FIXED subframe type, no wasted bits
+            frame.write(0, 1);
+            frame.write(0b001000 | order as u64, 6);
+            frame.write(0, 1);
+            for &sample in &samples[..order] {
+                frame.write_signed(sample, bits_per_sample);
+            }
+            // Rice coding with a 4-bit parameter where it fits, else 5-bit,
+            // in a single partition
+            let parameter_bits = if parameter <= MAX_RICE_PARAMETER { 4 } else { 5 };
+            frame.write(parameter_bits as u64 - 4, 2);
+            frame.write(0, 4);
+            frame.write(parameter as u64, parameter_bits);
+            for &residual in &residuals {
+                frame.write_rice(residual, parameter);
+            }
+        }
+        _ => {
+            frame.write(0, 1);
+            frame.write(0b000001, 6);
+            frame.write(0, 1);
+            for &sample in samples {
+                frame.write_signed(sample, bits_per_sample);
+            }
+        }
+    }
+}
+
+// Residuals of the fixed polynomial predictor of the given order
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i32> {
+    (order..samples.len())
+        .map(|n| {
+            let s = |k: usize| samples[n - k];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+// The Rice parameter coding `residuals` in the fewest bits, and that count
+fn best_rice_parameter(residuals: &[i32]) -> (u32, u64) {
+    (0..=MAX_RICE2_PARAMETER)
+        .map(|parameter| {
+            let bits = residuals
+                .iter()
+                .map(|&r| (zigzag(r) >> parameter) as u64 + 1 + parameter as u64)
+                .sum();
+            (parameter, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Big-endian bit packer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    // Bits in use in the last byte, 0 when byte-aligned
+    bit_len: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        if self.bit_len == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.bit_len;
+        }
+        self.bit_len = (self.bit_len + 1) % 8;
+    }
+
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    fn write_rice(&mut self, value: i32, parameter: u32) {
+        let value = zigzag(value);
+        for _ in 0..(value >> parameter) {
+            self.write_bit(false);
+        }
+        self.write_bit(true);
+        self.write(value as u64, parameter);
+    }
+
+    // FLAC's UTF-8-like variable length integer coding
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        let continuation_bytes = match value {
+            0..=0x7FF => 1,
+            0x800..=0xFFFF => 2,
+            0x1_0000..=0x1F_FFFF => 3,
+            0x20_0000..=0x3FF_FFFF => 4,
+            0x400_0000..=0x7FFF_FFFF => 5,
+            _ => 6,
+        };
+        // Leading byte: one 1 bit per byte in the sequence, then a 0
+        let prefix = !(0xFFu64 >> (continuation_bytes + 1)) & 0xFF;
+        self.write(prefix | (value >> (6 * continuation_bytes)), 8);
+        for i in (0..continuation_bytes).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        self.bit_len = 0;
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write(byte as u64, 8);
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+// CRC-8 with polynomial x^8 + x^2 + x + 1, as used by frame headers
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// CRC-16 with polynomial x^16 + x^15 + x^2 + 1, as used by frame footers
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+    crc
+}
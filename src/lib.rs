@@ -0,0 +1,44 @@
+//! Transcribe audio files or live microphone input to text for meeting minutes.
+//!
+//! The [`Transcriber`] loads a Whisper model once and can then be used to
+//! transcribe any [`AudioSource`], optionally labelling segments by speaker.
+//!
+//! ```no_run
+//! use audio_recorder::{FileSource, Transcriber, TranscriberOptions};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let transcriber = Transcriber::new("./models/ggml-base.en.bin", TranscriberOptions::default())?;
+//! let transcript = transcriber.transcribe_source(&mut FileSource::new("meeting.wav"))?;
+//! for segment in &transcript.segments {
+//!     println!("{} ms - {} ms: {}", segment.start_ms, segment.end_ms, segment.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod audio;
+pub mod capture;
+pub mod clustering;
+pub mod decoding;
+pub mod devices;
+pub mod diarization;
+pub mod filter;
+pub mod flac;
+pub mod live;
+pub mod model;
+pub mod output;
+pub mod profiles;
+pub mod recording;
+pub mod resample;
+pub mod source;
+pub mod streaming;
+pub mod transcript;
+pub mod vad;
+pub mod vocabulary;
+
+mod transcriber;
+
+pub use diarization::DiarizationOptions;
+pub use source::{AudioBlock, AudioFormat, AudioSource, FileSource, MicrophoneSource, PcmSource};
+pub use transcriber::{Task, Transcriber, TranscriberOptions};
+pub use transcript::{AudioDevice, LanguageProbability, Segment, Token, Transcript, Word};
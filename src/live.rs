@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::audio::WHISPER_SAMPLE_RATE;
+use crate::capture::Timeline;
+use crate::output::{self, FormatOptions, OutputFormat, TranscriptWriter};
+use crate::recording::RecordingWriter;
+use crate::source::AudioSource;
+use crate::streaming::SlidingWindow;
+use crate::transcriber::{samples_to_ms, Transcriber};
+use crate::transcript::{Segment, Transcript};
+use crate::vad::{SpeechChunker, SpeechWindow};
+
+/// Seconds of 16 kHz audio kept for transcription; older audio is dropped.
+const HISTORY_SECONDS: u32 = 120;
+
+/// Longest window of speech transcribed at once when windows end at
+/// pauses, in seconds.
+const MAX_SPEECH_WINDOW_SECONDS: u64 = 25;
+
+/// How often waiting threads check whether there is work to do.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Options for live recording.
+#[derive(Debug, Clone)]
+pub struct LiveOptions {
+    /// How often buffered audio is transcribed, in seconds. With voice
+    /// activity detection, windows instead end at the first pause after
+    /// this much audio.
+    pub chunk_seconds: u64,
+    /// How much already transcribed audio each window starts with, so
+    /// words cut off at the end of one window are heard in full. Not used
+    /// with voice activity detection.
+    pub overlap_seconds: f32,
+    /// Prompt each window with the text transcribed before it.
+    pub carry_prompt: bool,
+    /// Format the output file is streamed in.
+    pub format: OutputFormat,
+    pub format_options: FormatOptions,
+    /// Also save the captured audio to this WAV or FLAC file, at the
+    /// source's own sample rate and channel count.
+    pub save_audio: Option<PathBuf>,
+}
+
+impl Default for LiveOptions {
+    fn default() -> Self {
+        Self {
+            chunk_seconds: 5,
+            overlap_seconds: 1.0,
+            carry_prompt: true,
+            format: OutputFormat::default(),
+            format_options: FormatOptions::default(),
+            save_audio: None,
+        }
+    }
+}
+
+/// Transcribe `source` as it delivers audio, every `options.chunk_seconds`
+/// of audio, appending the results to `output_path` until the source ends
+/// or `stop` is set. Returns everything that was transcribed.
+///
+/// If the transcriber uses voice activity detection, only speech is
+/// transcribed, in windows that end at pauses.
+pub fn record_and_transcribe_live(
+    transcriber: &Transcriber,
+    source: &mut dyn AudioSource,
+    output_path: &Path,
+    options: &LiveOptions,
+    stop: &AtomicBool,
+) -> Result<Transcript> {
+    let chunk_seconds = options.chunk_seconds;
+
+    println!("=== Live Recording & Transcription ===");
+
+    let format = source.start()?;
+    let device = source.device();
+    println!("Recording from: {}", source.describe());
+    match device {
+        Some(ref device) => println!("Using config: {} Hz, {} ch, {}",
+                                     device.sample_rate, device.channels, device.sample_format),
+        None => println!("Format: {} Hz, {} ch", format.sample_rate, format.channels),
+    }
+
+    // Prepare output file
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
+    let mut writer = TranscriptWriter::new(file, options.format, options.format_options.clone());
+
+    let mut transcript = transcriber.new_transcript(&source.describe());
+    transcript.device = device;
+    writer.write_header(&transcript)
+        .and_then(|_| writer.flush())
+        .context("Failed to write to output file")?;
+    let output_file = Mutex::new(writer);
+    let transcript = Mutex::new(transcript);
+
+    // Print a segment and append it to the transcript and output file
+    let emit_segment = |segment: Segment| {
+        println!("{}", output::text::format_segment(&segment, &[]));
+        io::stdout().flush().unwrap();
+
+        if let Ok(mut writer) = output_file.lock() {
+            let _ = writer.write_segment(&segment).and_then(|_| writer.flush());
+        }
+        transcript.lock().unwrap().segments.push(segment);
+    };
+
+    let sample_rate = format.sample_rate;
+    let channels = format.channels as usize;
+    let chunk_samples = chunk_seconds * WHISPER_SAMPLE_RATE as u64;
+
+    let timeline = Mutex::new(Timeline::with_quality(
+        sample_rate,
+        format.channels,
+        HISTORY_SECONDS,
+        transcriber.options().resample_quality,
+    ));
+    // End of the audio handed to the last window, on the 16 kHz timeline
+    let transcribed_until = AtomicU64::new(0);
+    let source_finished = AtomicBool::new(false);
+
+    // With voice activity detection, windows end at pauses and may run to
+    // the maximum before one comes
+    let max_speech_samples = (2 * chunk_samples).min(MAX_SPEECH_WINDOW_SECONDS * WHISPER_SAMPLE_RATE as u64);
+    let chunker = match transcriber.options().vad {
+        Some(ref vad) => Some(Mutex::new(SpeechChunker::new(vad, chunk_samples, max_speech_samples)?)),
+        None => None,
+    };
+    // How far recorded audio may run ahead of transcription
+    let max_lead = match chunker {
+        Some(_) => max_speech_samples + chunk_samples,
+        None => 2 * chunk_samples,
+    };
+
+    let mut audio_file = match options.save_audio {
+        Some(ref path) => {
+            let audio_file = RecordingWriter::create(path, sample_rate, format.channels, format.bits_per_sample)?;
+            println!("Saving audio to: {}", path.display());
+            Some(audio_file)
+        }
+        None => None,
+    };
+
+    println!("\nRecording...\n");
+    if chunker.is_some() {
+        println!("Transcribing speech at pauses, about every {} seconds...\n", chunk_seconds);
+    } else {
+        println!("Transcribing every {} seconds with {} seconds of overlap...\n",
+                 chunk_seconds, options.overlap_seconds);
+    }
+
+    // Move blocks from the source into the timeline and the audio file
+    // until the source ends
+    let started = Instant::now();
+    let mut pump = || -> Result<()> {
+        let mut next_frame = 0;
+        let mut unflushed = 0;
+        let mut stopping = false;
+        loop {
+            if !stopping && stop.load(Ordering::Relaxed) {
+                source.stop();
+                stopping = true;
+            }
+            let Some(block) = source.next_block()? else {
+                break;
+            };
+
+            // Keep later audio in place if some was lost
+            let lost_frames = block.frame.saturating_sub(next_frame) as usize;
+            next_frame = block.end_frame(format.channels);
+            let audio_end = {
+                let mut timeline = timeline.lock().unwrap();
+                timeline.push_silence(lost_frames);
+                timeline.push_interleaved(&block.samples);
+                timeline.end()
+            };
+
+            if let Some(ref mut audio_file) = audio_file {
+                if lost_frames > 0 {
+                    audio_file.write(&vec![0.0; lost_frames * channels])?;
+                }
+                audio_file.write(&block.samples)?;
+                // Flush about once a second
+                unflushed += block.samples.len();
+                if unflushed >= sample_rate as usize * channels {
+                    audio_file.flush()?;
+                    unflushed = 0;
+                }
+            }
+
+            // A source faster than real time (a pipe, a generator) waits for
+            // transcription to catch up rather than outrunning the history
+            let ahead_of_clock = samples_to_ms(audio_end as usize) > started.elapsed().as_millis() as i64;
+            while ahead_of_clock
+                && !stop.load(Ordering::Relaxed)
+                && audio_end >= transcribed_until.load(Ordering::Relaxed) + max_lead
+            {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+        timeline.lock().unwrap().finish();
+
+        match audio_file.take() {
+            Some(audio_file) => audio_file.finalize().context("Failed to save audio"),
+            None => Ok(()),
+        }
+    };
+
+    // Transcribe from the window's start to the end of what has been
+    // recorded so far, emitting whatever the window adds
+    let window = Mutex::new(SlidingWindow::new(
+        (options.overlap_seconds * 1000.0) as i64,
+        options.carry_prompt,
+    ));
+    let transcribe_window = |is_final: bool| -> Result<()> {
+        let mut window = window.lock().unwrap();
+        let (window_start, samples_16k) = {
+            let requested = window.window_start_ms() as u64 * WHISPER_SAMPLE_RATE as u64 / 1000;
+            timeline.lock().unwrap().samples_from(requested)
+        };
+        transcribed_until.store(window_start + samples_16k.len() as u64, Ordering::Relaxed);
+        if samples_16k.is_empty() {
+            return Ok(());
+        }
+        let window_start_ms = samples_to_ms(window_start as usize);
+        let window_end_ms = window_start_ms + samples_to_ms(samples_16k.len());
+
+        let segments = transcriber
+            .transcribe_chunk_with_prompt(&samples_16k, window.prompt())?
+            .into_iter()
+            .map(|segment| segment.offset(window_start_ms))
+            .collect();
+        for segment in window.accept(segments, window_start_ms, window_end_ms, is_final) {
+            emit_segment(segment);
+        }
+        Ok(())
+    };
+
+    // Judge the audio recorded since last time, returning a window of
+    // speech once one ends at a pause
+    let next_speech = |chunker: &mut SpeechChunker| -> Result<Option<SpeechWindow>> {
+        let speech = push_recorded(chunker, &timeline)?;
+        transcribed_until.store(chunker.start(), Ordering::Relaxed);
+        Ok(speech)
+    };
+
+    // Transcribe the speech in a window, emitting whatever it adds
+    let transcribe_speech = |speech: SpeechWindow| -> Result<()> {
+        let mut window = window.lock().unwrap();
+        let (start, mut samples) = timeline.lock().unwrap().samples_from(speech.start);
+        samples.truncate(speech.end.saturating_sub(start) as usize);
+
+        let segments = transcriber.transcribe_regions(&samples, start, &speech.regions, window.prompt())?;
+        let window_start_ms = samples_to_ms(speech.start as usize);
+        let window_end_ms = samples_to_ms(speech.end as usize);
+        for segment in window.accept(segments, window_start_ms, window_end_ms, true) {
+            emit_segment(segment);
+        }
+        Ok(())
+    };
+
+    // Transcribe the next window if enough audio has arrived for it
+    let transcribe_ready = || -> Result<()> {
+        match chunker {
+            Some(ref chunker) => {
+                let speech = next_speech(&mut chunker.lock().unwrap())?;
+                speech.map_or(Ok(()), transcribe_speech)
+            }
+            None => {
+                let audio_end = timeline.lock().unwrap().end();
+                if audio_end < transcribed_until.load(Ordering::Relaxed) + chunk_samples {
+                    return Ok(());
+                }
+                transcribe_window(false)
+            }
+        }
+    };
+
+    // Transcribe everything left once the source has ended
+    let transcribe_rest = || -> Result<()> {
+        match chunker {
+            Some(ref chunker) => {
+                let mut chunker = chunker.lock().unwrap();
+                if let Some(speech) = next_speech(&mut chunker)? {
+                    transcribe_speech(speech)?;
+                }
+                chunker.finish().map_or(Ok(()), transcribe_speech)
+            }
+            None => transcribe_window(true),
+        }
+    };
+
+    std::thread::scope(|scope| -> Result<()> {
+        // Transcribe each time another window of audio is ready
+        let transcription_handle = scope.spawn(|| loop {
+            std::thread::sleep(POLL_INTERVAL);
+
+            if source_finished.load(Ordering::Acquire) {
+                break;
+            }
+            if let Err(e) = transcribe_ready() {
+                eprintln!("Transcription error: {:#}", e);
+            }
+        });
+
+        let pumped = pump();
+        source_finished.store(true, Ordering::Release);
+        transcription_handle.join().unwrap();
+        pumped?;
+
+        // Process remaining audio
+        println!("\nProcessing remaining audio...");
+        transcribe_rest().context("Final transcription failed")
+    })?;
+
+    let mut transcript = transcript.into_inner().unwrap();
+    transcript.audio_duration_ms = timeline.lock().unwrap().duration_ms();
+
+    let mut writer = output_file.into_inner().unwrap();
+    writer.finish(&transcript)?;
+    if writer.format() == OutputFormat::Txt {
+        let file = writer.into_inner();
+        writeln!(&file, "\nEnded: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+    }
+
+    println!("\n✓ Recording stopped!");
+    println!("✓ Transcription saved to: {}", output_path.display());
+
+    Ok(transcript)
+}
+
+/// A flag that is set once Enter is pressed, for stopping
+/// [`record_and_transcribe_live`] from the terminal.
+pub fn stop_on_enter() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let flag = stop.clone();
+    std::thread::spawn(move || {
+        let mut input = String::new();
+        let _ = io::stdin().read_line(&mut input);
+        flag.store(true, Ordering::Relaxed);
+    });
+    stop
+}
+
+/// Feed `chunker` the audio `timeline` has recorded since it was last fed,
+/// returning a window of speech once one ends at a pause. Each sample is fed
+/// once, however the recording arrived.
+pub fn push_recorded(chunker: &mut SpeechChunker, timeline: &Mutex<Timeline>) -> Result<Option<SpeechWindow>> {
+    let (start, mut samples) = timeline.lock().unwrap().samples_from(chunker.fed());
+    // Audio dropped from the history before it was judged counts as silence
+    let lost = start.saturating_sub(chunker.fed()) as usize;
+    samples.splice(0..0, std::iter::repeat_n(0.0, lost));
+    chunker.push(&samples)
+}
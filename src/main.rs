@@ -1,35 +1,63 @@
 use anyhow::{Context, Result};
-use clap::Parser;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use audio_recorder::audio::{is_stdin, load_audio_file, WHISPER_SAMPLE_RATE};
+use audio_recorder::clustering::ClusteringMethod;
+use audio_recorder::devices::print_devices;
+use audio_recorder::decoding::DecodingOptions;
+use audio_recorder::filter::FilterOptions;
+use audio_recorder::diarization::extract_voice_embedding;
+use audio_recorder::live::{record_and_transcribe_live, stop_on_enter, LiveOptions};
+use audio_recorder::output::{self, FormatOptions, OutputFormat};
+use audio_recorder::profiles::{ProfileStore, DEFAULT_PROFILES_DIR};
+use audio_recorder::resample::ResampleQuality;
+use audio_recorder::source::{
+    AudioSource, MicrophoneSource, RawPcmSource, RawSampleFormat, Realtime, SelectChannel,
+};
+use audio_recorder::vad::VadOptions;
+use audio_recorder::vocabulary::Vocabulary;
+use audio_recorder::{DiarizationOptions, FileSource, Task, Transcriber, TranscriberOptions};
+use clap::{Parser, Subcommand};
 use std::fs::File;
-use std::io::{self, Write};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use symphonia::core::audio::{AudioBuffer, Signal};
-use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
-use symphonia::core::meta::MetadataOptions;
-use symphonia::core::probe::Hint;
-use whisper_rs::{FullParams, WhisperContext, WhisperContextParameters};
-use pyannote_rs::{get_segments, Segment};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(name = "audio-recorder")]
 #[command(about = "Transcribe audio files or live microphone input to text for meeting minutes", long_about = None)]
 struct Args {
-    /// Path to the audio file to transcribe (omit for live recording)
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the audio file to transcribe, or - to read from stdin (omit
+    /// for live recording)
     #[arg(short, long)]
     input: Option<PathBuf>,
 
-    /// Record from microphone instead of transcribing a file
+    #[command(flatten)]
+    input_options: InputArgs,
+
+    /// Record from microphone instead of transcribing a file. With --input,
+    /// replay the file at real-time speed through live transcription
     #[arg(short, long)]
     live: bool,
 
-    /// Path to the output text file
+    /// Path to the output file (default: input name with the format's extension)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Txt)]
+    format: OutputFormat,
+
+    /// Maximum characters per subtitle line (srt/vtt)
+    #[arg(long, default_value = "42")]
+    max_line_length: usize,
+
+    /// Maximum lines per subtitle cue (srt/vtt)
+    #[arg(long, default_value = "2")]
+    max_lines: usize,
+
     /// Path to the Whisper model file (default: ./models/ggml-base.en.bin)
     #[arg(short, long, default_value = "./models/ggml-base.en.bin")]
     model: PathBuf,
@@ -38,785 +66,570 @@ struct Args {
     #[arg(short, long)]
     language: Option<String>,
 
+    /// Languages auto-detection may choose from, e.g. "pt,en"
+    #[arg(long, value_delimiter = ',', conflicts_with = "language")]
+    languages: Vec<String>,
+
+    /// Detect the language of every stretch of speech and transcribe each
+    /// in its own language, for meetings that switch (implies --vad)
+    #[arg(long, conflicts_with = "language")]
+    code_switching: bool,
+
+    /// Translate the speech to English instead of transcribing it (needs a
+    /// multilingual model, not a .en one)
+    #[arg(long)]
+    translate: bool,
+
+    /// Transcribe in the spoken language and add the English translation
+    /// to every segment
+    #[arg(long, conflicts_with = "translate")]
+    bilingual: bool,
+
+    /// Vocabulary file of terms to prompt Whisper with and correct
+    /// misrecognitions of; repeat to combine, e.g. shared and per-project
+    #[arg(long, value_name = "FILE")]
+    vocabulary: Vec<PathBuf>,
+
+    /// Transcribe long files in this many chunks at once, split at pauses
+    #[arg(short, long, default_value = "1")]
+    jobs: usize,
+
     /// Chunk size in seconds for live transcription (default: 5)
     #[arg(short = 'c', long, default_value = "5")]
     chunk_seconds: u64,
 
-    /// Enable speaker diarization (identify different speakers)
-    #[arg(short = 's', long)]
-    speaker_diarization: bool,
-}
+    /// Include word-level timestamps and confidence in JSON output
+    #[arg(long)]
+    word_timestamps: bool,
 
-fn resolve_model_path(path: &PathBuf) -> Result<PathBuf> {
-    // If path is absolute and exists, use it
-    if path.is_absolute() && path.exists() {
-        return Ok(path.clone());
-    }
+    /// Seconds of audio shared between consecutive live windows (0 for none)
+    #[arg(long, default_value = "1.0")]
+    overlap_seconds: f32,
 
-    // If path is relative and exists in current directory, use it
-    if path.exists() {
-        return Ok(path.canonicalize()?);
-    }
+    /// Audio host to record with, e.g. alsa or jack (default: system default)
+    #[arg(long)]
+    host: Option<String>,
 
-    // Try relative to current directory
-    let current_dir = std::env::current_dir()?;
-    let relative_path = current_dir.join(path);
-    if relative_path.exists() {
-        return Ok(relative_path.canonicalize()?);
-    }
+    /// Input device to record from, by index or name as shown by `devices`
+    /// (default: system default)
+    #[arg(long)]
+    device: Option<String>,
 
-    // Try relative to project root (look for Cargo.toml)
-    let mut search_dir = current_dir.clone();
-    loop {
-        let cargo_toml = search_dir.join("Cargo.toml");
-        if cargo_toml.exists() {
-            let project_path = search_dir.join(path);
-            if project_path.exists() {
-                return Ok(project_path.canonicalize()?);
-            }
-            break;
-        }
-        match search_dir.parent() {
-            Some(parent) => search_dir = parent.to_path_buf(),
-            None => break,
-        }
-    }
+    /// Also save the live recording to this WAV or FLAC file, at the
+    /// input's sample rate and channels, in 16 bits for 16-bit input and 24
+    /// bits for anything finer
+    #[arg(long)]
+    save_audio: Option<PathBuf>,
 
-    // If still not found, return original path with helpful error
-    anyhow::bail!(
-        "Model file not found: {}\n\
-         Searched in:\n\
-         - {}\n\
-         - {}\n\
-         - Project root (where Cargo.toml is located)\n\
-         Please ensure the model file exists or provide an absolute path.\n\
-         Current directory: {}",
-        path.display(),
-        path.display(),
-        relative_path.display(),
-        current_dir.display()
-    )
-}
+    /// After live recording, re-transcribe the saved audio (--save-audio) in
+    /// one pass into a separate "final" transcript, with speaker diarization
+    /// if enabled
+    #[arg(long, requires = "save_audio")]
+    refine: bool,
 
-fn load_audio_file(path: &PathBuf) -> Result<Vec<f32>> {
-    println!("Loading audio file: {}", path.display());
+    /// Whisper model for --refine, e.g. a larger one (default: --model)
+    #[arg(long, requires = "refine")]
+    refine_model: Option<PathBuf>,
 
-    // Open the media source
-    let src = File::open(path)
-        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
-    let mss = MediaSourceStream::new(Box::new(src), Default::default());
+    #[command(flatten)]
+    vad: VadArgs,
 
-    // Create a probe hint using the file extension
-    let mut hint = Hint::new();
-    if let Some(extension) = path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            hint.with_extension(ext_str);
-        }
-    }
+    #[command(flatten)]
+    decoding: DecodingArgs,
 
-    // Use the default probe to identify the format
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts: FormatOptions = Default::default();
-
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .with_context(|| "Failed to probe audio format")?;
-
-    let mut format = probed.format;
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .with_context(|| "No supported audio tracks found")?;
-
-    let track_id = track.id;
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &DecoderOptions::default())
-        .with_context(|| "Failed to create decoder")?;
-
-    let sample_rate = track.codec_params.sample_rate
-        .with_context(|| "Sample rate not specified")?;
-    println!("Sample rate: {} Hz", sample_rate);
-
-    // Decode all samples
-    let mut samples = Vec::new();
-    let mut frame_count = 0;
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(_) => break,
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
+    #[command(flatten)]
+    filter: FilterArgs,
 
-        match decoder.decode(&packet) {
-            Ok(decoded) => {
-                // Convert decoded buffer to f32
-                let spec = *decoded.spec();
-                let duration = decoded.capacity() as u64;
-                let mut audio_buf_f32: AudioBuffer<f32> = AudioBuffer::new(duration, spec);
-
-                decoded.convert(&mut audio_buf_f32);
-
-                // Convert to mono f32 samples
-                let channels = audio_buf_f32.spec().channels.count();
-                let planes = audio_buf_f32.planes();
-                let plane_slices = planes.planes();
-                let buf_frames = audio_buf_f32.frames();
-                
-                for i in 0..buf_frames {
-                    let mut sum = 0.0;
-                    for ch in 0..channels {
-                        sum += plane_slices[ch][i];
-                    }
-                    samples.push(sum / channels as f32);
-                }
+    /// Don't prompt each live window with the previously transcribed text
+    #[arg(long)]
+    no_carry_prompt: bool,
 
-                frame_count += 1;
-                if frame_count % 100 == 0 {
-                    print!("\rDecoded {} frames...", frame_count);
-                    std::io::stdout().flush().unwrap();
-                }
-            }
-            Err(e) => {
-                eprintln!("\nDecode error: {:?}", e);
-                break;
-            }
-        }
-    }
+    /// Enable speaker diarization (identify different speakers)
+    #[arg(short = 's', long)]
+    speaker_diarization: bool,
 
-    println!("\rDecoded {} frames, {} samples", frame_count, samples.len());
+    /// Exact number of speakers, if known (speaker diarization)
+    #[arg(long, conflicts_with_all = ["min_speakers", "max_speakers"])]
+    num_speakers: Option<usize>,
 
-    // Resample to 16kHz if needed (Whisper expects 16kHz)
-    if sample_rate != 16000 {
-        println!("Resampling from {} Hz to 16000 Hz...", sample_rate);
-        samples = resample(&samples, sample_rate, 16000);
-    }
+    /// Minimum number of speakers (speaker diarization)
+    #[arg(long)]
+    min_speakers: Option<usize>,
 
-    Ok(samples)
-}
+    /// Maximum number of speakers (speaker diarization)
+    #[arg(long)]
+    max_speakers: Option<usize>,
 
-fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
-    if from_rate == to_rate {
-        return samples.to_vec();
-    }
+    /// How voices are grouped into speakers (speaker diarization)
+    #[arg(long, value_enum, default_value_t = ClusteringMethod::Agglomerative)]
+    clustering: ClusteringMethod,
 
-    let ratio = to_rate as f64 / from_rate as f64;
-    let new_len = (samples.len() as f64 * ratio) as usize;
-    let mut resampled = Vec::with_capacity(new_len);
-
-    for i in 0..new_len {
-        let src_pos = i as f64 / ratio;
-        let src_idx = src_pos as usize;
-        let frac = src_pos - src_idx as f64;
-
-        if src_idx + 1 < samples.len() {
-            // Linear interpolation
-            let sample = samples[src_idx] as f64 * (1.0 - frac) + samples[src_idx + 1] as f64 * frac;
-            resampled.push(sample as f32);
-        } else if src_idx < samples.len() {
-            resampled.push(samples[src_idx]);
-        }
-    }
+    /// Cosine distance above which voices count as different speakers
+    /// (agglomerative clustering)
+    #[arg(long, default_value = "0.7")]
+    cluster_threshold: f32,
+
+    /// Split segments where the speaker changes mid-sentence (speaker diarization)
+    #[arg(long)]
+    split_on_speaker_change: bool,
+
+    /// Path to the speaker embedding model (default: searched in ./models)
+    #[arg(long, global = true)]
+    embedding_model: Option<PathBuf>,
 
-    resampled
+    /// Directory of enrolled voice profiles used to name speakers
+    #[arg(long, global = true, default_value = DEFAULT_PROFILES_DIR)]
+    profiles: PathBuf,
+
+    /// Minimum cosine similarity for a speaker to match an enrolled profile
+    #[arg(long, default_value = "0.5")]
+    profile_threshold: f32,
 }
 
-fn find_pyannote_model() -> Result<PathBuf> {
-    let models_dir = PathBuf::from("models");
-    
-    // Try different possible model file names/locations
-    let possible_paths = vec![
-        models_dir.join("segmentation.onnx"),
-        models_dir.join("segmentation-3.0.onnx"),
-        models_dir.join("pyannote-segmentation.onnx"),
-        PathBuf::from("segmentation.onnx"),
-    ];
-    
-    for path in &possible_paths {
-        if path.exists() {
-            println!("Found model file: {}", path.display());
-            return Ok(path.clone());
-        }
-    }
-    
-    // Model not found - provide helpful error message
-    anyhow::bail!(
-        "Speaker diarization model not found.\n\
-         \n\
-         To enable speaker diarization, you need to download the model:\n\
-         \n\
-         1. Visit: https://huggingface.co/pyannote/segmentation-3.0\n\
-         2. Accept the model terms (you may need a Hugging Face account)\n\
-         3. Download the ONNX model file\n\
-         4. Place it in the 'models' directory with one of these names:\n\
-            - segmentation.onnx\n\
-            - segmentation-3.0.onnx\n\
-            - pyannote-segmentation.onnx\n\
-         \n\
-         Note: The model file is typically ~50-100MB.\n\
-         \n\
-         Alternatively, you can run without speaker diarization by removing the --speaker-diarization flag."
-    )
+/// How the input is read.
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// Treat the input as headerless PCM samples in this encoding
+    #[arg(long, value_enum, requires = "input")]
+    raw_format: Option<RawSampleFormat>,
+
+    /// Sample rate of raw PCM input, in Hz
+    #[arg(long, default_value = "16000", requires = "raw_format")]
+    raw_rate: u32,
+
+    /// Number of interleaved channels in raw PCM input
+    #[arg(long, default_value = "1", requires = "raw_format")]
+    raw_channels: u16,
+
+    /// Decode the track with this id instead of the first audio track of
+    /// the input (the error for an unknown id lists the tracks)
+    #[arg(long, requires = "input", conflicts_with = "raw_format")]
+    track: Option<u32>,
+
+    /// Transcribe only this channel of the input or microphone, counting
+    /// from 0 (0 is left in stereo) instead of mixing all channels
+    #[arg(long)]
+    channel: Option<u16>,
+
+    /// Transcribe each channel of the input separately and label it as its
+    /// own speaker, e.g. agent and customer on a stereo call recording
+    #[arg(long, requires = "input", conflicts_with_all = ["channel", "live", "speaker_diarization"])]
+    channels_as_speakers: bool,
+
+    /// How carefully audio at other sample rates is converted to 16 kHz
+    #[arg(long, value_enum, default_value_t = ResampleQuality::Medium)]
+    resample_quality: ResampleQuality,
 }
 
-fn perform_speaker_diarization(audio_samples: &[f32], sample_rate: u32) -> Result<Vec<(f64, f64, usize)>> {
-    println!("Performing speaker diarization...");
-    println!("Audio length: {:.2} seconds, {} samples at {} Hz", 
-             audio_samples.len() as f64 / sample_rate as f64,
-             audio_samples.len(),
-             sample_rate);
-    
-    // Convert f32 samples to i16 for pyannote-rs
-    let samples_i16: Vec<i16> = audio_samples
-        .iter()
-        .map(|&s| (s * 32767.0).clamp(-32768.0, 32767.0) as i16)
-        .collect();
-    
-    println!("Converted to i16 format: {} samples", samples_i16.len());
-    
-    // Find the model file
-    let model_path = find_pyannote_model()?;
-    
-    println!("Using model: {}", model_path.display());
-    
-    // Get speech segments
-    let segments_iter = get_segments(&samples_i16, sample_rate, &model_path)
-        .map_err(|e| anyhow::anyhow!("Failed to get segments: {}", e))?;
-    
-    let mut segments: Vec<Segment> = Vec::new();
-    let mut segment_count = 0;
-    for segment_result in segments_iter {
-        match segment_result {
-            Ok(segment) => {
-                segments.push(segment);
-                segment_count += 1;
-                if segment_count % 10 == 0 {
-                    print!("\r  Processed {} segments...", segment_count);
-                    std::io::stdout().flush().unwrap();
-                }
-            }
-            Err(e) => {
-                eprintln!("\nSegment processing error: {}", e);
-                // Continue processing other segments
-            }
-        }
-    }
-    println!("\r  Processed {} segments total", segment_count);
-    
-    if segments.is_empty() {
-        anyhow::bail!("No speech segments found - the audio may not contain speech or the model failed to process it");
-    }
-    
-    println!("Successfully extracted {} speech segments", segments.len());
-    
-    // For now, assign sequential speaker IDs to segments
-    // TODO: Implement proper speaker embedding extraction and clustering
-    // This is a simplified version - full implementation would use EmbeddingExtractor
-    let mut speaker_segments = Vec::new();
-    let mut current_speaker = 0;
-    let mut last_end = 0.0;
-    
-    for segment in &segments {
-        // Simple heuristic: if there's a gap > 1 second, assume new speaker
-        if segment.start - last_end > 1.0 && current_speaker < 10 {
-            current_speaker += 1;
-        }
-        speaker_segments.push((segment.start, segment.end, current_speaker));
-        last_end = segment.end;
-    }
-    
-    let unique_speakers = speaker_segments.iter().map(|(_, _, s)| s).max().unwrap_or(&0) + 1;
-    println!("Found {} speech segments, identified {} potential speakers", 
-             speaker_segments.len(), unique_speakers);
-    println!("Note: Full speaker diarization with embedding clustering is in development");
-    
-    Ok(speaker_segments)
+/// Voice activity detection.
+#[derive(clap::Args, Debug)]
+struct VadArgs {
+    /// Transcribe only the speech found by voice activity detection,
+    /// skipping silence; live windows then end at pauses
+    #[arg(long)]
+    vad: bool,
+
+    /// Detect speech with this Silero VAD ONNX model instead of by energy
+    /// (implies --vad)
+    #[arg(long)]
+    vad_model: Option<PathBuf>,
+
+    /// Speech probability from which audio counts as speech (0.0 to 1.0)
+    #[arg(long, default_value = "0.5")]
+    vad_threshold: f32,
+
+    /// Ignore bursts of sound shorter than this many milliseconds
+    #[arg(long, default_value = "250")]
+    min_speech_ms: u32,
+
+    /// Pauses shorter than this many milliseconds don't end speech
+    #[arg(long, default_value = "500")]
+    min_silence_ms: u32,
+
+    /// Milliseconds of audio kept either side of each stretch of speech
+    #[arg(long, default_value = "200")]
+    speech_pad_ms: u32,
 }
 
-fn find_speaker_for_timestamp(segments: &[(f64, f64, usize)], timestamp_sec: f64) -> Option<usize> {
-    // Find the segment that contains this timestamp
-    // Use a small tolerance for matching (0.1 seconds)
-    for (start, end, speaker) in segments {
-        if timestamp_sec >= *start - 0.1 && timestamp_sec <= *end + 0.1 {
-            return Some(*speaker);
-        }
+impl VadArgs {
+    // `implied` by an option that needs the speech found
+    fn options(self, implied: bool) -> Option<VadOptions> {
+        (self.vad || self.vad_model.is_some() || implied).then_some(VadOptions {
+            threshold: self.vad_threshold,
+            min_speech_ms: self.min_speech_ms,
+            min_silence_ms: self.min_silence_ms,
+            speech_pad_ms: self.speech_pad_ms,
+            model: self.vad_model,
+        })
     }
-    None
 }
 
-fn transcribe_audio(
-    model_path: &PathBuf, 
-    audio_samples: &[f32], 
-    language: Option<String>,
-    enable_diarization: bool,
-) -> Result<String> {
-    let resolved_path = resolve_model_path(model_path)?;
-    println!("Loading Whisper model: {}", resolved_path.display());
-    let ctx_params = WhisperContextParameters::default();
-    let ctx = WhisperContext::new_with_params(
-        resolved_path.to_str().unwrap(),
-        ctx_params
-    )
-    .with_context(|| format!("Failed to load Whisper model from {}", resolved_path.display()))?;
-
-    println!("Initializing transcription...");
-    let mut state = ctx.create_state()
-        .context("Failed to create Whisper state")?;
-
-    let mut params = FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
-    
-    // Set language if provided
-    if let Some(ref lang) = language {
-        params.set_language(Some(lang.as_str()));
-    } else {
-        params.set_language(None); // Auto-detect
-    }
+/// Whisper decoding.
+#[derive(clap::Args, Debug)]
+struct DecodingArgs {
+    /// Decode with beam search keeping this many candidates, which is
+    /// slower but more accurate (default: greedy)
+    #[arg(long)]
+    beam_size: Option<usize>,
+
+    /// Candidates to sample when decoding greedily above temperature 0
+    #[arg(long, default_value = "1", conflicts_with = "beam_size")]
+    best_of: usize,
+
+    /// Sampling temperature of the first decode (0.0 to 1.0)
+    #[arg(long, default_value = "0.0")]
+    temperature: f32,
+
+    /// Temperature step for decoding unsure text again, up to 1.0 (0 to
+    /// never decode again)
+    #[arg(long, default_value = "0.2")]
+    temperature_increment: f32,
+
+    /// Threads to decode with (default: up to 4)
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// Tokens of earlier text Whisper is conditioned on (0 for none,
+    /// default: as much as fits)
+    #[arg(long)]
+    max_text_context: Option<usize>,
+
+    /// Token entropy above which a window is decoded again
+    #[arg(long, default_value = "2.4")]
+    entropy_threshold: f32,
+
+    /// Mean token log-probability below which text is decoded again
+    #[arg(long, default_value = "-1.0", allow_hyphen_values = true)]
+    logprob_threshold: f32,
+
+    /// No-speech probability above which unsure text is dropped as silence
+    #[arg(long, default_value = "0.6")]
+    no_speech_threshold: f32,
+
+    /// Text to prompt Whisper with, e.g. names and terms that come up or
+    /// the punctuation style to follow
+    #[arg(long)]
+    initial_prompt: Option<String>,
+}
 
-    params.set_translate(false);
-    params.set_print_progress(true);
-    params.set_print_special(false);
-    params.set_print_realtime(false);
-    params.set_suppress_blank(true);
-    params.set_suppress_non_speech_tokens(false);
-    params.set_single_segment(false);
-
-    println!("Transcribing audio (this may take a while)...");
-    state.full(params, audio_samples)
-        .context("Transcription failed")?;
-
-    // Perform speaker diarization if enabled
-    let speaker_segments = if enable_diarization {
-        println!("Speaker diarization is enabled");
-        match perform_speaker_diarization(audio_samples, 16000) {
-            Ok(segments) => {
-                println!("Speaker diarization completed successfully. Found {} speaker segments.", segments.len());
-                Some(segments)
-            },
-            Err(e) => {
-                eprintln!("Warning: Speaker diarization failed: {}", e);
-                eprintln!("Continuing without speaker identification...");
-                None
-            }
+impl DecodingArgs {
+    fn options(self) -> DecodingOptions {
+        DecodingOptions {
+            beam_size: self.beam_size,
+            best_of: self.best_of,
+            temperature: self.temperature,
+            temperature_increment: self.temperature_increment,
+            threads: self.threads,
+            max_text_context: self.max_text_context,
+            entropy_threshold: self.entropy_threshold,
+            logprob_threshold: self.logprob_threshold,
+            no_speech_threshold: self.no_speech_threshold,
+            initial_prompt: self.initial_prompt,
         }
-    } else {
-        None
-    };
-
-    // Extract the transcription
-    let num_segments = state.full_n_segments()
-        .context("Failed to get number of segments")?;
-    
-    let mut transcript = String::new();
-    for i in 0..num_segments {
-        let segment = state.full_get_segment_text(i)
-            .context("Failed to get segment text")?;
-        let start_timestamp = state.full_get_segment_t0(i)
-            .context("Failed to get segment start time")?;
-        let end_timestamp = state.full_get_segment_t1(i)
-            .context("Failed to get segment end time")?;
-
-        let start_sec = start_timestamp as f64 / 100.0;
-        let end_sec = end_timestamp as f64 / 100.0;
-        let start_min = (start_sec as u64) / 60;
-        let start_sec_remainder = (start_sec as u64) % 60;
-        let end_min = (end_sec as u64) / 60;
-        let end_sec_remainder = (end_sec as u64) % 60;
-
-        // Find speaker for this segment (use middle of segment)
-        let speaker_label = if let Some(ref segments) = speaker_segments {
-            let mid_time = (start_sec + end_sec) / 2.0;
-            // Try to find speaker for start, middle, or end of segment
-            let speaker_id = find_speaker_for_timestamp(segments, start_sec)
-                .or_else(|| find_speaker_for_timestamp(segments, mid_time))
-                .or_else(|| find_speaker_for_timestamp(segments, end_sec));
-            
-            if let Some(sid) = speaker_id {
-                format!("Speaker {}: ", sid + 1)
-            } else {
-                String::new()
-            }
-        } else {
-            String::new()
-        };
-
-        transcript.push_str(&format!(
-            "[{:02}:{:02} - {:02}:{:02}] {}{}\n",
-            start_min, start_sec_remainder, end_min, end_sec_remainder, 
-            speaker_label, segment.trim()
-        ));
     }
+}
 
-    Ok(transcript)
+/// Hallucination filtering.
+#[derive(clap::Args, Debug)]
+struct FilterArgs {
+    /// Keep Whisper's output as it is, without decoding suspect segments
+    /// again or dropping hallucinations
+    #[arg(long)]
+    no_filter: bool,
+
+    /// Compression ratio of a segment's text above which it is decoded again
+    #[arg(long, default_value = "2.4")]
+    compression_ratio_threshold: f32,
+
+    /// Repetitions in a row of a phrase or segment that count as a loop
+    #[arg(long, default_value = "4")]
+    repetition_threshold: usize,
 }
 
-fn record_and_transcribe_live(
-    model_path: &PathBuf,
-    output_path: &PathBuf,
-    language: Option<String>,
-    chunk_seconds: u64,
-) -> Result<()> {
-    println!("=== Live Recording & Transcription ===");
-    
-    // Resolve and load Whisper model
-    let resolved_path = resolve_model_path(model_path)?;
-    println!("Loading Whisper model: {}", resolved_path.display());
-    let ctx_params = WhisperContextParameters::default();
-    let ctx = WhisperContext::new_with_params(
-        resolved_path.to_str().unwrap(),
-        ctx_params
-    )
-    .with_context(|| format!("Failed to load Whisper model from {}", resolved_path.display()))?;
-    
-    // Create a second context for final processing (WhisperContext can't be cloned)
-    let ctx_params_final = WhisperContextParameters::default();
-    let ctx_final = WhisperContext::new_with_params(
-        resolved_path.to_str().unwrap(),
-        ctx_params_final
-    )
-    .with_context(|| format!("Failed to load Whisper model from {}", resolved_path.display()))?;
-
-    // Setup audio input
-    let host = cpal::default_host();
-    let input_device = host
-        .default_input_device()
-        .context("No input device available")?;
-
-    println!("Recording from: {}", input_device.name()?);
-
-    // Get supported config
-    let mut supported_configs = input_device.supported_input_configs()?;
-    let config = supported_configs
-        .next()
-        .context("No supported config")?
-        .with_max_sample_rate()
-        .config();
-
-    println!("Using config: {:?}", config);
-    println!("Sample rate: {} Hz", config.sample_rate.0);
-
-    // Prepare output file
-    let file = File::create(output_path)
-        .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
-    let file_clone = Arc::new(Mutex::new(file));
-    
-    {
-        let mut file = file_clone.lock().unwrap();
-        writeln!(file, "Meeting Minutes - Live Transcription")
-            .context("Failed to write to output file")?;
-        writeln!(file, "Started: {}\n", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))
-            .context("Failed to write to output file")?;
+impl FilterArgs {
+    // The thresholds Whisper decodes with also judge its segments
+    fn options(self, decoding: &DecodingOptions) -> Option<FilterOptions> {
+        (!self.no_filter).then_some(FilterOptions {
+            no_speech_threshold: decoding.no_speech_threshold,
+            logprob_threshold: decoding.logprob_threshold,
+            compression_ratio_threshold: self.compression_ratio_threshold,
+            repetition_threshold: self.repetition_threshold,
+            temperature_increment: decoding.temperature_increment,
+        })
     }
+}
 
-    // Audio buffer for collecting samples
-    let audio_buffer = Arc::new(Mutex::new(Vec::<f32>::new()));
-    let audio_buffer_clone = audio_buffer.clone();
-    let recording = Arc::new(std::sync::atomic::AtomicBool::new(true));
-    let recording_clone = recording.clone();
-
-    // Calculate chunk size in samples (16kHz)
-    let chunk_size_samples = (chunk_seconds * 16000) as usize;
-    let sample_rate = config.sample_rate.0;
-
-    println!("\nRecording... Press Enter to stop.\n");
-    println!("Transcribing in {} second chunks...\n", chunk_seconds);
-
-    // Build input stream
-    let channels = config.channels as usize;
-    let stream = input_device.build_input_stream(
-        &config.into(),
-        move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            if recording_clone.load(std::sync::atomic::Ordering::Relaxed) {
-                if let Ok(mut buffer) = audio_buffer_clone.lock() {
-                    // Convert to mono if stereo, and resample if needed
-                    for chunk in data.chunks(channels) {
-                        let mut sum = 0.0;
-                        for &sample in chunk {
-                            sum += sample;
-                        }
-                        let mono_sample = sum / channels as f32;
-                        buffer.push(mono_sample);
-                    }
-                }
-            }
-        },
-        move |err| eprintln!("Audio stream error: {}", err),
-        None,
-    )?;
-
-    stream.play()?;
-
-    // Start a thread for periodic transcription
-    let ctx_clone = Arc::new(ctx);
-    let language_clone = language.clone();
-    let file_clone_transcription = file_clone.clone();
-    let recording_transcription = recording.clone();
-    let audio_buffer_transcription = audio_buffer.clone();
-    
-    let transcription_handle = std::thread::spawn(move || {
-        let mut last_processed = 0;
-        let mut segment_counter = 0;
-
-        loop {
-            std::thread::sleep(std::time::Duration::from_secs(chunk_seconds));
-            
-            if !recording_transcription.load(std::sync::atomic::Ordering::Relaxed) {
-                break;
-            }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Enroll a person's voice from reference clips so diarization can
+    /// label them by name
+    Enroll {
+        /// Name to label this person with in transcripts
+        #[arg(short, long)]
+        name: String,
+
+        /// Audio clips containing only this person speaking
+        #[arg(required = true)]
+        clips: Vec<PathBuf>,
+    },
+    /// List audio hosts and input devices with their supported configs
+    Devices,
+}
 
-            let samples_to_process = {
-                let buffer = audio_buffer_transcription.lock().unwrap();
-                if buffer.len() - last_processed < chunk_size_samples {
-                    continue;
-                }
-                buffer[last_processed..].to_vec()
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Some(Command::Enroll { name, clips }) => {
+            let options = DiarizationOptions {
+                embedding_model: args.embedding_model,
+                ..Default::default()
             };
+            return enroll(&name, &clips, &args.profiles, &options);
+        }
+        Some(Command::Devices) => return print_devices(),
+        None => {}
+    }
 
-            if samples_to_process.is_empty() {
-                continue;
-            }
+    let decoding = args.decoding.options();
+    let vocabulary = load_vocabulary(&args.vocabulary)?;
+    let task = if args.bilingual {
+        Task::Bilingual
+    } else if args.translate {
+        Task::Translate
+    } else {
+        Task::Transcribe
+    };
+    let options = TranscriberOptions {
+        language: args.language,
+        languages: args.languages,
+        code_switching: args.code_switching,
+        task,
+        jobs: args.jobs,
+        speaker_diarization: args.speaker_diarization,
+        diarization: DiarizationOptions {
+            clustering: args.clustering,
+            threshold: args.cluster_threshold,
+            num_speakers: args.num_speakers,
+            min_speakers: args.min_speakers,
+            max_speakers: args.max_speakers,
+            embedding_model: args.embedding_model,
+            profiles_dir: Some(args.profiles),
+            profile_threshold: args.profile_threshold,
+            split_on_speaker_change: args.split_on_speaker_change,
+            ..Default::default()
+        },
+        print_progress: true,
+        resample_quality: args.input_options.resample_quality,
+        word_timestamps: args.word_timestamps,
+        vad: args.vad.options(args.code_switching),
+        filter: args.filter.options(&decoding),
+        decoding,
+        vocabulary,
+    };
+    let format_options = FormatOptions {
+        max_line_length: args.max_line_length,
+        max_lines: args.max_lines,
+    };
 
-            // Resample to 16kHz if needed
-            let samples_16k = if sample_rate != 16000 {
-                resample(&samples_to_process, sample_rate, 16000)
-            } else {
-                samples_to_process
+    // Check if we're doing live recording or file transcription
+    match args.input {
+        Some(input_path) if !args.live => {
+            // File transcription mode
+            let output_path = args.output.unwrap_or_else(|| {
+                if is_stdin(&input_path) {
+                    timestamped_output_path("transcription", args.format)
+                } else {
+                    let mut output = input_path.clone();
+                    output.set_extension(args.format.extension());
+                    output
+                }
+            });
+
+            println!("=== Audio Transcription Tool ===");
+            println!("Input: {}", input_path.display());
+            println!("Output: {}", output_path.display());
+            println!();
+
+            let mut source = open_input(&input_path, &args.input_options)?;
+            let transcriber = Transcriber::new(&args.model, options)?;
+            transcribe_file(
+                &transcriber,
+                source.as_mut(),
+                args.input_options.channels_as_speakers,
+                &output_path,
+                args.format,
+                &format_options,
+            )
+        }
+        _ => {
+            // Live recording mode
+            let output_path = args.output
+                .unwrap_or_else(|| timestamped_output_path("live_transcription", args.format));
+
+            let transcriber = Transcriber::new(&args.model, options.clone())?;
+            let live_options = LiveOptions {
+                chunk_seconds: args.chunk_seconds,
+                overlap_seconds: args.overlap_seconds,
+                carry_prompt: !args.no_carry_prompt,
+                save_audio: args.save_audio.clone(),
+                format: args.format,
+                format_options: format_options.clone(),
             };
-
-            // Transcribe chunk
-            let mut state = match ctx_clone.create_state() {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Failed to create Whisper state: {}", e);
-                    continue;
+            // A pipe delivers audio as it is produced; a file is replayed
+            // at real-time speed
+            let from_stdin = args.input.as_deref().is_some_and(is_stdin);
+            let mut source: Box<dyn AudioSource> = match args.input {
+                Some(input_path) => {
+                    let source = open_input(&input_path, &args.input_options)?;
+                    if from_stdin { source } else { Box::new(Realtime::new(source)) }
                 }
+                None => select_channel(
+                    Box::new(MicrophoneSource::new(args.host, args.device)),
+                    args.input_options.channel,
+                ),
             };
-
-            let mut params = FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
-            
-            if let Some(ref lang) = language_clone {
-                params.set_language(Some(lang.as_str()));
+            // Stdin carries the audio, so the stream ending stops recording
+            let stop = if from_stdin {
+                Arc::new(AtomicBool::new(false))
             } else {
-                params.set_language(None);
-            }
-
-            params.set_translate(false);
-            params.set_print_progress(false);
-            params.set_print_special(false);
-            params.set_print_realtime(false);
-            params.set_suppress_blank(true);
-            params.set_suppress_non_speech_tokens(false);
-            params.set_single_segment(false);
-
-            if let Err(e) = state.full(params, &samples_16k) {
-                eprintln!("Transcription error: {}", e);
-                continue;
-            }
-
-            // Extract and print transcription
-            let num_segments = match state.full_n_segments() {
-                Ok(n) => n,
-                Err(_) => continue,
+                println!("Press Enter to stop recording.\n");
+                stop_on_enter()
             };
-
-            for i in 0..num_segments {
-                let segment = match state.full_get_segment_text(i) {
-                    Ok(s) => s,
-                    Err(_) => continue,
-                };
-                
-                let start_timestamp = match state.full_get_segment_t0(i) {
-                    Ok(t) => t,
-                    Err(_) => continue,
-                };
-                
-                let end_timestamp = match state.full_get_segment_t1(i) {
-                    Ok(t) => t,
-                    Err(_) => continue,
-                };
-
-                let start_sec_total = start_timestamp / 100;
-                let end_sec_total = end_timestamp / 100;
-                let start_min_total = start_sec_total / 60;
-                let start_sec_remainder = start_sec_total % 60;
-                let end_min_total = end_sec_total / 60;
-                let end_sec_remainder = end_sec_total % 60;
-
-                let total_start_sec = (segment_counter * chunk_seconds) as i64 + start_sec_total as i64;
-                let total_end_sec = (segment_counter * chunk_seconds) as i64 + end_sec_total as i64;
-                let final_start_min = total_start_sec / 60;
-                let final_start_sec = total_start_sec % 60;
-                let final_end_min = total_end_sec / 60;
-                let final_end_sec = total_end_sec % 60;
-
-                let transcript_line = format!(
-                    "[{:02}:{:02} - {:02}:{:02}] {}\n",
-                    final_start_min, final_start_sec, final_end_min, final_end_sec,
-                    segment.trim()
-                );
-
-                print!("{}", transcript_line);
-                io::stdout().flush().unwrap();
-
-                if let Ok(mut file) = file_clone_transcription.lock() {
-                    let _ = file.write_all(transcript_line.as_bytes());
+            record_and_transcribe_live(&transcriber, source.as_mut(), &output_path, &live_options, &stop)?;
+
+            if let (true, Some(audio_path)) = (args.refine, args.save_audio) {
+                println!("\n=== Refining transcript from: {} ===", audio_path.display());
+                let final_path = final_output_path(&output_path);
+                match args.refine_model {
+                    Some(ref model) => {
+                        drop(transcriber);
+                        let transcriber = Transcriber::new(model, options)?;
+                        transcribe_file(&transcriber, &mut FileSource::new(&audio_path), false, &final_path, args.format, &format_options)?;
+                    }
+                    None => {
+                        transcribe_file(&transcriber, &mut FileSource::new(&audio_path), false, &final_path, args.format, &format_options)?;
+                    }
                 }
             }
-
-            segment_counter += 1;
-            last_processed += samples_16k.len();
+            Ok(())
         }
-    });
+    }
+}
 
-    // Wait for user to press Enter
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+// Transcribe a whole source, each channel separately with
+// `channels_as_speakers`, and write the transcript to `output_path`
+fn transcribe_file(
+    transcriber: &Transcriber,
+    source: &mut dyn AudioSource,
+    channels_as_speakers: bool,
+    output_path: &Path,
+    format: OutputFormat,
+    format_options: &FormatOptions,
+) -> Result<()> {
+    let transcript = if channels_as_speakers {
+        transcriber.transcribe_channels(source)?
+    } else {
+        transcriber.transcribe_source(source)?
+    };
 
-    // Stop recording
-    recording.store(false, std::sync::atomic::Ordering::Relaxed);
-    drop(stream);
+    // Save transcription to file
+    println!("\nSaving transcription to: {}", output_path.display());
+    let file = File::create(output_path)
+        .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
 
-    // Process remaining audio
-    println!("\nProcessing remaining audio...");
-    let remaining_samples = {
-        let buffer = audio_buffer.lock().unwrap();
-        buffer.clone()
-    };
+    output::write_transcript(file, &transcript, format, format_options)
+        .context("Failed to write to output file")?;
 
-    if !remaining_samples.is_empty() {
-        let samples_16k = if sample_rate != 16000 {
-            resample(&remaining_samples, sample_rate, 16000)
-        } else {
-            remaining_samples
-        };
-
-        let mut state = ctx_final.create_state()
-            .context("Failed to create Whisper state")?;
-
-        let mut params = FullParams::new(whisper_rs::SamplingStrategy::Greedy { best_of: 1 });
-        
-        if let Some(ref lang) = language {
-            params.set_language(Some(lang.as_str()));
-        } else {
-            params.set_language(None);
-        }
+    println!("✓ Transcription complete!");
+    println!("✓ Saved to: {}", output_path.display());
 
-        params.set_translate(false);
-        params.set_print_progress(false);
-        params.set_suppress_blank(true);
-
-        state.full(params, &samples_16k)
-            .context("Final transcription failed")?;
-
-        let num_segments = state.full_n_segments()
-            .context("Failed to get number of segments")?;
-
-        {
-            let mut file = file_clone.lock().unwrap();
-            for i in 0..num_segments {
-                let segment = state.full_get_segment_text(i)?;
-                let start_timestamp = state.full_get_segment_t0(i)?;
-                let end_timestamp = state.full_get_segment_t1(i)?;
-
-                let start_sec = start_timestamp / 100;
-                let end_sec = end_timestamp / 100;
-                let start_min = start_sec / 60;
-                let start_sec = start_sec % 60;
-                let end_min = end_sec / 60;
-                let end_sec = end_sec % 60;
-
-                let transcript_line = format!(
-                    "[{:02}:{:02} - {:02}:{:02}] {}\n",
-                    start_min, start_sec, end_min, end_sec, segment.trim()
-                );
-
-                print!("{}", transcript_line);
-                writeln!(file, "{}", transcript_line)?;
-            }
-        }
-    }
+    Ok(())
+}
 
-    transcription_handle.join().unwrap();
+// The source for `--input`: a container file, or headerless PCM with
+// `--raw-format`, read from stdin if the path is `-`, reduced to one channel
+// with `--channel`
+fn open_input(input_path: &Path, options: &InputArgs) -> Result<Box<dyn AudioSource>> {
+    let source: Box<dyn AudioSource> = match options.raw_format {
+        Some(raw_format) if is_stdin(input_path) => {
+            Box::new(RawPcmSource::stdin(raw_format, options.raw_rate, options.raw_channels))
+        }
+        Some(raw_format) => Box::new(RawPcmSource::new(
+            input_path.display().to_string(),
+            open_file(input_path)?,
+            raw_format,
+            options.raw_rate,
+            options.raw_channels,
+        )),
+        None => match options.track {
+            Some(track) => Box::new(FileSource::new(input_path).with_track(track)),
+            None => Box::new(FileSource::new(input_path)),
+        },
+    };
+    Ok(select_channel(source, options.channel))
+}
 
-    {
-        let mut file = file_clone.lock().unwrap();
-        writeln!(file, "\nEnded: {}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"))?;
+fn select_channel(source: Box<dyn AudioSource>, channel: Option<u16>) -> Box<dyn AudioSource> {
+    match channel {
+        Some(channel) => Box::new(SelectChannel::new(source, channel)),
+        None => source,
     }
+}
 
-    println!("\n✓ Recording stopped!");
-    println!("✓ Transcription saved to: {}", output_path.display());
+fn load_vocabulary(paths: &[PathBuf]) -> Result<Option<Vocabulary>> {
+    let mut vocabulary = Vocabulary::default();
+    for path in paths {
+        vocabulary.extend(Vocabulary::load(path)?);
+    }
+    if vocabulary.is_empty() {
+        return Ok(None);
+    }
+    println!("Vocabulary: {} terms", vocabulary.terms().len());
+    Ok(Some(vocabulary))
+}
 
-    Ok(())
+fn open_file(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open audio file: {}", path.display()))?;
+    Ok(BufReader::new(file))
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+// e.g. `live_transcription_20240101_093000.txt`
+fn timestamped_output_path(prefix: &str, format: OutputFormat) -> PathBuf {
+    PathBuf::from(format!("{}_{}.{}",
+        prefix,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+        format.extension()))
+}
 
-    // Check if we're doing live recording or file transcription
-    if args.live || args.input.is_none() {
-        // Live recording mode
-        let output_path = args.output.unwrap_or_else(|| {
-            PathBuf::from(format!("live_transcription_{}.txt", 
-                chrono::Local::now().format("%Y%m%d_%H%M%S")))
-        });
-
-        record_and_transcribe_live(
-            &args.model,
-            &output_path,
-            args.language,
-            args.chunk_seconds,
-        )
-    } else {
-        // File transcription mode
-        let input_path = args.input.unwrap();
-        let output_path = args.output.unwrap_or_else(|| {
-            let mut output = input_path.clone();
-            output.set_extension("txt");
-            output
-        });
-
-        println!("=== Audio Transcription Tool ===");
-        println!("Input: {}", input_path.display());
-        println!("Output: {}", output_path.display());
-        println!();
-
-        // Load and decode audio file
-        let audio_samples = load_audio_file(&input_path)?;
-
-        if audio_samples.is_empty() {
-            anyhow::bail!("No audio samples found in file");
-        }
+// `meeting.txt` -> `meeting.final.txt`
+fn final_output_path(output_path: &Path) -> PathBuf {
+    let stem = output_path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match output_path.extension() {
+        Some(extension) => format!("{}.final.{}", stem, extension.to_string_lossy()),
+        None => format!("{}.final", stem),
+    };
+    output_path.with_file_name(file_name)
+}
 
-        println!("Audio loaded: {} samples ({} seconds)", 
-                 audio_samples.len(), 
-                 audio_samples.len() as f32 / 16000.0);
-
-        // Transcribe using Whisper
-        let transcript = transcribe_audio(
-            &args.model, 
-            &audio_samples, 
-            args.language,
-            args.speaker_diarization,
-        )?;
-
-        // Save transcription to file
-        println!("\nSaving transcription to: {}", output_path.display());
-        let mut file = File::create(&output_path)
-            .with_context(|| format!("Failed to create output file: {}", output_path.display()))?;
-        
-        writeln!(file, "Meeting Minutes - Transcription")
-            .context("Failed to write to output file")?;
-        writeln!(file, "Source: {}\n", input_path.display())
-            .context("Failed to write to output file")?;
-        writeln!(file, "{}", transcript)
-            .context("Failed to write to output file")?;
-
-        println!("✓ Transcription complete!");
-        println!("✓ Saved to: {}", output_path.display());
-
-        Ok(())
+fn enroll(
+    name: &str,
+    clips: &[PathBuf],
+    profiles_dir: &Path,
+    options: &DiarizationOptions,
+) -> Result<()> {
+    let store = ProfileStore::new(profiles_dir);
+
+    for clip in clips {
+        println!("Enrolling {} from: {}", name, clip.display());
+        let samples = load_audio_file(clip)?;
+        let embedding = extract_voice_embedding(&samples, WHISPER_SAMPLE_RATE, options)
+            .with_context(|| format!("Failed to extract a voice embedding from {}", clip.display()))?;
+        let profile = store.enroll(name, &embedding)?;
+        println!("✓ {} now has {} enrolled clip(s)", profile.name, profile.clip_count);
     }
+
+    println!("✓ Voice profile saved in: {}", store.dir().display());
+    Ok(())
 }
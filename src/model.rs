@@ -0,0 +1,55 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Locate a model file, trying the path as given, relative to the current
+/// directory and relative to the project root (where Cargo.toml lives).
+pub fn resolve_model_path(path: &Path) -> Result<PathBuf> {
+    // If path is absolute and exists, use it
+    if path.is_absolute() && path.exists() {
+        return Ok(path.to_path_buf());
+    }
+
+    // If path is relative and exists in current directory, use it
+    if path.exists() {
+        return Ok(path.canonicalize()?);
+    }
+
+    // Try relative to current directory
+    let current_dir = std::env::current_dir()?;
+    let relative_path = current_dir.join(path);
+    if relative_path.exists() {
+        return Ok(relative_path.canonicalize()?);
+    }
+
+    // Try relative to project root (look for Cargo.toml)
+    let mut search_dir = current_dir.clone();
+    loop {
+        let cargo_toml = search_dir.join("Cargo.toml");
+        if cargo_toml.exists() {
+            let project_path = search_dir.join(path);
+            if project_path.exists() {
+                return Ok(project_path.canonicalize()?);
+            }
+            break;
+        }
+        match search_dir.parent() {
+            Some(parent) => search_dir = parent.to_path_buf(),
+            None => break,
+        }
+    }
+
+    // If still not found, return original path with helpful error
+    anyhow::bail!(
+        "Model file not found: {}\n\
+         Searched in:\n\
+         - {}\n\
+         - {}\n\
+         - Project root (where Cargo.toml is located)\n\
+         Please ensure the model file exists or provide an absolute path.\n\
+         Current directory: {}",
+        path.display(),
+        path.display(),
+        relative_path.display(),
+        current_dir.display()
+    )
+}
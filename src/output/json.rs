@@ -0,0 +1,73 @@
+//! JSON and JSON-Lines formats for machine consumption.
+
+use serde::Serialize;
+use std::io::{self, Write};
+
+use super::speaker_label;
+use crate::transcript::{AudioDevice, LanguageProbability, Segment, Transcript};
+
+/// Version of the JSON document layout, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    source: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<&'a AudioDevice>,
+    created_at: String,
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    language_probabilities: &'a [LanguageProbability],
+    model: Option<&'a str>,
+    audio_duration_ms: i64,
+    speakers: Vec<Speaker>,
+    segments: &'a [Segment],
+}
+
+#[derive(Serialize)]
+struct Speaker {
+    id: usize,
+    label: String,
+    segment_count: usize,
+    speaking_time_ms: i64,
+}
+
+/// Write `transcript` as a single pretty-printed JSON document.
+pub fn write_json<W: Write>(writer: &mut W, transcript: &Transcript) -> io::Result<()> {
+    let speakers = transcript
+        .speakers()
+        .into_iter()
+        .map(|id| {
+            let segments = transcript.segments.iter().filter(|s| s.speaker == Some(id));
+            Speaker {
+                id,
+                label: speaker_label(id, &transcript.speaker_names),
+                segment_count: segments.clone().count(),
+                speaking_time_ms: segments.map(Segment::duration_ms).sum(),
+            }
+        })
+        .collect();
+
+    let document = Document {
+        schema_version: SCHEMA_VERSION,
+        source: &transcript.source,
+        device: transcript.device.as_ref(),
+        created_at: transcript.created_at.to_rfc3339(),
+        language: transcript.language.as_deref(),
+        language_probabilities: &transcript.language_probabilities,
+        model: transcript.model.as_deref(),
+        audio_duration_ms: transcript.audio_duration_ms,
+        speakers,
+        segments: &transcript.segments,
+    };
+
+    serde_json::to_writer_pretty(&mut *writer, &document)?;
+    writeln!(writer)
+}
+
+/// Write `segment` as one line of JSON.
+pub fn write_jsonl_segment<W: Write>(writer: &mut W, segment: &Segment) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, segment)?;
+    writeln!(writer)
+}
@@ -0,0 +1,158 @@
+//! Renderers that turn a [`Transcript`](crate::Transcript) into files.
+
+pub mod json;
+pub mod subtitles;
+pub mod text;
+
+use std::io::{self, Write};
+
+use crate::transcript::{Segment, Transcript};
+
+/// File format a transcript is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Plain-text meeting minutes
+    #[default]
+    Txt,
+    /// SubRip subtitles
+    Srt,
+    /// WebVTT subtitles
+    Vtt,
+    /// One JSON document with metadata and all segments
+    Json,
+    /// One JSON segment per line, suitable for streaming
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// File extension conventionally used for this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Txt => "txt",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// Layout options for the subtitle formats.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Maximum number of characters on one subtitle line.
+    pub max_line_length: usize,
+    /// Maximum number of lines in one subtitle cue; longer segments are
+    /// split into several cues.
+    pub max_lines: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            max_line_length: 42,
+            max_lines: 2,
+        }
+    }
+}
+
+/// Writes a transcript segment by segment, so live recording can stream
+/// output in any format as it is transcribed.
+pub struct TranscriptWriter<W: Write> {
+    writer: W,
+    format: OutputFormat,
+    options: FormatOptions,
+    // Number of subtitle cues written so far
+    cue_count: usize,
+    // Speaker names of the transcript being written, from `write_header`
+    speaker_names: Vec<String>,
+}
+
+impl<W: Write> TranscriptWriter<W> {
+    pub fn new(writer: W, format: OutputFormat, options: FormatOptions) -> Self {
+        Self {
+            writer,
+            format,
+            options,
+            cue_count: 0,
+            speaker_names: Vec::new(),
+        }
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Write whatever precedes the first segment.
+    pub fn write_header(&mut self, transcript: &Transcript) -> io::Result<()> {
+        self.speaker_names = transcript.speaker_names.clone();
+        match self.format {
+            OutputFormat::Txt => text::write_header(&mut self.writer, transcript),
+            OutputFormat::Vtt => subtitles::write_vtt_header(&mut self.writer),
+            OutputFormat::Srt | OutputFormat::Json | OutputFormat::Jsonl => Ok(()),
+        }
+    }
+
+    pub fn write_segment(&mut self, segment: &Segment) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Txt => writeln!(self.writer, "{}", text::format_segment(segment, &self.speaker_names)),
+            OutputFormat::Srt => {
+                self.cue_count = subtitles::write_srt_cues(
+                    &mut self.writer,
+                    segment,
+                    self.cue_count,
+                    &self.speaker_names,
+                    &self.options,
+                )?;
+                Ok(())
+            }
+            OutputFormat::Vtt => {
+                subtitles::write_vtt_cues(&mut self.writer, segment, &self.speaker_names, &self.options)
+            }
+            OutputFormat::Jsonl => json::write_jsonl_segment(&mut self.writer, segment),
+            // The JSON document needs the whole transcript, see `finish`
+            OutputFormat::Json => Ok(()),
+        }
+    }
+
+    /// Write whatever follows the last segment and flush. `transcript` must
+    /// be the same transcript whose segments were written.
+    pub fn finish(&mut self, transcript: &Transcript) -> io::Result<()> {
+        if self.format == OutputFormat::Json {
+            json::write_json(&mut self.writer, transcript)?;
+        }
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Write a complete transcript in `format`.
+pub fn write_transcript<W: Write>(
+    writer: W,
+    transcript: &Transcript,
+    format: OutputFormat,
+    options: &FormatOptions,
+) -> io::Result<()> {
+    let mut writer = TranscriptWriter::new(writer, format, options.clone());
+    writer.write_header(transcript)?;
+    for segment in &transcript.segments {
+        writer.write_segment(segment)?;
+    }
+    writer.finish(transcript)
+}
+
+/// Display name for a zero-based speaker index: its enrolled name if
+/// `names` has one, otherwise `Speaker N`.
+pub fn speaker_label(speaker: usize, names: &[String]) -> String {
+    match names.get(speaker) {
+        Some(name) => name.clone(),
+        None => format!("Speaker {}", speaker + 1),
+    }
+}
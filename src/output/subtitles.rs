@@ -0,0 +1,207 @@
+//! SubRip (SRT) and WebVTT subtitle formats.
+
+use std::io::{self, Write};
+
+use super::{speaker_label, FormatOptions};
+use crate::transcript::Segment;
+
+/// A single subtitle cue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub lines: Vec<String>,
+    /// Lines of the cue's share of the segment's translation, shown in
+    /// italics below `lines`.
+    pub translation: Vec<String>,
+}
+
+pub fn write_vtt_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writeln!(writer, "WEBVTT")?;
+    writeln!(writer)
+}
+
+/// Write `segment` as one or more numbered SRT cues following cue
+/// `cue_count`. Returns the new cue count.
+pub fn write_srt_cues<W: Write>(
+    writer: &mut W,
+    segment: &Segment,
+    mut cue_count: usize,
+    speaker_names: &[String],
+    options: &FormatOptions,
+) -> io::Result<usize> {
+    // SRT has no speaker markup, so the label becomes part of the text
+    let text = match segment.speaker {
+        Some(speaker) => format!("{}: {}", speaker_label(speaker, speaker_names), segment.text),
+        None => segment.text.clone(),
+    };
+
+    for cue in segment_cues(segment, &text, options) {
+        cue_count += 1;
+        writeln!(writer, "{}", cue_count)?;
+        writeln!(
+            writer,
+            "{} --> {}",
+            format_srt_timestamp(cue.start_ms),
+            format_srt_timestamp(cue.end_ms)
+        )?;
+        for line in &cue.lines {
+            writeln!(writer, "{}", line)?;
+        }
+        for line in &cue.translation {
+            writeln!(writer, "<i>{}</i>", line)?;
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(cue_count)
+}
+
+/// Write `segment` as one or more WebVTT cues, tagging the speaker with a
+/// voice span when diarization assigned one, and the language with a
+/// language span when code switching detected one.
+pub fn write_vtt_cues<W: Write>(
+    writer: &mut W,
+    segment: &Segment,
+    speaker_names: &[String],
+    options: &FormatOptions,
+) -> io::Result<()> {
+    let voice = segment.speaker.map(|speaker| speaker_label(speaker, speaker_names));
+
+    for cue in segment_cues(segment, &segment.text, options) {
+        writeln!(
+            writer,
+            "{} --> {}",
+            format_vtt_timestamp(cue.start_ms),
+            format_vtt_timestamp(cue.end_ms)
+        )?;
+        let text = cue
+            .lines
+            .iter()
+            .map(|line| escape_vtt(line))
+            .chain(cue.translation.iter().map(|line| format!("<i>{}</i>", escape_vtt(line))))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = match segment.language {
+            Some(ref language) => format!("<lang {}>{}</lang>", escape_vtt(language), text),
+            None => text,
+        };
+        match voice {
+            Some(ref voice) => writeln!(writer, "<v {}>{}", escape_vtt(voice), text)?,
+            None => writeln!(writer, "{}", text)?,
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+/// Split `text`, a segment's text with any speaker label, into cues and
+/// give each cue its share of the segment's translation, divided by word
+/// count so the two stay in step.
+pub fn segment_cues(segment: &Segment, text: &str, options: &FormatOptions) -> Vec<Cue> {
+    let mut cues = split_into_cues(segment.start_ms, segment.end_ms, text, options);
+    if let Some(ref translation) = segment.translation {
+        let words: Vec<&str> = translation.split_whitespace().collect();
+        let cue_total = cues.len();
+        for (i, cue) in cues.iter_mut().enumerate() {
+            let share = &words[i * words.len() / cue_total..(i + 1) * words.len() / cue_total];
+            cue.translation = wrap_text(&share.join(" "), options.max_line_length.max(1));
+        }
+    }
+    cues
+}
+
+/// Wrap `text` into lines of at most `max_line_length` characters and group
+/// them into cues of at most `max_lines` lines. When more than one cue is
+/// needed, the time span is divided between them by character count.
+pub fn split_into_cues(start_ms: i64, end_ms: i64, text: &str, options: &FormatOptions) -> Vec<Cue> {
+    let lines = wrap_text(text, options.max_line_length.max(1));
+    if lines.is_empty() {
+        return Vec::new();
+    }
+
+    let groups: Vec<Vec<String>> = lines
+        .chunks(options.max_lines.max(1))
+        .map(|group| group.to_vec())
+        .collect();
+
+    let cue_total = groups.len();
+    let total_chars: usize = groups.iter().map(|g| group_chars(g)).sum();
+    let duration = (end_ms - start_ms).max(0);
+
+    let mut cues = Vec::with_capacity(cue_total);
+    let mut chars_so_far = 0;
+    let mut cue_start = start_ms;
+    for (i, lines) in groups.into_iter().enumerate() {
+        chars_so_far += group_chars(&lines);
+        let cue_end = if i + 1 == cue_total {
+            end_ms
+        } else {
+            start_ms + duration * chars_so_far as i64 / total_chars.max(1) as i64
+        };
+        cues.push(Cue {
+            start_ms: cue_start,
+            end_ms: cue_end,
+            lines,
+            translation: Vec::new(),
+        });
+        cue_start = cue_end;
+    }
+
+    cues
+}
+
+fn group_chars(lines: &[String]) -> usize {
+    lines.iter().map(|line| line.chars().count()).sum()
+}
+
+/// Greedily wrap `text` at word boundaries. Words longer than
+/// `max_line_length` are kept whole on a line of their own.
+pub fn wrap_text(text: &str, max_line_length: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        let needed = if current.is_empty() {
+            word.chars().count()
+        } else {
+            current.chars().count() + 1 + word.chars().count()
+        };
+        if needed > max_line_length && !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+/// Format milliseconds as `HH:MM:SS,mmm`.
+pub fn format_srt_timestamp(ms: i64) -> String {
+    let (hours, minutes, seconds, millis) = split_timestamp(ms);
+    format!("{:02}:{:02}:{:02},{:03}", hours, minutes, seconds, millis)
+}
+
+/// Format milliseconds as `HH:MM:SS.mmm`.
+pub fn format_vtt_timestamp(ms: i64) -> String {
+    let (hours, minutes, seconds, millis) = split_timestamp(ms);
+    format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+}
+
+fn split_timestamp(ms: i64) -> (i64, i64, i64, i64) {
+    let ms = ms.max(0);
+    (ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
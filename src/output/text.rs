@@ -0,0 +1,70 @@
+//! The plain-text "Meeting Minutes" format.
+
+use std::io::{self, Write};
+
+use super::speaker_label;
+use crate::transcript::{Segment, Transcript};
+
+/// Write the title block that precedes the segments.
+pub fn write_header<W: Write>(writer: &mut W, transcript: &Transcript) -> io::Result<()> {
+    writeln!(writer, "Meeting Minutes - Transcription")?;
+    writeln!(writer, "Source: {}", transcript.source)?;
+    if let Some(ref device) = transcript.device {
+        writeln!(
+            writer,
+            "Device: {} ({}), {} Hz, {} ch, {}",
+            device.name, device.host, device.sample_rate, device.channels, device.sample_format
+        )?;
+    }
+    writeln!(writer, "Started: {}", transcript.created_at.format("%Y-%m-%d %H:%M:%S"))?;
+    if let Some(ref language) = transcript.language {
+        if transcript.language_probabilities.is_empty() {
+            writeln!(writer, "Language: {}", language)?;
+        } else {
+            let detected = transcript
+                .language_probabilities
+                .iter()
+                .map(|detected| format!("{} {:.0}%", detected.language, detected.probability * 100.0))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(writer, "Language: {} (detected: {})", language, detected)?;
+        }
+    }
+    writeln!(writer)
+}
+
+/// Render a segment as `[MM:SS - MM:SS] Speaker N: text`, using the
+/// speaker's name from `speaker_names` if there is one. A language the
+/// segment was tagged with goes before the speaker, as `(pt)`, and a
+/// translation follows on a second line, indented to the text.
+pub fn format_segment(segment: &Segment, speaker_names: &[String]) -> String {
+    let mut speaker_label = match segment.speaker {
+        Some(speaker) => format!("{}: ", speaker_label(speaker, speaker_names)),
+        None => String::new(),
+    };
+    if let Some(ref language) = segment.language {
+        speaker_label.insert_str(0, &format!("({}) ", language));
+    }
+    let times = format!(
+        "[{} - {}] ",
+        format_timestamp(segment.start_ms),
+        format_timestamp(segment.end_ms)
+    );
+    match segment.translation {
+        Some(ref translation) => format!(
+            "{}{}{}\n{}EN: {}",
+            times,
+            speaker_label,
+            segment.text,
+            " ".repeat(times.chars().count()),
+            translation
+        ),
+        None => format!("{}{}{}", times, speaker_label, segment.text),
+    }
+}
+
+/// Format a millisecond timestamp as `MM:SS`.
+pub fn format_timestamp(ms: i64) -> String {
+    let total_secs = ms / 1000;
+    format!("{:02}:{:02}", total_secs / 60, total_secs % 60)
+}
@@ -0,0 +1,158 @@
+//! Enrolled voice profiles, stored as one JSON file per person.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::clustering::cosine_similarity;
+
+/// Default directory voice profiles are stored in.
+pub const DEFAULT_PROFILES_DIR: &str = "profiles";
+
+/// A named speaker embedding, averaged over every clip enrolled for them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VoiceProfile {
+    pub name: String,
+    pub embedding: Vec<f32>,
+    /// Number of clips averaged into `embedding`.
+    pub clip_count: usize,
+    pub updated_at: DateTime<Local>,
+}
+
+/// A directory of voice profiles.
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Load every profile in the directory, sorted by name. A missing
+    /// directory has no profiles.
+    pub fn load_all(&self) -> Result<Vec<VoiceProfile>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut profiles = Vec::new();
+        let entries = fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read profiles directory: {}", self.dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            profiles.push(read_profile(&path)?);
+        }
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(profiles)
+    }
+
+    /// Add a clip's embedding to `name`'s profile, creating it if needed.
+    pub fn enroll(&self, name: &str, embedding: &[f32]) -> Result<VoiceProfile> {
+        let path = self.profile_path(name);
+        let profile = if path.exists() {
+            let mut profile = read_profile(&path)?;
+            // Names differing only in case or punctuation share a file
+            if profile.name.trim() != name.trim() {
+                anyhow::bail!(
+                    "{} is already enrolled as {}; use a name that differs by more than \
+                     case or punctuation",
+                    path.display(),
+                    profile.name
+                );
+            }
+            if profile.embedding.len() != embedding.len() {
+                anyhow::bail!(
+                    "Profile for {} was enrolled with a different embedding model \
+                     ({} dimensions, got {})",
+                    name,
+                    profile.embedding.len(),
+                    embedding.len()
+                );
+            }
+            // Running mean over all enrolled clips
+            let count = profile.clip_count as f32;
+            for (mean, &value) in profile.embedding.iter_mut().zip(embedding) {
+                *mean = (*mean * count + value) / (count + 1.0);
+            }
+            profile.clip_count += 1;
+            profile.updated_at = Local::now();
+            profile
+        } else {
+            VoiceProfile {
+                name: name.to_string(),
+                embedding: embedding.to_vec(),
+                clip_count: 1,
+                updated_at: Local::now(),
+            }
+        };
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create profiles directory: {}", self.dir.display()))?;
+        let json = serde_json::to_string_pretty(&profile)?;
+        fs::write(&path, json)
+            .with_context(|| format!("Failed to write profile: {}", path.display()))?;
+
+        Ok(profile)
+    }
+
+    fn profile_path(&self, name: &str) -> PathBuf {
+        // Keep file names portable whatever the person's name is
+        let file_stem: String = name
+            .trim()
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", file_stem))
+    }
+}
+
+fn read_profile(path: &Path) -> Result<VoiceProfile> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read profile: {}", path.display()))?;
+    serde_json::from_str(&json).with_context(|| format!("Invalid profile: {}", path.display()))
+}
+
+/// Match speaker centroids to profiles. Each profile is used at most once,
+/// best matches first, and only if its cosine similarity reaches
+/// `threshold`. Returns the matched profile index for each centroid.
+pub fn match_profiles(
+    centroids: &[Vec<f32>],
+    profiles: &[VoiceProfile],
+    threshold: f32,
+) -> Vec<Option<usize>> {
+    let mut candidates = Vec::new();
+    for (speaker, centroid) in centroids.iter().enumerate() {
+        for (profile_index, profile) in profiles.iter().enumerate() {
+            if profile.embedding.len() != centroid.len() {
+                continue;
+            }
+            let similarity = cosine_similarity(centroid, &profile.embedding);
+            if similarity >= threshold {
+                candidates.push((similarity, speaker, profile_index));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut matches = vec![None; centroids.len()];
+    let mut used = vec![false; profiles.len()];
+    for (_, speaker, profile_index) in candidates {
+        if matches[speaker].is_none() && !used[profile_index] {
+            matches[speaker] = Some(profile_index);
+            used[profile_index] = true;
+        }
+    }
+
+    matches
+}
@@ -0,0 +1,102 @@
+//! Saving captured audio to disk while it is being recorded.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::flac::FlacWriter;
+
+/// Writes interleaved samples to a WAV or FLAC file as 16- or 24-bit PCM.
+///
+/// Both formats are kept valid after every [`flush`](Self::flush), so a
+/// recording interrupted by a crash can still be played and transcribed.
+pub struct RecordingWriter {
+    inner: Inner,
+    // Largest sample value at the file's bit depth
+    full_scale: f32,
+}
+
+enum Inner {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter<BufWriter<File>>),
+}
+
+impl RecordingWriter {
+    /// Create `path`, choosing WAV or FLAC from its extension. Sources of
+    /// up to 16 bits per sample are saved at 16 bits, others (such as
+    /// float capture) at 24 bits.
+    pub fn create(path: &Path, sample_rate: u32, channels: u16, source_bits_per_sample: u16) -> Result<Self> {
+        let bits_per_sample = if source_bits_per_sample <= 16 { 16 } else { 24 };
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let inner = match extension.as_deref() {
+            Some("wav") => {
+                let spec = hound::WavSpec {
+                    channels,
+                    sample_rate,
+                    bits_per_sample,
+                    sample_format: hound::SampleFormat::Int,
+                };
+                let writer = hound::WavWriter::create(path, spec)
+                    .with_context(|| format!("Failed to create audio file: {}", path.display()))?;
+                Inner::Wav(writer)
+            }
+            Some("flac") => {
+                let file = File::create(path)
+                    .with_context(|| format!("Failed to create audio file: {}", path.display()))?;
+                let writer = FlacWriter::new(BufWriter::new(file), sample_rate, channels, bits_per_sample as u32)
+                    .with_context(|| format!("Failed to write audio file: {}", path.display()))?;
+                Inner::Flac(writer)
+            }
+            _ => anyhow::bail!(
+                "Unsupported audio file type: {} (use .wav or .flac)",
+                path.display()
+            ),
+        };
+
+        let full_scale = ((1 << (bits_per_sample - 1)) - 1) as f32;
+        Ok(Self { inner, full_scale })
+    }
+
+    /// Append interleaved samples in the range -1.0 to 1.0.
+    pub fn write(&mut self, samples: &[f32]) -> Result<()> {
+        let full_scale = self.full_scale;
+        let to_int = |s: f32| (s * full_scale).clamp(-full_scale - 1.0, full_scale) as i32;
+        match self.inner {
+            Inner::Wav(ref mut writer) => {
+                for &sample in samples {
+                    writer.write_sample(to_int(sample))?;
+                }
+            }
+            Inner::Flac(ref mut writer) => {
+                let samples: Vec<i32> = samples.iter().map(|&s| to_int(s)).collect();
+                writer.write_samples(&samples)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Push everything written so far to disk.
+    pub fn flush(&mut self) -> Result<()> {
+        match self.inner {
+            Inner::Wav(ref mut writer) => writer.flush()?,
+            Inner::Flac(ref mut writer) => writer.flush()?,
+        }
+        Ok(())
+    }
+
+    /// Finish the file, writing its final length.
+    pub fn finalize(self) -> Result<()> {
+        match self.inner {
+            Inner::Wav(writer) => writer.finalize()?,
+            Inner::Flac(writer) => {
+                writer.finalize()?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Band-limited sample rate conversion.
+//!
+//! [`Resampler`] is a polyphase windowed-sinc filter: each output sample is
+//! a weighted sum of the input samples around its position, using a
+//! Kaiser-windowed sinc low-pass that removes everything above the lower
+//! of the two Nyquist frequencies. Unlike interpolating between neighbours,
+//! this stops high-frequency noise in 44.1/48 kHz captures from folding
+//! down into the speech band.
+//!
+//! The filter taps for every fractional position are computed once, and
+//! input can be fed in blocks of any size: the output is identical to
+//! resampling the whole signal at once.
+
+/// How much filtering goes into resampling. Better quality keeps more of
+/// the band flat and rejects aliases more strongly, at the cost of a longer
+/// filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ResampleQuality {
+    /// Short filter: flat to about 60% of the lower Nyquist frequency,
+    /// aliases attenuated by 60 dB
+    Fast,
+    /// Flat to about 75% of the lower Nyquist frequency, aliases attenuated
+    /// by 90 dB
+    #[default]
+    Medium,
+    /// Flat to about 85% of the lower Nyquist frequency, aliases attenuated
+    /// by 120 dB
+    High,
+}
+
+impl ResampleQuality {
+    // Zero crossings of the sinc on each side of the centre, and stopband
+    // attenuation in dB
+    fn design(self) -> (f64, f64) {
+        match self {
+            ResampleQuality::Fast => (8.0, 60.0),
+            ResampleQuality::Medium => (24.0, 90.0),
+            ResampleQuality::High => (64.0, 120.0),
+        }
+    }
+}
+
+/// Fractional positions are rounded to this many steps when the rates have
+/// no small common ratio, e.g. 44 099 Hz, to bound the filter table.
+const MAX_PHASES: u64 = 1024;
+
+/// Converts a mono stream from one sample rate to another, block by block.
+///
+/// Output sample `n` lies at input position `n * from_rate / to_rate`, so
+/// positions on both timelines stay exactly in step however the input is
+/// split up.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    // Output advances `down` input samples every `up` outputs
+    up: u64,
+    down: u64,
+    phases: u64,
+    // Taps on each side of an output position
+    half_taps: usize,
+    // `phases` rows of `2 * half_taps` taps; row p weights inputs
+    // i - half_taps + 1 ..= i + half_taps for position i + p / phases
+    filter: Vec<f32>,
+    // Input still needed, `input[0]` being input sample `input_start`
+    input: Vec<f32>,
+    input_start: u64,
+    input_len: u64,
+    next_output: u64,
+    passthrough: bool,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Self {
+        let divisor = gcd(from_rate.max(1) as u64, to_rate.max(1) as u64);
+        let up = to_rate.max(1) as u64 / divisor;
+        let down = from_rate.max(1) as u64 / divisor;
+        let phases = up.min(MAX_PHASES);
+
+        let (zero_crossings, attenuation) = quality.design();
+        // Cutoff in cycles per input sample. The transition band of a
+        // Kaiser-windowed sinc is centred on the cutoff, so pull it in far
+        // enough that the stopband begins at the lower Nyquist frequency.
+        let transition = (attenuation - 7.95) / (28.72 * zero_crossings);
+        let nyquist = 0.5 * (up as f64 / down as f64).min(1.0);
+        let cutoff = nyquist / (1.0 + transition);
+        let width = zero_crossings / (2.0 * cutoff);
+        let half_taps = width.ceil() as usize;
+        let beta = kaiser_beta(attenuation);
+
+        let taps = 2 * half_taps;
+        let mut filter = Vec::with_capacity(phases as usize * taps);
+        for phase in 0..phases {
+            let fraction = phase as f64 / phases as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|k| {
+                    // Distance from the output position to this input
+                    let distance = (half_taps - 1) as f64 - k as f64 + fraction;
+                    windowed_sinc(distance, cutoff, width, beta)
+                })
+                .collect();
+            // Normalise so a constant signal keeps its level exactly
+            let sum: f64 = row.iter().sum();
+            filter.extend(row.iter().map(|&tap| (tap / sum) as f32));
+        }
+
+        Self {
+            up,
+            down,
+            phases,
+            half_taps,
+            filter,
+            input: Vec::new(),
+            input_start: 0,
+            input_len: 0,
+            next_output: 0,
+            passthrough: from_rate == to_rate,
+        }
+    }
+
+    /// Feed `input` and append every output sample that can now be
+    /// computed to `output`. Output lags input by about
+    /// [`latency`](Self::latency) input samples until [`finish`](Self::finish).
+    pub fn process(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        if self.passthrough {
+            output.extend_from_slice(input);
+            self.input_len += input.len() as u64;
+            self.next_output = self.input_len;
+            return;
+        }
+        self.input.extend_from_slice(input);
+        self.input_len += input.len() as u64;
+        self.produce(self.input_len, output);
+    }
+
+    /// The input has ended: append the remaining output, treating the
+    /// signal as silent from here on. Further input must not be fed.
+    pub fn finish(&mut self, output: &mut Vec<f32>) {
+        if self.passthrough {
+            return;
+        }
+        let end = self.input_len;
+        self.input.resize(self.input.len() + self.half_taps, 0.0);
+        // Every output position before the end of the input
+        let total = (end * self.up).div_ceil(self.down);
+        while self.next_output < total {
+            let (index, phase) = self.position(self.next_output);
+            output.push(self.output_at(index, phase));
+            self.next_output += 1;
+        }
+    }
+
+    /// Input samples that must arrive after a position before the output
+    /// sample there can be computed.
+    pub fn latency(&self) -> usize {
+        if self.passthrough { 0 } else { self.half_taps }
+    }
+
+    /// Output samples produced so far.
+    pub fn output_len(&self) -> u64 {
+        self.next_output
+    }
+
+    // Produce outputs whose taps all lie before input sample `available`
+    fn produce(&mut self, available: u64, output: &mut Vec<f32>) {
+        loop {
+            let (index, phase) = self.position(self.next_output);
+            if index + self.half_taps as u64 >= available {
+                break;
+            }
+            output.push(self.output_at(index, phase));
+            self.next_output += 1;
+        }
+
+        // Inputs before the next output's first tap are never needed again
+        let (index, _) = self.position(self.next_output);
+        let keep_from = (index + 1).saturating_sub(self.half_taps as u64).max(self.input_start);
+        let discard = ((keep_from - self.input_start) as usize).min(self.input.len());
+        self.input.drain(..discard);
+        self.input_start += discard as u64;
+    }
+
+    // Input sample at or before output `n`, and the phase of its fractional
+    // offset
+    fn position(&self, n: u64) -> (u64, usize) {
+        let numerator = n * self.down;
+        let mut index = numerator / self.up;
+        let remainder = numerator % self.up;
+        let mut phase = if self.phases == self.up {
+            remainder
+        } else {
+            (remainder * self.phases + self.up / 2) / self.up
+        };
+        if phase == self.phases {
+            index += 1;
+            phase = 0;
+        }
+        (index, phase as usize)
+    }
+
+    fn output_at(&self, index: u64, phase: usize) -> f32 {
+        let taps = 2 * self.half_taps;
+        let row = &self.filter[phase * taps..(phase + 1) * taps];
+        // Absolute index of the first tap; the input before the start is
+        // silence
+        let first = index as i64 - self.half_taps as i64 + 1;
+        let skip = (self.input_start as i64 - first).max(0) as usize;
+        let offset = (first + skip as i64 - self.input_start as i64) as usize;
+        row[skip..]
+            .iter()
+            .zip(&self.input[offset..])
+            .map(|(&tap, &sample)| tap * sample)
+            .sum()
+    }
+}
+
+// Kaiser-windowed sinc low-pass with `cutoff` in cycles per sample, at
+// `distance` samples from the centre, for a window `width` samples either
+// side
+fn windowed_sinc(distance: f64, cutoff: f64, width: f64, beta: f64) -> f64 {
+    let x = distance / width;
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let window = bessel_i0(beta * (1.0 - x * x).sqrt()) / bessel_i0(beta);
+    let t = 2.0 * cutoff * distance;
+    let sinc = if t == 0.0 {
+        1.0
+    } else {
+        (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t)
+    };
+    2.0 * cutoff * sinc * window
+}
+
+// Kaiser window shape for a stopband attenuation in dB
+fn kaiser_beta(attenuation: f64) -> f64 {
+    if attenuation > 50.0 {
+        0.1102 * (attenuation - 8.7)
+    } else if attenuation >= 21.0 {
+        0.5842 * (attenuation - 21.0).powf(0.4) + 0.07886 * (attenuation - 21.0)
+    } else {
+        0.0
+    }
+}
+
+// Modified Bessel function of the first kind, order zero
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..100 {
+        term *= half / k as f64;
+        let squared = term * term;
+        sum += squared;
+        if squared < sum * 1e-16 {
+            break;
+        }
+    }
+    sum
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
@@ -0,0 +1,85 @@
+//! Picking channels out of multi-channel audio.
+
+use anyhow::Result;
+
+use super::{AudioBlock, AudioFormat, AudioSource};
+use crate::transcript::AudioDevice;
+
+/// Passes on a single channel of another source, instead of the mix of
+/// all channels, e.g. one side of a call recorded in stereo.
+pub struct SelectChannel<S> {
+    inner: S,
+    channel: u16,
+    channels: u16,
+    // Position within a frame that the next block starts at, for sources
+    // whose blocks can split frames
+    frame_offset: usize,
+}
+
+impl<S: AudioSource> SelectChannel<S> {
+    /// Take channel `channel` (counting from 0) of `inner`.
+    pub fn new(inner: S, channel: u16) -> Self {
+        Self {
+            inner,
+            channel,
+            channels: 0,
+            frame_offset: 0,
+        }
+    }
+}
+
+impl<S: AudioSource> AudioSource for SelectChannel<S> {
+    fn describe(&self) -> String {
+        format!("{} (channel {})", self.inner.describe(), self.channel)
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        let format = self.inner.start()?;
+        if self.channel >= format.channels {
+            anyhow::bail!(
+                "{} has {} channel(s), so there is no channel {} (channels count from 0)",
+                self.inner.describe(),
+                format.channels,
+                self.channel
+            );
+        }
+        self.channels = format.channels;
+        self.frame_offset = 0;
+        Ok(AudioFormat {
+            sample_rate: format.sample_rate,
+            channels: 1,
+            bits_per_sample: format.bits_per_sample,
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        let Some(block) = self.inner.next_block()? else {
+            return Ok(None);
+        };
+        let channels = self.channels as usize;
+        let first = (self.channel as usize + channels - self.frame_offset) % channels;
+        let samples = block.samples.iter().skip(first).step_by(channels).copied().collect();
+        self.frame_offset = (self.frame_offset + block.samples.len()) % channels;
+        Ok(Some(AudioBlock {
+            frame: block.frame,
+            samples,
+        }))
+    }
+
+    fn stop(&mut self) {
+        self.inner.stop();
+    }
+
+    fn device(&self) -> Option<AudioDevice> {
+        self.inner.device()
+    }
+}
+
+/// Speaker labels for audio with one speaker per channel: `Left` and
+/// `Right` for stereo, otherwise `Channel 0`, `Channel 1` and so on.
+pub fn channel_names(channels: usize) -> Vec<String> {
+    if channels == 2 {
+        return vec!["Left".to_string(), "Right".to_string()];
+    }
+    (0..channels).map(|channel| format!("Channel {}", channel)).collect()
+}
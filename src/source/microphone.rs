@@ -0,0 +1,140 @@
+//! Recording from an audio input device.
+
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use std::time::Duration;
+
+use super::{AudioBlock, AudioFormat, AudioSource};
+use crate::capture::{ring_buffer, Consumer};
+use crate::devices::{build_input_stream, find_host, find_input_device, negotiate_input_config};
+use crate::transcript::AudioDevice;
+
+/// Seconds of capture the ring buffer holds while the reader is busy.
+const RING_BUFFER_SECONDS: usize = 4;
+
+/// How long [`MicrophoneSource::next_block`] waits between checks for
+/// new audio.
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Audio captured from an input device with cpal.
+///
+/// The device callback only copies samples into a lock-free ring buffer, so
+/// however long the reader takes with each block the device is never
+/// stalled. Samples the reader was too slow to collect are reported as a
+/// gap between blocks.
+pub struct MicrophoneSource {
+    host: Option<String>,
+    device: Option<String>,
+    capture: Option<Capture>,
+    device_info: Option<AudioDevice>,
+}
+
+struct Capture {
+    // `None` once stopped
+    stream: Option<cpal::Stream>,
+    consumer: Consumer,
+    channels: usize,
+    // Samples received or dropped so far
+    samples: u64,
+}
+
+impl MicrophoneSource {
+    /// Record from `device` on `host`, see [`crate::devices`]; the defaults
+    /// are used for whichever is `None`.
+    pub fn new(host: Option<String>, device: Option<String>) -> Self {
+        Self {
+            host,
+            device,
+            capture: None,
+            device_info: None,
+        }
+    }
+}
+
+impl AudioSource for MicrophoneSource {
+    fn describe(&self) -> String {
+        match self.device_info {
+            Some(ref device) => format!("Microphone: {}", device.name),
+            None => "Microphone".to_string(),
+        }
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        let host = find_host(self.host.as_deref())?;
+        let input_device = find_input_device(&host, self.device.as_deref())?;
+        let device_name = input_device.name()?;
+
+        let supported_config = negotiate_input_config(&input_device)?;
+        let config = supported_config.config();
+        let channels = config.channels as usize;
+
+        let (mut producer, consumer) =
+            ring_buffer(RING_BUFFER_SECONDS * config.sample_rate.0 as usize * channels, channels);
+        let stream = build_input_stream(&input_device, &supported_config, move |data: &[f32]| {
+            producer.push(data);
+        })?;
+        stream.play().context("Failed to start recording")?;
+
+        self.device_info = Some(AudioDevice {
+            host: host.id().name().to_string(),
+            name: device_name,
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+            sample_format: supported_config.sample_format().to_string(),
+        });
+        self.capture = Some(Capture {
+            stream: Some(stream),
+            consumer,
+            channels,
+            samples: 0,
+        });
+
+        Ok(AudioFormat {
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+            bits_per_sample: supported_config.sample_format().sample_size() as u16 * 8,
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        let capture = self.capture.as_mut().context("Audio source has not been started")?;
+        loop {
+            let stopped = capture.stream.is_none();
+
+            let mut samples = Vec::new();
+            capture.consumer.pop_into(&mut samples);
+            // Dropped samples were pushed after everything popped here, so
+            // they come before the next block
+            let dropped = capture.consumer.take_dropped();
+            if dropped > 0 {
+                eprintln!("Warning: {} audio frames were lost", dropped / capture.channels);
+            }
+
+            if !samples.is_empty() {
+                let block = AudioBlock {
+                    frame: capture.samples / capture.channels as u64,
+                    samples,
+                };
+                capture.samples += (block.samples.len() + dropped) as u64;
+                return Ok(Some(block));
+            }
+            capture.samples += dropped as u64;
+
+            if stopped {
+                return Ok(None);
+            }
+            std::thread::sleep(CAPTURE_POLL_INTERVAL);
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(ref mut capture) = self.capture {
+            // Dropping the stream stops the device callback
+            capture.stream = None;
+        }
+    }
+
+    fn device(&self) -> Option<AudioDevice> {
+        self.device_info.clone()
+    }
+}
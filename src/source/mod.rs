@@ -0,0 +1,294 @@
+//! Where audio comes from.
+//!
+//! An [`AudioSource`] delivers interleaved samples in timestamped blocks,
+//! whether it is a file, a microphone, a pipe or a generated signal. Live
+//! transcription consumes the blocks as they arrive; file transcription
+//! simply [`load`](AudioSource::load)s the whole source.
+
+pub mod channels;
+pub mod microphone;
+pub mod raw;
+pub mod realtime;
+pub mod synthetic;
+
+pub use channels::{channel_names, SelectChannel};
+pub use microphone::MicrophoneSource;
+pub use raw::{RawPcmSource, RawSampleFormat};
+pub use realtime::Realtime;
+pub use synthetic::{Signal, SyntheticSource};
+
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::audio::{is_stdin, load_audio_track, mix_to_mono, resample_with_quality, AudioDecoder, WHISPER_SAMPLE_RATE};
+use crate::resample::ResampleQuality;
+use crate::transcript::AudioDevice;
+
+/// Sample rate and channel count of the audio a source delivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Bits per sample the audio had before it was converted to f32, 32
+    /// for audio that was float to begin with.
+    pub bits_per_sample: u16,
+}
+
+/// A block of interleaved samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioBlock {
+    /// Position of the block's first frame, counted from the start of the
+    /// source. A block starting after the previous one ended means the
+    /// audio in between was lost.
+    pub frame: u64,
+    pub samples: Vec<f32>,
+}
+
+impl AudioBlock {
+    /// The frame just after this block, for a source with `channels`.
+    pub fn end_frame(&self, channels: u16) -> u64 {
+        self.frame + (self.samples.len() / channels.max(1) as usize) as u64
+    }
+}
+
+/// Something that can provide audio to a [`Transcriber`](crate::Transcriber).
+pub trait AudioSource {
+    /// Human-readable description of where the audio comes from, used in
+    /// transcript headers.
+    fn describe(&self) -> String;
+
+    /// Prepare to deliver audio, e.g. open the file or start the device,
+    /// and return its format.
+    fn start(&mut self) -> Result<AudioFormat>;
+
+    /// The next block of audio, waiting for it if necessary. Returns `None`
+    /// once the source has ended.
+    fn next_block(&mut self) -> Result<Option<AudioBlock>>;
+
+    /// Stop delivering new audio. Later calls to
+    /// [`next_block`](Self::next_block) return what was already captured,
+    /// then `None`.
+    fn stop(&mut self) {}
+
+    /// The device the audio is captured from, once started, for sources
+    /// that record from one.
+    fn device(&self) -> Option<AudioDevice> {
+        None
+    }
+
+    /// Read the whole source as mono f32 samples at [`WHISPER_SAMPLE_RATE`].
+    fn load(&mut self) -> Result<Vec<f32>> {
+        self.load_with_quality(ResampleQuality::default())
+    }
+
+    /// Like [`load`](Self::load), resampling at the given quality.
+    fn load_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<f32>> {
+        let format = self.start()?;
+        let channels = format.channels as usize;
+        let mut samples = Vec::new();
+        let mut next_frame = 0;
+        while let Some(block) = self.next_block()? {
+            // Keep later audio in place if some was lost
+            let gap = block.frame.saturating_sub(next_frame) as usize;
+            samples.resize(samples.len() + gap, 0.0);
+            samples.extend(mix_to_mono(&block.samples, channels));
+            next_frame = block.end_frame(format.channels);
+        }
+        Ok(resample_with_quality(&samples, format.sample_rate, WHISPER_SAMPLE_RATE, quality))
+    }
+
+    /// Read the whole source with each channel kept separate, as mono f32
+    /// samples at [`WHISPER_SAMPLE_RATE`].
+    fn load_channels(&mut self) -> Result<Vec<Vec<f32>>> {
+        self.load_channels_with_quality(ResampleQuality::default())
+    }
+
+    /// Like [`load_channels`](Self::load_channels), resampling at the given
+    /// quality.
+    fn load_channels_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<Vec<f32>>> {
+        let format = self.start()?;
+        let channels = format.channels as usize;
+        let mut planes = vec![Vec::new(); channels];
+        let mut next_frame = 0;
+        while let Some(block) = self.next_block()? {
+            let gap = block.frame.saturating_sub(next_frame) as usize;
+            for plane in &mut planes {
+                plane.resize(plane.len() + gap, 0.0);
+            }
+            for frame in block.samples.chunks_exact(channels) {
+                for (plane, &sample) in planes.iter_mut().zip(frame) {
+                    plane.push(sample);
+                }
+            }
+            next_frame = block.end_frame(format.channels);
+        }
+        Ok(planes
+            .iter()
+            .map(|plane| resample_with_quality(plane, format.sample_rate, WHISPER_SAMPLE_RATE, quality))
+            .collect())
+    }
+}
+
+impl<S: AudioSource + ?Sized> AudioSource for Box<S> {
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        (**self).start()
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        (**self).next_block()
+    }
+
+    fn stop(&mut self) {
+        (**self).stop()
+    }
+
+    fn device(&self) -> Option<AudioDevice> {
+        (**self).device()
+    }
+
+    fn load_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<f32>> {
+        (**self).load_with_quality(quality)
+    }
+
+    fn load_channels_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<Vec<f32>>> {
+        (**self).load_channels_with_quality(quality)
+    }
+}
+
+/// An audio file in any format symphonia can decode, or a container
+/// streamed on standard input if the path is `-`.
+pub struct FileSource {
+    path: PathBuf,
+    track: Option<u32>,
+    decoder: Option<AudioDecoder>,
+    frame: u64,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            track: None,
+            decoder: None,
+            frame: 0,
+        }
+    }
+
+    /// Decode the track with id `track` instead of the first audio track.
+    pub fn with_track(mut self, track: u32) -> Self {
+        self.track = Some(track);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AudioSource for FileSource {
+    fn describe(&self) -> String {
+        if is_stdin(&self.path) {
+            "stdin".to_string()
+        } else {
+            self.path.display().to_string()
+        }
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        let decoder = AudioDecoder::open_track(&self.path, self.track)?;
+        let format = AudioFormat {
+            sample_rate: decoder.sample_rate(),
+            channels: decoder.channels(),
+            bits_per_sample: decoder.bits_per_sample(),
+        };
+        self.decoder = Some(decoder);
+        self.frame = 0;
+        Ok(format)
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        // Not started, or stopped
+        let Some(ref mut decoder) = self.decoder else {
+            return Ok(None);
+        };
+        let Some(samples) = decoder.next_block()? else {
+            return Ok(None);
+        };
+        let block = AudioBlock { frame: self.frame, samples };
+        self.frame = block.end_frame(decoder.channels());
+        Ok(Some(block))
+    }
+
+    fn stop(&mut self) {
+        self.decoder = None;
+    }
+
+    fn load_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<f32>> {
+        load_audio_track(&self.path, self.track, quality)
+    }
+}
+
+/// Mono f32 samples already in memory, at any sample rate.
+pub struct PcmSource {
+    name: String,
+    samples: Vec<f32>,
+    sample_rate: u32,
+    position: usize,
+}
+
+impl PcmSource {
+    pub fn new(name: impl Into<String>, samples: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            name: name.into(),
+            samples,
+            sample_rate,
+            position: 0,
+        }
+    }
+}
+
+impl AudioSource for PcmSource {
+    fn describe(&self) -> String {
+        self.name.clone()
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        self.position = 0;
+        Ok(AudioFormat {
+            sample_rate: self.sample_rate,
+            channels: 1,
+            bits_per_sample: 32,
+        })
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        if self.position >= self.samples.len() {
+            return Ok(None);
+        }
+        // A tenth of a second at a time, like a capture device
+        let end = (self.position + (self.sample_rate as usize / 10).max(1)).min(self.samples.len());
+        let block = AudioBlock {
+            frame: self.position as u64,
+            samples: self.samples[self.position..end].to_vec(),
+        };
+        self.position = end;
+        Ok(Some(block))
+    }
+
+    fn stop(&mut self) {
+        self.position = self.samples.len();
+    }
+
+    fn load_with_quality(&mut self, quality: ResampleQuality) -> Result<Vec<f32>> {
+        let samples = std::mem::take(&mut self.samples);
+        if self.sample_rate == WHISPER_SAMPLE_RATE {
+            Ok(samples)
+        } else {
+            Ok(resample_with_quality(&samples, self.sample_rate, WHISPER_SAMPLE_RATE, quality))
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Headerless PCM read from a pipe or any other reader.
+
+use anyhow::{Context, Result};
+use std::io::{self, Read};
+
+use super::{AudioBlock, AudioFormat, AudioSource};
+
+/// Bytes requested from the reader at a time.
+const READ_SIZE: usize = 8192;
+
+/// Encoding of raw PCM samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RawSampleFormat {
+    /// Signed 16-bit little-endian
+    S16le,
+    /// 32-bit float little-endian
+    F32le,
+}
+
+impl RawSampleFormat {
+    /// Size of one sample in bytes.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            RawSampleFormat::S16le => 2,
+            RawSampleFormat::F32le => 4,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f32 {
+        match self {
+            RawSampleFormat::S16le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            RawSampleFormat::F32le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+/// Interleaved PCM samples without a header, such as `ffmpeg -f s16le`
+/// writes, delivered as soon as they are read.
+pub struct RawPcmSource<R: Read> {
+    name: String,
+    reader: R,
+    sample_format: RawSampleFormat,
+    format: AudioFormat,
+    // Bytes of a sample split across reads
+    partial: Vec<u8>,
+    samples: u64,
+    ended: bool,
+}
+
+impl RawPcmSource<io::Stdin> {
+    /// Read raw PCM from standard input.
+    pub fn stdin(sample_format: RawSampleFormat, sample_rate: u32, channels: u16) -> Self {
+        Self::new("stdin", io::stdin(), sample_format, sample_rate, channels)
+    }
+}
+
+impl<R: Read> RawPcmSource<R> {
+    pub fn new(
+        name: impl Into<String>,
+        reader: R,
+        sample_format: RawSampleFormat,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            reader,
+            sample_format,
+            format: AudioFormat {
+                sample_rate,
+                channels: channels.max(1),
+                bits_per_sample: sample_format.bytes_per_sample() as u16 * 8,
+            },
+            partial: Vec::new(),
+            samples: 0,
+            ended: false,
+        }
+    }
+}
+
+impl<R: Read> AudioSource for RawPcmSource<R> {
+    fn describe(&self) -> String {
+        self.name.clone()
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        Ok(self.format)
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        let sample_size = self.sample_format.bytes_per_sample();
+        let mut buffer = [0u8; READ_SIZE];
+        loop {
+            if self.ended {
+                return Ok(None);
+            }
+            let read = match self.reader.read(&mut buffer) {
+                Ok(0) => {
+                    // A trailing partial sample is dropped
+                    self.ended = true;
+                    return Ok(None);
+                }
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read from {}", self.name)),
+            };
+
+            self.partial.extend_from_slice(&buffer[..read]);
+            let whole = self.partial.len() - self.partial.len() % sample_size;
+            if whole == 0 {
+                continue;
+            }
+            let samples: Vec<f32> = self.partial[..whole]
+                .chunks_exact(sample_size)
+                .map(|bytes| self.sample_format.decode(bytes))
+                .collect();
+            self.partial.drain(..whole);
+
+            let block = AudioBlock {
+                frame: self.samples / self.format.channels as u64,
+                samples,
+            };
+            self.samples += block.samples.len() as u64;
+            return Ok(Some(block));
+        }
+    }
+
+    fn stop(&mut self) {
+        self.ended = true;
+    }
+}
@@ -0,0 +1,69 @@
+//! Pacing a source to real time.
+
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+use super::{AudioBlock, AudioFormat, AudioSource};
+use crate::transcript::AudioDevice;
+
+/// Delivers another source's audio no faster than real time, as if it were
+/// being captured live: each block is returned once the time it covers has
+/// passed since [`start`](AudioSource::start).
+///
+/// Wrapping a [`FileSource`](super::FileSource) replays a recording through
+/// live transcription.
+pub struct Realtime<S> {
+    inner: S,
+    format: Option<AudioFormat>,
+    started: Option<Instant>,
+}
+
+impl<S: AudioSource> Realtime<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            format: None,
+            started: None,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AudioSource> AudioSource for Realtime<S> {
+    fn describe(&self) -> String {
+        self.inner.describe()
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        let format = self.inner.start()?;
+        self.format = Some(format);
+        self.started = Some(Instant::now());
+        Ok(format)
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        let Some(block) = self.inner.next_block()? else {
+            return Ok(None);
+        };
+        if let (Some(format), Some(started)) = (self.format, self.started) {
+            let end_frame = block.end_frame(format.channels);
+            let due = started + Duration::from_secs_f64(end_frame as f64 / format.sample_rate as f64);
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep(due - now);
+            }
+        }
+        Ok(Some(block))
+    }
+
+    fn stop(&mut self) {
+        self.inner.stop();
+    }
+
+    fn device(&self) -> Option<AudioDevice> {
+        self.inner.device()
+    }
+}
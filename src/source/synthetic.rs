@@ -0,0 +1,193 @@
+//! Generated test signals.
+
+use anyhow::Result;
+use std::f32::consts::PI;
+
+use super::{AudioBlock, AudioFormat, AudioSource};
+
+/// Vowel formants (F1, F2) in Hz that synthetic syllables cycle through.
+const VOWEL_FORMANTS: [(f32, f32); 5] = [
+    (730.0, 1090.0),
+    (270.0, 2290.0),
+    (300.0, 870.0),
+    (530.0, 1840.0),
+    (640.0, 1190.0),
+];
+
+/// Pitch of each synthetic utterance in Hz, cycled through.
+const UTTERANCE_PITCHES: [f32; 4] = [110.0, 125.0, 140.0, 118.0];
+
+const SYLLABLE_SECONDS: f32 = 0.2;
+const SYLLABLES_PER_UTTERANCE: u32 = 8;
+const PAUSE_SECONDS: f32 = 0.6;
+const SPEECH_LEVEL: f32 = 0.3;
+
+/// What a [`SyntheticSource`] generates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// A sine tone at half of full scale.
+    Tone { frequency: f32 },
+    /// Speech-like sound: voiced syllables with changing vowels, grouped
+    /// into 1.6 s utterances separated by 0.6 s pauses. Not intelligible,
+    /// but has the pitch, formants and rhythm of speech.
+    Speech,
+    Silence,
+}
+
+/// A generated signal, for exercising the audio pipeline without a device
+/// or audio files. The same channel value is written to every channel.
+///
+/// Blocks are produced as fast as they are asked for; wrap the source in
+/// [`Realtime`](super::Realtime) to pace it like a live capture.
+#[derive(Debug, Clone)]
+pub struct SyntheticSource {
+    signal: Signal,
+    format: AudioFormat,
+    total_frames: u64,
+    block_frames: usize,
+    frame: u64,
+}
+
+impl SyntheticSource {
+    /// `seconds` of `signal`, at 16 kHz mono in blocks of 10 ms unless
+    /// changed with [`with_format`](Self::with_format) and
+    /// [`with_block_frames`](Self::with_block_frames).
+    pub fn new(signal: Signal, seconds: f32) -> Self {
+        let mut source = Self {
+            signal,
+            format: AudioFormat {
+                sample_rate: 16_000,
+                channels: 1,
+                bits_per_sample: 32,
+            },
+            total_frames: 0,
+            block_frames: 160,
+            frame: 0,
+        };
+        source.set_duration(seconds);
+        source
+    }
+
+    pub fn tone(frequency: f32, seconds: f32) -> Self {
+        Self::new(Signal::Tone { frequency }, seconds)
+    }
+
+    pub fn speech(seconds: f32) -> Self {
+        Self::new(Signal::Speech, seconds)
+    }
+
+    /// Generate at `sample_rate` with `channels`, keeping the duration and
+    /// 10 ms blocks.
+    pub fn with_format(mut self, sample_rate: u32, channels: u16) -> Self {
+        let seconds = self.total_frames as f32 / self.format.sample_rate as f32;
+        self.format = AudioFormat {
+            sample_rate,
+            channels: channels.max(1),
+            bits_per_sample: 32,
+        };
+        self.block_frames = (sample_rate as usize / 100).max(1);
+        self.set_duration(seconds);
+        self
+    }
+
+    pub fn with_block_frames(mut self, block_frames: usize) -> Self {
+        self.block_frames = block_frames.max(1);
+        self
+    }
+
+    /// The signal's value at `seconds` from the start.
+    pub fn value_at(&self, seconds: f32) -> f32 {
+        match self.signal {
+            Signal::Tone { frequency } => 0.5 * (2.0 * PI * frequency * seconds).sin(),
+            Signal::Speech => speech_at(seconds),
+            Signal::Silence => 0.0,
+        }
+    }
+
+    /// Whether the speech signal is sounding at `seconds`, as opposed to
+    /// pausing between utterances. Tones are always sounding.
+    pub fn is_speech_at(&self, seconds: f32) -> bool {
+        match self.signal {
+            Signal::Tone { .. } => true,
+            Signal::Speech => utterance_position(seconds).is_some(),
+            Signal::Silence => false,
+        }
+    }
+
+    fn set_duration(&mut self, seconds: f32) {
+        self.total_frames = (seconds.max(0.0) as f64 * self.format.sample_rate as f64).round() as u64;
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn describe(&self) -> String {
+        match self.signal {
+            Signal::Tone { frequency } => format!("Synthetic: {} Hz tone", frequency),
+            Signal::Speech => "Synthetic: speech".to_string(),
+            Signal::Silence => "Synthetic: silence".to_string(),
+        }
+    }
+
+    fn start(&mut self) -> Result<AudioFormat> {
+        self.frame = 0;
+        Ok(self.format)
+    }
+
+    fn next_block(&mut self) -> Result<Option<AudioBlock>> {
+        if self.frame >= self.total_frames {
+            return Ok(None);
+        }
+        let frames = (self.block_frames as u64).min(self.total_frames - self.frame);
+        let channels = self.format.channels as usize;
+        let mut samples = Vec::with_capacity(frames as usize * channels);
+        for frame in self.frame..self.frame + frames {
+            let value = self.value_at(frame as f32 / self.format.sample_rate as f32);
+            samples.extend(std::iter::repeat_n(value, channels));
+        }
+
+        let block = AudioBlock { frame: self.frame, samples };
+        self.frame += frames;
+        Ok(Some(block))
+    }
+
+    fn stop(&mut self) {
+        self.total_frames = self.frame;
+    }
+}
+
+// Utterance number and seconds into it, or `None` during a pause
+fn utterance_position(seconds: f32) -> Option<(u32, f32)> {
+    let utterance_seconds = SYLLABLE_SECONDS * SYLLABLES_PER_UTTERANCE as f32;
+    let period = utterance_seconds + PAUSE_SECONDS;
+    let utterance = (seconds / period).floor();
+    let offset = seconds - utterance * period;
+    (seconds >= 0.0 && offset < utterance_seconds).then_some((utterance as u32, offset))
+}
+
+fn speech_at(seconds: f32) -> f32 {
+    let Some((utterance, offset)) = utterance_position(seconds) else {
+        return 0.0;
+    };
+    let syllable = (offset / SYLLABLE_SECONDS) as u32;
+    let syllable_phase = offset / SYLLABLE_SECONDS - syllable as f32;
+    let (f1, f2) = VOWEL_FORMANTS[((utterance * 3 + syllable * 2) % VOWEL_FORMANTS.len() as u32) as usize];
+    let pitch = UTTERANCE_PITCHES[(utterance % UTTERANCE_PITCHES.len() as u32) as usize];
+
+    // Harmonics of the pitch, shaped by two formant resonances
+    let mut value = 0.0;
+    let mut total_power = 0.0;
+    let mut harmonic = 1;
+    while harmonic as f32 * pitch < 4000.0 {
+        let frequency = harmonic as f32 * pitch;
+        let weight = (1.0 / (1.0 + ((frequency - f1) / 100.0).powi(2))
+            + 0.5 / (1.0 + ((frequency - f2) / 150.0).powi(2)))
+            / harmonic as f32;
+        value += weight * (2.0 * PI * frequency * seconds).sin();
+        total_power += weight * weight;
+        harmonic += 1;
+    }
+
+    // Each syllable swells and fades
+    let envelope = (PI * syllable_phase).sin().powi(2);
+    (SPEECH_LEVEL * envelope * value / total_power.sqrt()).clamp(-1.0, 1.0)
+}
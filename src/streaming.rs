@@ -0,0 +1,171 @@
+//! Sliding-window bookkeeping for live transcription.
+//!
+//! Each window is transcribed from a little before the end of the last
+//! emitted segment, so words cut off at the previous window's edge are heard
+//! in full. Segments the previous window already produced are dropped, and
+//! words repeated across the seam are removed, so the emitted transcript
+//! reads continuously.
+
+use crate::transcript::Segment;
+
+/// Windows are never grown past this, so Whisper always sees under 30 s.
+const MAX_WINDOW_MS: i64 = 25_000;
+
+/// Segments ending this close to already emitted audio count as repeats.
+const REPEAT_TOLERANCE_MS: i64 = 200;
+
+/// Longest run of words compared when removing repeats across the seam.
+const MAX_REPEATED_WORDS: usize = 12;
+
+/// Most recent emitted text kept as the prompt for the next window.
+const MAX_PROMPT_CHARS: usize = 600;
+
+/// Tracks what has been emitted and decides where the next window starts.
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    overlap_ms: i64,
+    carry_prompt: bool,
+    // End of the audio covered by emitted segments (or skipped as silence)
+    committed_ms: i64,
+    // Normalised recently emitted words, for removing repeats
+    recent_words: Vec<String>,
+    prompt: String,
+}
+
+impl SlidingWindow {
+    /// `overlap_ms` is how much already transcribed audio each window
+    /// starts with. With `carry_prompt`, recently emitted text is offered as
+    /// the prompt for the next window.
+    pub fn new(overlap_ms: i64, carry_prompt: bool) -> Self {
+        Self {
+            overlap_ms: overlap_ms.max(0),
+            carry_prompt,
+            committed_ms: 0,
+            recent_words: Vec::new(),
+            prompt: String::new(),
+        }
+    }
+
+    /// Where the next window starts, in milliseconds from the start of the
+    /// recording.
+    pub fn window_start_ms(&self) -> i64 {
+        (self.committed_ms - self.overlap_ms).max(0)
+    }
+
+    /// Text to prompt Whisper with for the next window, if any.
+    pub fn prompt(&self) -> Option<&str> {
+        (self.carry_prompt && !self.prompt.is_empty()).then_some(self.prompt.as_str())
+    }
+
+    /// Take the segments transcribed from `window_start_ms..window_end_ms`
+    /// (already on the recording's timeline) and return those to emit.
+    ///
+    /// Unless `is_final`, a segment running up to the end of the window may
+    /// have been cut off and is held back to be transcribed again with the
+    /// next window.
+    pub fn accept(
+        &mut self,
+        segments: Vec<Segment>,
+        window_start_ms: i64,
+        window_end_ms: i64,
+        is_final: bool,
+    ) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = segments
+            .into_iter()
+            .filter(|s| !s.text.is_empty() && s.end_ms > self.committed_ms + REPEAT_TOLERANCE_MS)
+            .map(|mut s| {
+                s.end_ms = s.end_ms.min(window_end_ms);
+                s
+            })
+            .collect();
+
+        let window_full = window_end_ms - window_start_ms >= MAX_WINDOW_MS;
+        let mut held_back = false;
+        if !is_final && !window_full {
+            if let Some(last) = segments.last() {
+                if last.end_ms > window_end_ms - self.overlap_ms.max(REPEAT_TOLERANCE_MS) {
+                    segments.pop();
+                    held_back = true;
+                }
+            }
+        }
+
+        let mut emitted = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let segment = if segment.start_ms < self.committed_ms {
+                match self.remove_repeated_words(segment) {
+                    Some(segment) => segment,
+                    None => continue,
+                }
+            } else {
+                segment
+            };
+            self.commit(&segment);
+            emitted.push(segment);
+        }
+
+        // Nothing new was said: move past the silence so windows stay short
+        if emitted.is_empty() && !held_back {
+            let skip_to = if is_final { window_end_ms } else { window_end_ms - self.overlap_ms };
+            self.committed_ms = self.committed_ms.max(skip_to);
+        }
+
+        emitted
+    }
+
+    fn commit(&mut self, segment: &Segment) {
+        self.committed_ms = self.committed_ms.max(segment.end_ms);
+
+        self.recent_words.extend(segment.text.split_whitespace().map(normalize_word));
+        let excess = self.recent_words.len().saturating_sub(MAX_REPEATED_WORDS);
+        self.recent_words.drain(..excess);
+
+        if !self.prompt.is_empty() {
+            self.prompt.push(' ');
+        }
+        self.prompt.push_str(&segment.text.replace('\0', ""));
+        if self.prompt.len() > MAX_PROMPT_CHARS {
+            // Cut at a word boundary
+            let cut = self.prompt.len() - MAX_PROMPT_CHARS;
+            let cut = self.prompt[cut..].find(' ').map_or(cut, |i| cut + i + 1);
+            self.prompt.drain(..cut);
+        }
+    }
+
+    // Drop the words at the start of `segment` that were the last words
+    // emitted. Returns `None` if nothing new is left.
+    fn remove_repeated_words(&self, mut segment: Segment) -> Option<Segment> {
+        let words: Vec<&str> = segment.text.split_whitespace().collect();
+        let normalized: Vec<String> = words.iter().map(|w| normalize_word(w)).collect();
+
+        let longest = normalized.len().min(self.recent_words.len()).min(MAX_REPEATED_WORDS);
+        let repeated = (1..=longest)
+            .rev()
+            .find(|&k| normalized[..k] == self.recent_words[self.recent_words.len() - k..])
+            .unwrap_or(0);
+        if repeated == 0 {
+            segment.start_ms = segment.start_ms.max(self.committed_ms);
+            return Some(segment);
+        }
+        if repeated == words.len() {
+            return None;
+        }
+
+        segment.text = words[repeated..].join(" ");
+        if segment.words.len() >= repeated {
+            segment.words.drain(..repeated);
+        }
+        let new_start = segment.words.first().map_or(self.committed_ms, |w| w.start_ms);
+        segment.start_ms = segment.start_ms.max(new_start).min(segment.end_ms);
+        segment.tokens.retain(|t| t.start_ms.is_none_or(|start| start >= segment.start_ms));
+        Some(segment)
+    }
+}
+
+// Lowercase and strip punctuation so "Hello," matches "hello"
+pub(crate) fn normalize_word(word: &str) -> String {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,831 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use whisper_rs::{FullParams, WhisperContext, WhisperContextParameters, WhisperState};
+
+use crate::audio::{MIN_WHISPER_SAMPLES, WHISPER_SAMPLE_RATE};
+use crate::decoding::DecodingOptions;
+use crate::diarization::{assign_speakers, perform_speaker_diarization, DiarizationOptions};
+use crate::filter::{merge_segments, no_speech_estimate, remove_loops, remove_repeated_segments, FilterOptions};
+use crate::model::resolve_model_path;
+use crate::resample::ResampleQuality;
+use crate::source::{channel_names, AudioSource};
+use crate::transcript::{align_translations, group_words, rank_languages, LanguageProbability, Segment, Token, Transcript};
+use crate::vocabulary::Vocabulary;
+use crate::vad::{detect_speech, split_at_silences, SpeechAudio, SpeechRegion, VadOptions};
+
+/// Audio Whisper detects the language from, at the start of the speech.
+const LANGUAGE_DETECTION_SAMPLES: usize = 30 * WHISPER_SAMPLE_RATE as usize;
+
+/// Chunks of a recording transcribed in parallel are about as long as the
+/// recording divided by the jobs, within these bounds.
+const MIN_CHUNK_SECONDS: u64 = 60;
+const MAX_CHUNK_SECONDS: u64 = 600;
+
+/// What Whisper produces from the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Task {
+    /// Text in the spoken language.
+    #[default]
+    Transcribe,
+    /// English text, whatever the spoken language.
+    Translate,
+    /// Text in the spoken language, each segment with its English
+    /// translation.
+    Bilingual,
+}
+
+/// Options controlling how a [`Transcriber`] decodes audio.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriberOptions {
+    /// Language code (e.g. "en", "es", "fr"); `None` auto-detects.
+    pub language: Option<String>,
+    /// Languages auto-detection may choose from (e.g. "pt", "en"); empty
+    /// for any.
+    pub languages: Vec<String>,
+    /// Detect the language of every stretch of speech found by voice
+    /// activity detection and transcribe each in its own language, tagging
+    /// segments with it. Needs `vad`.
+    pub code_switching: bool,
+    pub task: Task,
+    /// Transcribe long recordings in chunks, split at pauses, on this many
+    /// Whisper states at once; 0 or 1 transcribes in one pass.
+    pub jobs: usize,
+    /// Label segments by speaker using pyannote diarization.
+    pub speaker_diarization: bool,
+    pub diarization: DiarizationOptions,
+    /// Print Whisper's progress while transcribing whole files.
+    pub print_progress: bool,
+    /// How carefully audio at other sample rates is converted to 16 kHz.
+    pub resample_quality: ResampleQuality,
+    /// Compute token timestamps and group them into timed words.
+    pub word_timestamps: bool,
+    /// Transcribe only the speech found by voice activity detection,
+    /// skipping silence Whisper would otherwise invent text for.
+    pub vad: Option<VadOptions>,
+    /// Decode suspect segments again and drop or clean up hallucinations.
+    pub filter: Option<FilterOptions>,
+    /// Search strategy, thresholds and prompt for every decode.
+    pub decoding: DecodingOptions,
+    /// Terms to prompt Whisper with and correct misrecognitions of.
+    pub vocabulary: Option<Vocabulary>,
+}
+
+/// A loaded Whisper model that can transcribe any number of audio sources.
+pub struct Transcriber {
+    ctx: WhisperContext,
+    model_name: String,
+    options: TranscriberOptions,
+    // Glossary of the vocabulary that fits in Whisper's prompt
+    vocabulary_prompt: Option<String>,
+}
+
+impl Transcriber {
+    /// Load the Whisper model at `model_path`.
+    pub fn new(model_path: impl AsRef<Path>, options: TranscriberOptions) -> Result<Self> {
+        let resolved_path = resolve_model_path(model_path.as_ref())?;
+        println!("Loading Whisper model: {}", resolved_path.display());
+        for language in options.language.iter().chain(&options.languages) {
+            if whisper_rs::get_lang_id(language).is_none() {
+                anyhow::bail!("Unknown language code: {}", language);
+            }
+        }
+        let ctx_params = WhisperContextParameters::default();
+        let ctx = WhisperContext::new_with_params(
+            resolved_path.to_str().context("Model path is not valid UTF-8")?,
+            ctx_params
+        )
+        .with_context(|| format!("Failed to load Whisper model from {}", resolved_path.display()))?;
+
+        let model_name = resolved_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let vocabulary_prompt = match options.vocabulary {
+            Some(ref vocabulary) => vocabulary_prompt(&ctx, vocabulary, options.decoding.initial_prompt.as_deref()),
+            None => None,
+        };
+
+        Ok(Self { ctx, model_name, options, vocabulary_prompt })
+    }
+
+    pub fn options(&self) -> &TranscriberOptions {
+        &self.options
+    }
+
+    /// File name of the loaded Whisper model.
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    /// Start an empty transcript for `source_name` with this transcriber's
+    /// model and language filled in.
+    pub fn new_transcript(&self, source_name: &str) -> Transcript {
+        let mut transcript = Transcript::new(source_name);
+        transcript.model = Some(self.model_name.clone());
+        transcript.language = self.options.language.clone();
+        transcript
+    }
+
+    /// Load `source` and transcribe it.
+    pub fn transcribe_source(&self, source: &mut dyn AudioSource) -> Result<Transcript> {
+        let audio_samples = source.load_with_quality(self.options.resample_quality)?;
+
+        if audio_samples.is_empty() {
+            anyhow::bail!("No audio samples found in {}", source.describe());
+        }
+
+        println!("Audio loaded: {} samples ({} seconds)",
+                 audio_samples.len(),
+                 audio_samples.len() as f32 / WHISPER_SAMPLE_RATE as f32);
+
+        self.transcribe(&source.describe(), &audio_samples)
+    }
+
+    /// Transcribe 16 kHz mono samples, running speaker diarization if enabled.
+    /// `source_name` is recorded in the transcript.
+    pub fn transcribe(&self, source_name: &str, audio_samples: &[f32]) -> Result<Transcript> {
+        let mut transcript = self.new_transcript(source_name);
+        transcript.audio_duration_ms = samples_to_ms(audio_samples.len());
+
+        println!("Initializing transcription...");
+        let mut state = self.ctx.create_state()
+            .context("Failed to create Whisper state")?;
+
+        let speech = self.detect_speech(audio_samples)?;
+        if let Some(ref regions) = speech {
+            print_speech_found(regions, audio_samples.len());
+        }
+
+        let (language, detected) = self.choose_language(&mut state, audio_samples, speech.as_deref())?;
+        if !detected.is_empty() {
+            transcript.language = language.map(str::to_string);
+            transcript.language_probabilities = detected;
+        }
+
+        println!("Transcribing audio (this may take a while)...");
+        let segments = self.transcribe_all(&mut state, language, audio_samples, speech.as_deref())?;
+
+        // Perform speaker diarization if enabled
+        let diarization = if self.options.speaker_diarization {
+            println!("Speaker diarization is enabled");
+            match perform_speaker_diarization(audio_samples, WHISPER_SAMPLE_RATE, &self.options.diarization) {
+                Ok(diarization) => {
+                    println!("Speaker diarization completed successfully. Found {} speaker segments.", diarization.turns.len());
+                    Some(diarization)
+                },
+                Err(e) => {
+                    eprintln!("Warning: Speaker diarization failed: {}", e);
+                    eprintln!("Continuing without speaker identification...");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        // Whisper never ran if no speech was found
+        if transcript.language.is_none() && !segments.is_empty() {
+            transcript.language = self.language(&state);
+        }
+        transcript.segments = match diarization {
+            Some(diarization) => {
+                transcript.speaker_names = diarization.speaker_names;
+                assign_speakers(
+                    segments,
+                    &diarization.turns,
+                    self.options.diarization.split_on_speaker_change,
+                )
+            }
+            None => segments,
+        };
+
+        self.strip_unrequested_word_timing(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Load `source` with its channels kept apart and transcribe each one
+    /// separately, for recordings with one speaker per channel. Segments
+    /// are merged in time order and labelled with their channel as the
+    /// speaker; speaker diarization is not used.
+    pub fn transcribe_channels(&self, source: &mut dyn AudioSource) -> Result<Transcript> {
+        let channels = source.load_channels_with_quality(self.options.resample_quality)?;
+        let longest = channels.iter().map(Vec::len).max().unwrap_or(0);
+        if longest == 0 {
+            anyhow::bail!("No audio samples found in {}", source.describe());
+        }
+
+        println!("Audio loaded: {} channels of {} seconds",
+                 channels.len(),
+                 longest as f32 / WHISPER_SAMPLE_RATE as f32);
+
+        let mut transcript = self.new_transcript(&source.describe());
+        transcript.audio_duration_ms = samples_to_ms(longest);
+        transcript.speaker_names = channel_names(channels.len());
+
+        let mut state = self.ctx.create_state()
+            .context("Failed to create Whisper state")?;
+        for (channel, samples) in channels.iter().enumerate() {
+            println!("Transcribing {} ({} of {})...",
+                     transcript.speaker_names[channel], channel + 1, channels.len());
+            let speech = self.detect_speech(samples)?;
+            if let Some(ref regions) = speech {
+                print_speech_found(regions, samples.len());
+            }
+            // Each channel may be in its own language; the first one
+            // detected is reported
+            let (language, detected) = self.choose_language(&mut state, samples, speech.as_deref())?;
+            if transcript.language_probabilities.is_empty() && !detected.is_empty() {
+                transcript.language = language.map(str::to_string);
+                transcript.language_probabilities = detected;
+            }
+
+            let segments = self.transcribe_all(&mut state, language, samples, speech.as_deref())?;
+
+            if transcript.language.is_none() && !segments.is_empty() {
+                transcript.language = self.language(&state);
+            }
+            for mut segment in segments {
+                segment.speaker = Some(channel);
+                transcript.segments.push(segment);
+            }
+        }
+        // Stable, so simultaneous segments stay in channel order
+        transcript.segments.sort_by_key(|segment| segment.start_ms);
+
+        self.strip_unrequested_word_timing(&mut transcript);
+        Ok(transcript)
+    }
+
+    /// Transcribe a short chunk of 16 kHz mono samples without diarization
+    /// or progress output.
+    pub fn transcribe_chunk(&self, audio_samples: &[f32]) -> Result<Vec<Segment>> {
+        self.transcribe_chunk_with_prompt(audio_samples, None)
+    }
+
+    /// Like [`transcribe_chunk`](Self::transcribe_chunk), but conditioning
+    /// Whisper on `initial_prompt`, typically the text that came just before,
+    /// ahead of any [`DecodingOptions::initial_prompt`].
+    pub fn transcribe_chunk_with_prompt(
+        &self,
+        audio_samples: &[f32],
+        initial_prompt: Option<&str>,
+    ) -> Result<Vec<Segment>> {
+        let mut state = self.ctx.create_state()
+            .context("Failed to create Whisper state")?;
+
+        let params = self.full_params(self.options.language.as_deref(), initial_prompt, false);
+        let speech = self.detect_speech(audio_samples)?;
+        self.run(&mut state, params, audio_samples, 0, speech.as_deref())
+    }
+
+    /// Transcribe only `regions` of 16 kHz mono samples that start at sample
+    /// `samples_start` of a longer recording, as used by live recording
+    /// with voice activity detection. Segments are timed from the start of
+    /// the recording.
+    pub fn transcribe_regions(
+        &self,
+        audio_samples: &[f32],
+        samples_start: u64,
+        regions: &[SpeechRegion],
+        initial_prompt: Option<&str>,
+    ) -> Result<Vec<Segment>> {
+        let mut state = self.ctx.create_state()
+            .context("Failed to create Whisper state")?;
+
+        let params = self.full_params(self.options.language.as_deref(), initial_prompt, false);
+        self.run(&mut state, params, audio_samples, samples_start, Some(regions))
+    }
+
+    // Find the speech in `audio_samples` if voice activity detection is on
+    fn detect_speech(&self, audio_samples: &[f32]) -> Result<Option<Vec<SpeechRegion>>> {
+        match self.options.vad {
+            Some(ref vad) => detect_speech(audio_samples, vad).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Transcribe all of `audio_samples` in `language`, in chunks on several
+    // Whisper states at once if `jobs` allows and the audio is long enough
+    fn transcribe_all(
+        &self,
+        state: &mut WhisperState,
+        language: Option<&str>,
+        audio_samples: &[f32],
+        speech: Option<&[SpeechRegion]>,
+    ) -> Result<Vec<Segment>> {
+        let cuts = self.chunk_cuts(audio_samples, speech)?;
+        if cuts.is_empty() {
+            let params = self.full_params(language, None, self.options.print_progress);
+            return self.run(state, params, audio_samples, 0, speech);
+        }
+        self.run_parallel(language, audio_samples, speech, &cuts)
+    }
+
+    // Where to cut `audio_samples` into chunks to transcribe at once, at
+    // pauses in the speech; nowhere with one job or short audio
+    fn chunk_cuts(&self, audio_samples: &[f32], speech: Option<&[SpeechRegion]>) -> Result<Vec<u64>> {
+        let total = audio_samples.len() as u64;
+        let min_chunk = MIN_CHUNK_SECONDS * WHISPER_SAMPLE_RATE as u64;
+        if self.options.jobs <= 1 || total < 2 * min_chunk {
+            return Ok(Vec::new());
+        }
+        let target = (total / self.options.jobs as u64).clamp(min_chunk, MAX_CHUNK_SECONDS * WHISPER_SAMPLE_RATE as u64);
+        Ok(match speech {
+            Some(regions) => split_at_silences(regions, total, target),
+            // Pauses are found by energy just to place the cuts
+            None => split_at_silences(&detect_speech(audio_samples, &VadOptions::default())?, total, target),
+        })
+    }
+
+    // Transcribe the chunks of `audio_samples` between `cuts` on up to
+    // `jobs` Whisper states from the one model, taking the next chunk as
+    // each finishes, and put their segments back in order
+    fn run_parallel(
+        &self,
+        language: Option<&str>,
+        audio_samples: &[f32],
+        speech: Option<&[SpeechRegion]>,
+        cuts: &[u64],
+    ) -> Result<Vec<Segment>> {
+        let total = audio_samples.len() as u64;
+        let chunks: Vec<(u64, u64)> = std::iter::once(0)
+            .chain(cuts.iter().copied())
+            .zip(cuts.iter().copied().chain(std::iter::once(total)))
+            .collect();
+        let jobs = self.options.jobs.min(chunks.len());
+        // Share the cores between the jobs unless told otherwise
+        let threads = self.options.decoding.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, |cores| cores.get()) / jobs
+        });
+        println!("Transcribing {} chunks of about {:.0} minutes, {} at a time...",
+                 chunks.len(),
+                 total as f32 / chunks.len() as f32 / WHISPER_SAMPLE_RATE as f32 / 60.0,
+                 jobs);
+
+        let next_chunk = AtomicUsize::new(0);
+        let finished = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results: Mutex<Vec<Vec<Segment>>> = Mutex::new(vec![Vec::new(); chunks.len()]);
+        let work = || -> Result<()> {
+            let mut state = self.ctx.create_state()
+                .context("Failed to create Whisper state")?;
+            while !failed.load(Ordering::Relaxed) {
+                let index = next_chunk.fetch_add(1, Ordering::Relaxed);
+                let Some(&(start, end)) = chunks.get(index) else {
+                    break;
+                };
+                let mut params = self.full_params(language, None, false);
+                params.set_n_threads(threads.max(1) as i32);
+                let regions: Option<Vec<SpeechRegion>> = speech.map(|regions| {
+                    regions
+                        .iter()
+                        .map(|region| SpeechRegion { start: region.start.max(start), end: region.end.min(end) })
+                        .filter(|region| !region.is_empty())
+                        .collect()
+                });
+                let samples = &audio_samples[start as usize..end as usize];
+                let segments = self.run(&mut state, params, samples, start, regions.as_deref())
+                    .inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+                results.lock().unwrap()[index] = segments;
+                println!("Transcribed chunk {} of {}", finished.fetch_add(1, Ordering::Relaxed) + 1, chunks.len());
+            }
+            Ok(())
+        };
+
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs).map(|_| scope.spawn(work)).collect();
+            workers.into_iter().try_for_each(|worker| worker.join().unwrap())
+        })?;
+        Ok(results.into_inner().unwrap().into_iter().flatten().collect())
+    }
+
+    // Run Whisper over `audio_samples`, or only over the `speech` in them,
+    // and collect the segments timed from sample `samples_start`'s origin
+    fn run(
+        &self,
+        state: &mut WhisperState,
+        params: FullParams,
+        audio_samples: &[f32],
+        samples_start: u64,
+        speech: Option<&[SpeechRegion]>,
+    ) -> Result<Vec<Segment>> {
+        let Some(regions) = speech else {
+            state.full(params.clone(), audio_samples)
+                .context("Transcription failed")?;
+            let segments = self.filter_segments(self.collect_segments(state)?, &params, audio_samples)?;
+            let segments = self.add_translations(state, &params, segments, audio_samples)?;
+            let offset_ms = samples_to_ms(samples_start as usize);
+            return Ok(segments
+                .into_iter()
+                .map(|segment| self.correct(segment.offset(offset_ms)))
+                .collect());
+        };
+
+        if self.options.code_switching {
+            return self.run_by_language(state, &params, audio_samples, samples_start, regions);
+        }
+        self.run_speech(state, params, audio_samples, samples_start, regions)
+    }
+
+    // Run Whisper over only `regions` of `audio_samples`
+    fn run_speech(
+        &self,
+        state: &mut WhisperState,
+        params: FullParams,
+        audio_samples: &[f32],
+        samples_start: u64,
+        regions: &[SpeechRegion],
+    ) -> Result<Vec<Segment>> {
+        let speech = SpeechAudio::extract(audio_samples, samples_start, regions);
+        if speech.is_empty() {
+            return Ok(Vec::new());
+        }
+        state.full(params.clone(), speech.samples())
+            .context("Transcription failed")?;
+        let segments = self.filter_segments(self.collect_segments(state)?, &params, speech.samples())?;
+        let segments = self.add_translations(state, &params, segments, speech.samples())?;
+        Ok(segments
+            .into_iter()
+            .map(|segment| self.correct(speech.map_segment(segment)))
+            .collect())
+    }
+
+    // Detect the language of each of `regions` and transcribe consecutive
+    // regions in the same language together, tagging their segments with it
+    fn run_by_language(
+        &self,
+        state: &mut WhisperState,
+        params: &FullParams,
+        audio_samples: &[f32],
+        samples_start: u64,
+        regions: &[SpeechRegion],
+    ) -> Result<Vec<Segment>> {
+        let mut runs: Vec<(Option<&'static str>, Vec<SpeechRegion>)> = Vec::new();
+        for region in regions {
+            let start = (region.start.saturating_sub(samples_start) as usize).min(audio_samples.len());
+            let end = (region.end.saturating_sub(samples_start) as usize).clamp(start, audio_samples.len());
+            let detected = self.detect_language(state, &audio_samples[start..end])?;
+            let language = detected.first().and_then(|detected| static_language(&detected.language));
+            match runs.last_mut() {
+                Some((last, run)) if *last == language => run.push(*region),
+                _ => runs.push((language, vec![*region])),
+            }
+        }
+
+        let mut segments = Vec::new();
+        for (language, run) in runs {
+            let mut params = params.clone();
+            params.set_language(language);
+            params.set_print_progress(false);
+            for mut segment in self.run_speech(state, params, audio_samples, samples_start, &run)? {
+                segment.language = language.map(str::to_string);
+                segments.push(segment);
+            }
+        }
+        Ok(segments)
+    }
+
+    // The requested language, otherwise the one detected at the start of
+    // the `speech` in `audio_samples` along with how likely each language was
+    fn choose_language(
+        &self,
+        state: &mut WhisperState,
+        audio_samples: &[f32],
+        speech: Option<&[SpeechRegion]>,
+    ) -> Result<(Option<&str>, Vec<LanguageProbability>)> {
+        if self.options.language.is_some() {
+            return Ok((self.options.language.as_deref(), Vec::new()));
+        }
+        let detected = match speech {
+            Some(regions) => {
+                // Only as many regions as detection listens to
+                let count = regions
+                    .iter()
+                    .scan(0, |total, region| {
+                        let before = *total;
+                        *total += region.len() as usize;
+                        Some(before)
+                    })
+                    .take_while(|&before| before < LANGUAGE_DETECTION_SAMPLES)
+                    .count();
+                let speech = SpeechAudio::extract(audio_samples, 0, &regions[..count]);
+                if speech.is_empty() {
+                    return Ok((None, Vec::new()));
+                }
+                self.detect_language(state, speech.samples())?
+            }
+            None => self.detect_language(state, audio_samples)?,
+        };
+
+        let Some(language) = detected.first().and_then(|detected| static_language(&detected.language)) else {
+            return Ok((None, Vec::new()));
+        };
+        println!(
+            "Detected language: {}",
+            detected
+                .iter()
+                .map(|detected| format!("{} {:.0}%", detected.language, detected.probability * 100.0))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok((Some(language), detected))
+    }
+
+    // How likely each language is to be spoken at the start of
+    // `audio_samples`, most likely first, among `languages` if given
+    fn detect_language(&self, state: &mut WhisperState, audio_samples: &[f32]) -> Result<Vec<LanguageProbability>> {
+        let mut samples = audio_samples[..audio_samples.len().min(LANGUAGE_DETECTION_SAMPLES)].to_vec();
+        if samples.len() < MIN_WHISPER_SAMPLES {
+            samples.resize(MIN_WHISPER_SAMPLES, 0.0);
+        }
+        let threads = self.options.decoding.thread_count();
+        state.pcm_to_mel(&samples, threads)
+            .context("Failed to compute spectrogram for language detection")?;
+        let (_, probabilities) = state.lang_detect(0, threads)
+            .context("Language detection failed")?;
+        let probabilities = probabilities
+            .iter()
+            .enumerate()
+            .filter_map(|(id, &probability)| Some((whisper_rs::get_lang_str(id as i32)?, probability)));
+        Ok(rank_languages(probabilities, &self.options.languages))
+    }
+
+    // In bilingual mode, translate `audio_samples` to English as well and
+    // pair the translated segments with the transcribed ones
+    fn add_translations(
+        &self,
+        state: &mut WhisperState,
+        params: &FullParams,
+        segments: Vec<Segment>,
+        audio_samples: &[f32],
+    ) -> Result<Vec<Segment>> {
+        if self.options.task != Task::Bilingual || segments.is_empty() {
+            return Ok(segments);
+        }
+        // The text carried between live windows is in the spoken language,
+        // so only the fixed part of the prompt is kept for the translation
+        let mut params = params.clone();
+        params.set_translate(true);
+        params.set_print_progress(false);
+        params.set_initial_prompt(&self.options.decoding.prompt(self.vocabulary_prompt.as_deref()));
+        state.full(params.clone(), audio_samples)
+            .context("Translation failed")?;
+        let translations = self.filter_segments(self.collect_segments(state)?, &params, audio_samples)?;
+        Ok(align_translations(segments, translations))
+    }
+
+    // Replace misrecognitions of vocabulary terms
+    fn correct(&self, segment: Segment) -> Segment {
+        match self.options.vocabulary {
+            Some(ref vocabulary) => vocabulary.correct(segment),
+            None => segment,
+        }
+    }
+
+    // Decode suspect segments of `audio_samples` again if the filter is on,
+    // then drop those that are most likely silence and cut loops down
+    fn filter_segments(
+        &self,
+        segments: Vec<Segment>,
+        params: &FullParams,
+        audio_samples: &[f32],
+    ) -> Result<Vec<Segment>> {
+        let Some(ref filter) = self.options.filter else {
+            return Ok(segments);
+        };
+        // Created when first needed
+        let mut retry_state = None;
+        let mut speech = None;
+
+        let mut kept = Vec::with_capacity(segments.len());
+        for segment in segments {
+            let segment = if filter.needs_fallback(&segment) {
+                let state = match retry_state {
+                    Some(ref mut state) => state,
+                    None => retry_state.insert(self.ctx.create_state()
+                        .context("Failed to create Whisper state")?),
+                };
+                self.decode_again(state, filter, params, audio_samples, segment)?
+            } else {
+                segment
+            };
+            // Decoding again can find there was nothing to say
+            if segment.text.is_empty() {
+                continue;
+            }
+
+            // Only unsure segments can be silence
+            if segment.avg_logprob < filter.logprob_threshold {
+                // Without Whisper's own estimate, go by how much of the
+                // segment's audio sounds like speech
+                let no_speech_prob = match segment.no_speech_prob {
+                    Some(probability) => probability,
+                    None => {
+                        let regions = match speech {
+                            Some(ref regions) => regions,
+                            None => speech.insert(detect_speech(audio_samples, &VadOptions::default())?),
+                        };
+                        no_speech_estimate(&segment, regions)
+                    }
+                };
+                if filter.is_silence(&segment, no_speech_prob) {
+                    continue;
+                }
+            }
+            kept.push(remove_loops(segment, filter.repetition_threshold));
+        }
+        Ok(remove_repeated_segments(kept, filter.repetition_threshold))
+    }
+
+    // Decode the audio under `segment` again at each fallback temperature
+    // until the result passes the filter, otherwise keeping the most
+    // confident attempt
+    fn decode_again(
+        &self,
+        state: &mut WhisperState,
+        filter: &FilterOptions,
+        params: &FullParams,
+        audio_samples: &[f32],
+        segment: Segment,
+    ) -> Result<Segment> {
+        let to_sample = |ms: i64| (ms.max(0) as usize * WHISPER_SAMPLE_RATE as usize / 1000).min(audio_samples.len());
+        let (start, end) = (to_sample(segment.start_ms), to_sample(segment.end_ms));
+        if start >= end {
+            return Ok(segment);
+        }
+        let mut clip = audio_samples[start..end].to_vec();
+        if clip.len() < MIN_WHISPER_SAMPLES {
+            clip.resize(MIN_WHISPER_SAMPLES, 0.0);
+        }
+
+        let (start_ms, end_ms) = (segment.start_ms, segment.end_ms);
+        let mut best = segment;
+        for temperature in filter.fallback_temperatures() {
+            let mut params = params.clone();
+            params.set_temperature(temperature);
+            params.set_temperature_inc(0.0);
+            params.set_print_progress(false);
+            state.full(params, &clip)
+                .context("Transcription failed")?;
+
+            let Some(mut attempt) = merge_segments(self.collect_segments(state)?) else {
+                continue;
+            };
+            attempt = attempt.offset(samples_to_ms(start));
+            attempt.end_ms = attempt.end_ms.min(end_ms).max(start_ms);
+            if !filter.needs_fallback(&attempt) {
+                return Ok(attempt);
+            }
+            if attempt.avg_logprob > best.avg_logprob {
+                best = attempt;
+            }
+        }
+        Ok(best)
+    }
+
+    fn full_params<'a>(
+        &'a self,
+        language: Option<&'a str>,
+        initial_prompt: Option<&str>,
+        print_progress: bool,
+    ) -> FullParams<'a, 'a> {
+        let prompt = [initial_prompt, self.vocabulary_prompt.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut params = self.options.decoding.full_params(
+            language,
+            Some(&prompt),
+            self.word_timing(),
+            print_progress,
+        );
+        params.set_translate(self.options.task == Task::Translate);
+        params
+    }
+
+    // Word timings are also computed to split segments between speakers,
+    // but only kept if asked for
+    fn strip_unrequested_word_timing(&self, transcript: &mut Transcript) {
+        if self.word_timing() && !self.options.word_timestamps {
+            for segment in &mut transcript.segments {
+                segment.words.clear();
+                for token in &mut segment.tokens {
+                    token.start_ms = None;
+                    token.end_ms = None;
+                }
+            }
+        }
+    }
+
+    // Whether token timestamps are needed, for output or to split segments
+    // between speakers
+    fn word_timing(&self) -> bool {
+        self.options.word_timestamps
+            || (self.options.speaker_diarization && self.options.diarization.split_on_speaker_change)
+    }
+
+    // The requested language, or the one Whisper detected
+    fn language(&self, state: &WhisperState) -> Option<String> {
+        self.options.language.clone().or_else(|| {
+            let lang_id = state.full_lang_id_from_state().ok()?;
+            whisper_rs::get_lang_str(lang_id).map(str::to_string)
+        })
+    }
+
+    fn collect_segments(&self, state: &WhisperState) -> Result<Vec<Segment>> {
+        let num_segments = state.full_n_segments()
+            .context("Failed to get number of segments")?;
+
+        let mut segments = Vec::with_capacity(num_segments as usize);
+        for i in 0..num_segments {
+            let text = state.full_get_segment_text(i)
+                .context("Failed to get segment text")?;
+            // Whisper timestamps are in centiseconds
+            let start_timestamp = state.full_get_segment_t0(i)
+                .context("Failed to get segment start time")?;
+            let end_timestamp = state.full_get_segment_t1(i)
+                .context("Failed to get segment end time")?;
+
+            let mut segment = Segment::new(start_timestamp * 10, end_timestamp * 10, text.trim())
+                .with_tokens(self.collect_tokens(state, i)?);
+            if self.word_timing() {
+                segment.words = group_words(&segment.tokens);
+            }
+            segments.push(segment);
+        }
+
+        Ok(segments)
+    }
+
+    fn collect_tokens(&self, state: &WhisperState, segment: i32) -> Result<Vec<Token>> {
+        let num_tokens = state.full_n_tokens(segment)
+            .context("Failed to get number of tokens")?;
+
+        let mut tokens = Vec::with_capacity(num_tokens as usize);
+        for i in 0..num_tokens {
+            let data = state.full_get_token_data(segment, i)
+                .context("Failed to get token data")?;
+            // Skip special and timestamp tokens, which all come after EOT
+            if data.id >= self.ctx.token_eot() {
+                continue;
+            }
+            let text = state.full_get_token_text_lossy(segment, i)
+                .context("Failed to get token text")?;
+            // Token timestamps are in centiseconds, and -1 when not computed
+            let timestamp = |t: i64| (self.word_timing() && t >= 0).then_some(t * 10);
+            tokens.push(Token {
+                id: data.id,
+                text,
+                probability: data.p,
+                logprob: data.plog,
+                start_ms: timestamp(data.t0),
+                end_ms: timestamp(data.t1),
+            });
+        }
+
+        Ok(tokens)
+    }
+}
+
+// A glossary of the vocabulary within half of Whisper's prompt, less the
+// initial prompt, leaving the rest for text carried between live windows
+fn vocabulary_prompt(ctx: &WhisperContext, vocabulary: &Vocabulary, initial_prompt: Option<&str>) -> Option<String> {
+    // Whisper keeps at most this many prompt tokens
+    let max_tokens = (ctx.n_text_ctx() / 2 - 1).max(0) as usize;
+    let count = |text: &str| ctx.tokenize(text, max_tokens).map(|tokens| tokens.len()).ok();
+
+    let Some((prompt, listed)) = vocabulary.prompt_within(max_tokens / 2, initial_prompt, count) else {
+        println!("The initial prompt leaves no room for the vocabulary glossary; terms are only corrected");
+        return None;
+    };
+    if listed < vocabulary.terms().len() {
+        println!(
+            "Prompting with {} of {} vocabulary terms; the rest are only corrected",
+            listed,
+            vocabulary.terms().len()
+        );
+    }
+    Some(prompt)
+}
+
+// The code of `language` as whisper.cpp knows it, which outlives the params
+fn static_language(language: &str) -> Option<&'static str> {
+    whisper_rs::get_lang_str(whisper_rs::get_lang_id(language)?)
+}
+
+fn print_speech_found(regions: &[SpeechRegion], total_samples: usize) {
+    let speech: u64 = regions.iter().map(SpeechRegion::len).sum();
+    println!("Voice activity detection: {:.1} of {:.1} seconds are speech, in {} regions",
+             speech as f32 / WHISPER_SAMPLE_RATE as f32,
+             total_samples as f32 / WHISPER_SAMPLE_RATE as f32,
+             regions.len());
+}
+
+/// Convert a number of samples at [`WHISPER_SAMPLE_RATE`] to milliseconds.
+pub(crate) fn samples_to_ms(samples: usize) -> i64 {
+    (samples as u64 * 1000 / WHISPER_SAMPLE_RATE as u64) as i64
+}
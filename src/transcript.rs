@@ -0,0 +1,293 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// The result of transcribing one audio source. Every output format is
+/// rendered from this.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub segments: Vec<Segment>,
+    /// Language code Whisper transcribed in, either as requested or detected.
+    pub language: Option<String>,
+    /// The languages detected at the start of the speech, most likely
+    /// first. Empty when the language was given.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub language_probabilities: Vec<LanguageProbability>,
+    /// Description of the audio source (file path, input device, ...).
+    pub source: String,
+    /// The input device and stream config, for live recordings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<AudioDevice>,
+    /// When transcription (or recording, in live mode) started.
+    pub created_at: DateTime<Local>,
+    /// File name of the Whisper model used.
+    pub model: Option<String>,
+    /// Length of the transcribed audio in milliseconds.
+    pub audio_duration_ms: i64,
+    /// Names of speakers matched against enrolled voice profiles, indexed
+    /// by speaker. Empty when speakers are only numbered.
+    #[serde(default)]
+    pub speaker_names: Vec<String>,
+}
+
+impl Transcript {
+    /// Create an empty transcript for `source`, timestamped now.
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            segments: Vec::new(),
+            language: None,
+            language_probabilities: Vec::new(),
+            source: source.into(),
+            device: None,
+            created_at: Local::now(),
+            model: None,
+            audio_duration_ms: 0,
+            speaker_names: Vec::new(),
+        }
+    }
+
+    /// End time of the last segment, in milliseconds.
+    pub fn end_ms(&self) -> i64 {
+        self.segments.iter().map(|s| s.end_ms).max().unwrap_or(0)
+    }
+
+    /// Distinct speakers in order of first appearance.
+    pub fn speakers(&self) -> Vec<usize> {
+        let mut speakers = Vec::new();
+        for speaker in self.segments.iter().filter_map(|s| s.speaker) {
+            if !speakers.contains(&speaker) {
+                speakers.push(speaker);
+            }
+        }
+        speakers
+    }
+}
+
+/// The audio input a live transcript was recorded from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioDevice {
+    /// Audio host (backend) the device belongs to, e.g. "ALSA".
+    pub host: String,
+    pub name: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Sample format of the stream, e.g. "f32" or "i16".
+    pub sample_format: String,
+}
+
+/// How likely Whisper found it that `language` is spoken.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageProbability {
+    /// Language code, e.g. "pt".
+    pub language: String,
+    pub probability: f32,
+}
+
+/// A piece of transcribed speech with its position in the audio.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    /// Start of the segment in milliseconds from the beginning of the audio.
+    pub start_ms: i64,
+    /// End of the segment in milliseconds from the beginning of the audio.
+    pub end_ms: i64,
+    /// Zero-based speaker index, if speaker diarization assigned one.
+    pub speaker: Option<usize>,
+    /// Language code the segment was transcribed in, when code switching
+    /// detected it for this stretch of speech.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Transcribed text, trimmed of surrounding whitespace.
+    pub text: String,
+    /// English translation of `text`, in bilingual mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+    /// The text tokens Whisper decoded for this segment (special and
+    /// timestamp tokens excluded).
+    pub tokens: Vec<Token>,
+    /// Mean log-probability of `tokens`.
+    pub avg_logprob: f32,
+    /// Probability that the segment contains no speech. whisper.cpp only
+    /// reports this with newer bindings, so it may be absent.
+    pub no_speech_prob: Option<f32>,
+    /// Word-level timing, present when word timestamps were requested.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+}
+
+impl Segment {
+    /// Create a segment with no speaker or decoder metadata.
+    pub fn new(start_ms: i64, end_ms: i64, text: impl Into<String>) -> Self {
+        Self {
+            start_ms,
+            end_ms,
+            speaker: None,
+            language: None,
+            text: text.into(),
+            translation: None,
+            tokens: Vec::new(),
+            avg_logprob: 0.0,
+            no_speech_prob: None,
+            words: Vec::new(),
+        }
+    }
+
+    /// Set the segment's tokens and their mean log-probability.
+    pub fn with_tokens(mut self, tokens: Vec<Token>) -> Self {
+        self.avg_logprob = if tokens.is_empty() {
+            0.0
+        } else {
+            tokens.iter().map(|t| t.logprob).sum::<f32>() / tokens.len() as f32
+        };
+        self.tokens = tokens;
+        self
+    }
+
+    /// Shift the segment by `offset_ms`, e.g. to place a live chunk on the
+    /// session timeline.
+    pub fn offset(mut self, offset_ms: i64) -> Self {
+        self.start_ms += offset_ms;
+        self.end_ms += offset_ms;
+        for token in &mut self.tokens {
+            token.start_ms = token.start_ms.map(|t| t + offset_ms);
+            token.end_ms = token.end_ms.map(|t| t + offset_ms);
+        }
+        for word in &mut self.words {
+            word.start_ms += offset_ms;
+            word.end_ms += offset_ms;
+        }
+        self
+    }
+
+    pub fn duration_ms(&self) -> i64 {
+        self.end_ms - self.start_ms
+    }
+}
+
+/// A single decoded Whisper token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Token {
+    pub id: i32,
+    pub text: String,
+    /// Probability Whisper assigned to the token.
+    pub probability: f32,
+    /// Log of `probability`.
+    pub logprob: f32,
+    /// Start of the token in milliseconds, when token timestamps are on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_ms: Option<i64>,
+    /// End of the token in milliseconds, when token timestamps are on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_ms: Option<i64>,
+}
+
+/// A word with its timing and how confident Whisper was in it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Word {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// The word, including any punctuation attached to it.
+    pub text: String,
+    /// Mean probability of the word's tokens.
+    pub probability: f32,
+}
+
+/// Group timed tokens into words. A token starting with whitespace begins a
+/// new word; any other token (word pieces, punctuation) joins the previous
+/// one. Tokens without timestamps are ignored.
+pub fn group_words(tokens: &[Token]) -> Vec<Word> {
+    let mut words: Vec<Word> = Vec::new();
+    // Number of tokens in the last word, for its mean probability
+    let mut token_count = 0;
+
+    for token in tokens {
+        let (Some(start_ms), Some(end_ms)) = (token.start_ms, token.end_ms) else {
+            continue;
+        };
+        let starts_word = token.text.starts_with(char::is_whitespace);
+        match words.last_mut() {
+            Some(word) if !starts_word => {
+                word.text.push_str(&token.text);
+                word.end_ms = word.end_ms.max(end_ms);
+                word.probability = (word.probability * token_count as f32 + token.probability)
+                    / (token_count + 1) as f32;
+                token_count += 1;
+            }
+            _ => {
+                words.push(Word {
+                    start_ms,
+                    end_ms,
+                    text: token.text.trim_start().to_string(),
+                    probability: token.probability,
+                });
+                token_count = 1;
+            }
+        }
+    }
+
+    // Drop words that were only whitespace
+    words.retain(|word| !word.text.trim().is_empty());
+    for word in &mut words {
+        word.text = word.text.trim_end().to_string();
+    }
+    words
+}
+
+/// Pair each segment of `translations` with the segment of `segments` it
+/// overlaps most, or is nearest to, joining the text of all translations
+/// paired with one segment.
+pub fn align_translations(mut segments: Vec<Segment>, translations: Vec<Segment>) -> Vec<Segment> {
+    for translated in translations {
+        if translated.text.is_empty() {
+            continue;
+        }
+        // Negative when they don't overlap, the gap between them
+        let overlap = |segment: &Segment| {
+            segment.end_ms.min(translated.end_ms) - segment.start_ms.max(translated.start_ms)
+        };
+        let Some(segment) = segments
+            .iter_mut()
+            .rev()
+            .max_by_key(|segment| overlap(segment))
+        else {
+            break;
+        };
+        match segment.translation {
+            Some(ref mut translation) => {
+                translation.push(' ');
+                translation.push_str(&translated.text);
+            }
+            None => segment.translation = Some(translated.text),
+        }
+    }
+    segments
+}
+
+/// Languages below this probability are left out of detection reports.
+const MIN_REPORTED_PROBABILITY: f32 = 0.01;
+
+/// Rank detected `probabilities` by language code, most likely first. With
+/// `candidates`, only those languages are kept and their probabilities
+/// scaled to add up to 1. Unlikely languages other than the first are
+/// left out.
+pub fn rank_languages<'a>(
+    probabilities: impl IntoIterator<Item = (&'a str, f32)>,
+    candidates: &[String],
+) -> Vec<LanguageProbability> {
+    let mut ranked: Vec<LanguageProbability> = probabilities
+        .into_iter()
+        .filter(|(language, _)| candidates.is_empty() || candidates.iter().any(|c| c == language))
+        .map(|(language, probability)| LanguageProbability {
+            language: language.to_string(),
+            probability,
+        })
+        .collect();
+    let total: f32 = ranked.iter().map(|l| l.probability).sum();
+    if !candidates.is_empty() && total > 0.0 {
+        for language in &mut ranked {
+            language.probability /= total;
+        }
+    }
+    ranked.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+    let mut first = true;
+    ranked.retain(|language| std::mem::take(&mut first) || language.probability >= MIN_REPORTED_PROBABILITY);
+    ranked
+}
@@ -0,0 +1,558 @@
+//! Voice activity detection.
+//!
+//! Whisper tends to invent text such as "Thank you." or loops of repeated
+//! phrases for stretches of audio without speech. A
+//! [`VoiceActivityDetector`] finds the regions of 16 kHz audio that contain
+//! speech so only those are transcribed, and [`SpeechAudio`] moves the
+//! resulting timestamps from the speech-only audio back to the original
+//! timeline.
+//!
+//! Speech is told apart from the background by frame energy, or by the
+//! Silero VAD model when one is given.
+
+use anyhow::{Context, Result};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use crate::audio::{MIN_WHISPER_SAMPLES, WHISPER_SAMPLE_RATE};
+use crate::transcript::Segment;
+
+/// Energy is measured over frames of 30 ms.
+const ENERGY_FRAME_SAMPLES: usize = 480;
+
+/// The noise floor is the quietest frame within this many seconds.
+const NOISE_FLOOR_SECONDS: usize = 3;
+
+/// Frames quieter than this, in dBFS, are never speech.
+const MIN_SPEECH_DB: f32 = -50.0;
+
+/// Frames this far above the noise floor, in dB, have a speech probability
+/// of 0, rising to 1 at `FULL_SPEECH_DB`.
+const NO_SPEECH_DB: f32 = 3.0;
+const FULL_SPEECH_DB: f32 = 12.0;
+
+/// Silero VAD takes 32 ms frames at 16 kHz, preceded by the last 4 ms of
+/// the previous frame.
+const SILERO_FRAME_SAMPLES: usize = 512;
+const SILERO_CONTEXT_SAMPLES: usize = 64;
+const SILERO_STATE_SIZE: usize = 2 * 128;
+
+/// Once in speech, frames must drop this far below the threshold to start
+/// a pause.
+const THRESHOLD_HYSTERESIS: f32 = 0.15;
+
+/// Options for finding speech.
+#[derive(Debug, Clone)]
+pub struct VadOptions {
+    /// Speech probability from which a frame counts as speech, 0.0 to 1.0.
+    pub threshold: f32,
+    /// Shorter bursts of sound are ignored, in milliseconds.
+    pub min_speech_ms: u32,
+    /// Shorter pauses don't end a region of speech, in milliseconds.
+    pub min_silence_ms: u32,
+    /// Audio kept either side of each region so quiet word edges aren't
+    /// cut off, in milliseconds.
+    pub speech_pad_ms: u32,
+    /// Silero VAD ONNX model to detect speech with instead of frame energy.
+    pub model: Option<PathBuf>,
+}
+
+impl Default for VadOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            min_speech_ms: 250,
+            min_silence_ms: 500,
+            speech_pad_ms: 200,
+            model: None,
+        }
+    }
+}
+
+/// A stretch of speech, in samples at [`WHISPER_SAMPLE_RATE`] from the
+/// start of the audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpeechRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl SpeechRegion {
+    pub fn len(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Scores fixed-size frames of 16 kHz audio
+trait FrameDetector: Send {
+    fn frame_samples(&self) -> usize;
+
+    // Probability that `frame` contains speech
+    fn speech_probability(&mut self, frame: &[f32]) -> Result<f32>;
+}
+
+// Speech is whatever stands out from the background noise, taken to be
+// the quietest recent frame
+struct EnergyDetector {
+    levels: VecDeque<f32>,
+}
+
+impl EnergyDetector {
+    fn new() -> Self {
+        Self { levels: VecDeque::new() }
+    }
+}
+
+impl FrameDetector for EnergyDetector {
+    fn frame_samples(&self) -> usize {
+        ENERGY_FRAME_SAMPLES
+    }
+
+    fn speech_probability(&mut self, frame: &[f32]) -> Result<f32> {
+        let power = frame.iter().map(|&s| s * s).sum::<f32>() / frame.len() as f32;
+        let level = 10.0 * (power + 1e-10).log10();
+
+        let window = NOISE_FLOOR_SECONDS * WHISPER_SAMPLE_RATE as usize / ENERGY_FRAME_SAMPLES;
+        if self.levels.len() == window {
+            self.levels.pop_front();
+        }
+        self.levels.push_back(level);
+        let noise_floor = self.levels.iter().copied().fold(f32::INFINITY, f32::min);
+
+        if level < MIN_SPEECH_DB {
+            return Ok(0.0);
+        }
+        Ok(((level - noise_floor - NO_SPEECH_DB) / (FULL_SPEECH_DB - NO_SPEECH_DB)).clamp(0.0, 1.0))
+    }
+}
+
+// The Silero VAD model (v5), which carries a recurrent state from frame to
+// frame
+struct SileroDetector {
+    session: Session,
+    state: Vec<f32>,
+    context: Vec<f32>,
+}
+
+impl SileroDetector {
+    fn new(model_path: &Path) -> Result<Self> {
+        let session = Session::builder()
+            .and_then(|builder| builder.with_intra_threads(1))
+            .and_then(|builder| builder.commit_from_file(model_path))
+            .with_context(|| format!("Failed to load Silero VAD model from {}", model_path.display()))?;
+        Ok(Self {
+            session,
+            state: vec![0.0; SILERO_STATE_SIZE],
+            context: vec![0.0; SILERO_CONTEXT_SAMPLES],
+        })
+    }
+}
+
+impl FrameDetector for SileroDetector {
+    fn frame_samples(&self) -> usize {
+        SILERO_FRAME_SAMPLES
+    }
+
+    fn speech_probability(&mut self, frame: &[f32]) -> Result<f32> {
+        let mut input = Vec::with_capacity(SILERO_CONTEXT_SAMPLES + frame.len());
+        input.extend_from_slice(&self.context);
+        input.extend_from_slice(frame);
+
+        let input = Tensor::from_array(([1, input.len()], input))?;
+        let state = Tensor::from_array(([2, 1, SILERO_STATE_SIZE / 2], self.state.clone()))?;
+        let sample_rate = Tensor::from_array(((), vec![WHISPER_SAMPLE_RATE as i64]))?;
+        let outputs = self
+            .session
+            .run(ort::inputs!["input" => input, "state" => state, "sr" => sample_rate])
+            .context("Silero VAD failed")?;
+
+        let (_, probability) = outputs["output"].try_extract_tensor::<f32>()?;
+        let probability = probability.first().copied().unwrap_or(0.0);
+        let (_, state) = outputs["stateN"].try_extract_tensor::<f32>()?;
+        self.state.copy_from_slice(state);
+        self.context.copy_from_slice(&frame[frame.len() - SILERO_CONTEXT_SAMPLES..]);
+        Ok(probability)
+    }
+}
+
+/// Finds speech in 16 kHz mono audio fed to it in blocks of any size.
+///
+/// Speech starts at the first frame reaching the threshold and ends once
+/// the audio has stayed clearly below it for the minimum pause, so a
+/// region is reported a little after it ends.
+pub struct VoiceActivityDetector {
+    detector: Box<dyn FrameDetector>,
+    threshold: f32,
+    min_speech: u64,
+    min_silence: u64,
+    pad: u64,
+    // Samples not yet making up a whole frame
+    pending: Vec<f32>,
+    // Samples taken into frames so far
+    position: u64,
+    // Start of the speech being heard, and of the pause in it, if any
+    speech_start: Option<u64>,
+    silence_start: Option<u64>,
+    // End of the last region returned, which the next one can't pad into
+    last_end: u64,
+    // Position and speech probability of each frame of the current speech
+    frames: Vec<(u64, f32)>,
+}
+
+impl VoiceActivityDetector {
+    /// Load the detector `options` ask for.
+    pub fn new(options: &VadOptions) -> Result<Self> {
+        let detector: Box<dyn FrameDetector> = match options.model {
+            Some(ref path) => Box::new(SileroDetector::new(path)?),
+            None => Box::new(EnergyDetector::new()),
+        };
+        let samples = |ms: u32| ms as u64 * WHISPER_SAMPLE_RATE as u64 / 1000;
+        Ok(Self {
+            detector,
+            threshold: options.threshold,
+            min_speech: samples(options.min_speech_ms),
+            min_silence: samples(options.min_silence_ms),
+            pad: samples(options.speech_pad_ms),
+            pending: Vec::new(),
+            position: 0,
+            speech_start: None,
+            silence_start: None,
+            last_end: 0,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Feed the samples following those fed so far, and return the regions
+    /// of speech that have ended since.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Vec<SpeechRegion>> {
+        let frame_samples = self.detector.frame_samples();
+        self.pending.extend_from_slice(samples);
+
+        let mut regions = Vec::new();
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= frame_samples {
+            let frame = &self.pending[consumed..consumed + frame_samples];
+            let probability = self.detector.speech_probability(frame)?;
+            let frame_start = self.position;
+            consumed += frame_samples;
+            self.position += frame_samples as u64;
+
+            if probability >= self.threshold {
+                self.silence_start = None;
+                if self.speech_start.is_none() {
+                    self.speech_start = Some(frame_start);
+                    self.frames.clear();
+                }
+            } else if self.speech_start.is_some()
+                && self.silence_start.is_none()
+                && probability < self.threshold - THRESHOLD_HYSTERESIS
+            {
+                self.silence_start = Some(frame_start);
+            }
+
+            if let Some(speech_start) = self.speech_start {
+                self.frames.push((frame_start, probability));
+                if let Some(silence_start) = self.silence_start {
+                    if self.position - silence_start >= self.min_silence {
+                        regions.extend(self.end_speech(speech_start, silence_start));
+                    }
+                }
+            }
+        }
+        self.pending.drain(..consumed);
+        Ok(regions)
+    }
+
+    /// The audio has ended: return the speech still going on, if any.
+    pub fn finish(&mut self) -> Option<SpeechRegion> {
+        // A last partial frame counts as part of the speech it follows
+        self.position += self.pending.len() as u64;
+        self.pending.clear();
+        let speech_start = self.speech_start?;
+        let speech_end = self.silence_start.unwrap_or(self.position);
+        self.end_speech(speech_start, speech_end)
+    }
+
+    /// End the speech going on, if any, at its quietest frame after sample
+    /// `after`, for when speech runs on too long without a pause. The speech
+    /// after the cut continues as a new region.
+    pub fn split(&mut self, after: u64) -> Option<SpeechRegion> {
+        let speech_start = self.speech_start?;
+        let frame_samples = self.detector.frame_samples() as u64;
+        let cut = self
+            .frames
+            .iter()
+            .filter(|&&(frame_start, _)| frame_start >= after)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(self.position, |&(frame_start, _)| frame_start + frame_samples / 2);
+
+        let region = SpeechRegion {
+            start: speech_start.saturating_sub(self.pad).max(self.last_end),
+            end: cut,
+        };
+        self.last_end = cut;
+        self.speech_start = Some(cut);
+        if self.silence_start.is_some_and(|silence_start| silence_start < cut) {
+            self.silence_start = None;
+        }
+        self.frames.retain(|&(frame_start, _)| frame_start >= cut);
+        Some(region)
+    }
+
+    /// Samples fed so far that have been judged.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Samples fed so far, including a partial frame waiting to be judged.
+    pub fn fed(&self) -> u64 {
+        self.position + self.pending.len() as u64
+    }
+
+    /// Whether speech is going on at the end of the audio judged so far.
+    pub fn in_speech(&self) -> bool {
+        self.speech_start.is_some()
+    }
+
+    fn end_speech(&mut self, speech_start: u64, speech_end: u64) -> Option<SpeechRegion> {
+        self.speech_start = None;
+        self.silence_start = None;
+        self.frames.clear();
+        if speech_end - speech_start < self.min_speech {
+            return None;
+        }
+        let region = SpeechRegion {
+            start: speech_start.saturating_sub(self.pad).max(self.last_end),
+            end: (speech_end + self.pad).min(self.position),
+        };
+        self.last_end = region.end;
+        Some(region)
+    }
+}
+
+/// Find the regions of speech in 16 kHz mono `samples`.
+pub fn detect_speech(samples: &[f32], options: &VadOptions) -> Result<Vec<SpeechRegion>> {
+    let mut detector = VoiceActivityDetector::new(options)?;
+    let mut regions = detector.push(samples)?;
+    regions.extend(detector.finish());
+    Ok(regions)
+}
+
+/// Where to cut `total` samples of audio into chunks of about `target`
+/// samples: each cut is in the middle of the longest pause between
+/// `regions` of speech within half a chunk of where the chunk would end,
+/// so chunks are between half and one and a half times `target` long.
+pub fn split_at_silences(regions: &[SpeechRegion], total: u64, target: u64) -> Vec<u64> {
+    let target = target.max(1);
+    // Pauses before, between and after the regions
+    let pauses: Vec<(u64, u64)> = std::iter::once(0)
+        .chain(regions.iter().map(|region| region.end))
+        .zip(regions.iter().map(|region| region.start).chain(std::iter::once(total)))
+        .filter(|&(start, end)| end > start)
+        .collect();
+
+    let mut cuts = Vec::new();
+    let mut start = 0;
+    while total - start > target + target / 2 {
+        let ideal = start + target;
+        let cut = pauses
+            .iter()
+            .map(|&(pause_start, pause_end)| ((pause_start + pause_end) / 2, pause_end - pause_start))
+            .filter(|&(middle, _)| middle > ideal - target / 2 && middle < ideal + target / 2)
+            .max_by_key(|&(middle, length)| (length, std::cmp::Reverse(middle.abs_diff(ideal))))
+            .map_or(ideal, |(middle, _)| middle);
+        cuts.push(cut);
+        start = cut;
+    }
+    cuts
+}
+
+/// The speech cut out of a stretch of audio and joined up, remembering
+/// where each piece came from.
+#[derive(Debug, Clone, Default)]
+pub struct SpeechAudio {
+    samples: Vec<f32>,
+    // (start in `samples`, start on the original timeline, length)
+    pieces: Vec<(u64, u64, u64)>,
+}
+
+impl SpeechAudio {
+    /// Join the parts of `samples` inside `regions`. `samples` start at
+    /// sample `samples_start` of the timeline the regions are on.
+    pub fn extract(samples: &[f32], samples_start: u64, regions: &[SpeechRegion]) -> Self {
+        let samples_end = samples_start + samples.len() as u64;
+        let mut speech = Self::default();
+        for region in regions {
+            let start = region.start.max(samples_start);
+            let end = region.end.min(samples_end);
+            if start >= end {
+                continue;
+            }
+            speech.pieces.push((speech.samples.len() as u64, start, end - start));
+            speech
+                .samples
+                .extend_from_slice(&samples[(start - samples_start) as usize..(end - samples_start) as usize]);
+        }
+        if !speech.samples.is_empty() && speech.samples.len() < MIN_WHISPER_SAMPLES {
+            speech.samples.resize(MIN_WHISPER_SAMPLES, 0.0);
+        }
+        speech
+    }
+
+    /// The joined speech, padded with silence if it is very short.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    /// Seconds of speech, excluding padding.
+    pub fn duration_seconds(&self) -> f32 {
+        self.pieces.iter().map(|&(_, _, len)| len).sum::<u64>() as f32 / WHISPER_SAMPLE_RATE as f32
+    }
+
+    /// Move a segment transcribed from [`samples`](Self::samples) to the
+    /// original timeline.
+    pub fn map_segment(&self, mut segment: Segment) -> Segment {
+        segment.start_ms = self.to_original_ms(segment.start_ms, false);
+        segment.end_ms = self.to_original_ms(segment.end_ms, true);
+        for token in &mut segment.tokens {
+            token.start_ms = token.start_ms.map(|t| self.to_original_ms(t, false));
+            token.end_ms = token.end_ms.map(|t| self.to_original_ms(t, true));
+        }
+        for word in &mut segment.words {
+            word.start_ms = self.to_original_ms(word.start_ms, false);
+            word.end_ms = self.to_original_ms(word.end_ms, true);
+        }
+        segment
+    }
+
+    // A time where two pieces join is the start of the later piece, or the
+    // end of the earlier one when `is_end`
+    fn to_original_ms(&self, ms: i64, is_end: bool) -> i64 {
+        let position = ms.max(0) as u64 * WHISPER_SAMPLE_RATE as u64 / 1000;
+        let piece = self
+            .pieces
+            .iter()
+            .rev()
+            .find(|&&(start, _, _)| if is_end { start < position } else { start <= position })
+            .or(self.pieces.first());
+        let Some(&(start, original_start, len)) = piece else {
+            return ms;
+        };
+        let original = original_start + position.saturating_sub(start).min(len);
+        (original * 1000 / WHISPER_SAMPLE_RATE as u64) as i64
+    }
+}
+
+/// A stretch of live audio to transcribe, from `start` to `end` on the
+/// 16 kHz timeline, and the speech in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeechWindow {
+    pub start: u64,
+    pub end: u64,
+    pub regions: Vec<SpeechRegion>,
+}
+
+/// Splits live audio into windows that end at pauses in speech, skipping
+/// audio without any.
+///
+/// A window ends at the first pause once it is at least the target length,
+/// or sooner if the speaker falls silent for a while. Speech running on
+/// past the maximum length without a pause is cut at its quietest point.
+pub struct SpeechChunker {
+    vad: VoiceActivityDetector,
+    target: u64,
+    max: u64,
+    flush_after: u64,
+    pad: u64,
+    // Start of the audio not yet handed out in a window
+    start: u64,
+    regions: Vec<SpeechRegion>,
+}
+
+impl SpeechChunker {
+    /// Windows of about `target_samples`, at most `max_samples`.
+    pub fn new(options: &VadOptions, target_samples: u64, max_samples: u64) -> Result<Self> {
+        let vad = VoiceActivityDetector::new(options)?;
+        let pad = vad.pad;
+        Ok(Self {
+            vad,
+            target: target_samples,
+            max: max_samples.max(target_samples),
+            // A second of silence means the speaker has finished for now
+            flush_after: WHISPER_SAMPLE_RATE as u64,
+            pad,
+            start: 0,
+            regions: Vec::new(),
+        })
+    }
+
+    /// Feed the samples following those fed so far, and return a window
+    /// if one is now complete.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Option<SpeechWindow>> {
+        let ended = self.vad.push(samples)?;
+        self.regions.extend(ended);
+        let position = self.vad.position();
+
+        if let Some(last) = self.regions.last() {
+            let paused = !self.vad.in_speech() && position - last.end >= self.flush_after;
+            if last.end - self.start >= self.target || paused {
+                return Ok(Some(self.take_window(last.end)));
+            }
+        }
+        if self.vad.in_speech() {
+            if position - self.start >= self.max {
+                let region = self.vad.split(self.start + self.target);
+                self.regions.extend(region);
+                return Ok(Some(self.take_window(region.map_or(position, |region| region.end))));
+            }
+        } else if self.regions.is_empty() {
+            // Nothing said yet: skip the silence, keeping enough to pad
+            // speech that starts next
+            self.start = self.start.max(position.saturating_sub(self.pad));
+        }
+        Ok(None)
+    }
+
+    /// The audio has ended: return the last window, if it has any speech.
+    pub fn finish(&mut self) -> Option<SpeechWindow> {
+        self.regions.extend(self.vad.finish());
+        let end = self.regions.last()?.end;
+        Some(self.take_window(end))
+    }
+
+    /// Samples fed so far that have been judged.
+    pub fn position(&self) -> u64 {
+        self.vad.position()
+    }
+
+    /// Samples fed so far, judged or not; the next push starts here.
+    pub fn fed(&self) -> u64 {
+        self.vad.fed()
+    }
+
+    /// Start of the audio not yet handed out in a window; earlier audio is
+    /// no longer needed.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    fn take_window(&mut self, end: u64) -> SpeechWindow {
+        let window = SpeechWindow {
+            start: self.start,
+            end,
+            regions: std::mem::take(&mut self.regions),
+        };
+        self.start = end;
+        window
+    }
+}
@@ -0,0 +1,395 @@
+//! Custom vocabulary: product names, acronyms and jargon Whisper misspells.
+//!
+//! A vocabulary file lists one term per line, optionally followed by `:`
+//! and the ways Whisper has been heard to get it wrong, separated by
+//! commas, and by `|` and how loosely other words may match it (see
+//! [`Matching`]). Lines starting with `#` are comments.
+//!
+//! ```text
+//! # Platform team
+//! Kubernetes: cuber netties, cooper nettis
+//! Grafana | distance 2
+//! SRE: s are e
+//! Linear | exact
+//! ```
+//!
+//! The terms are listed in Whisper's prompt so it is more likely to spell
+//! them right, and transcribed words that match a listed misrecognition, or
+//! sound and look close enough to a term, are replaced by it.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::streaming::normalize_word;
+use crate::transcript::{Segment, Word};
+
+/// Terms with fewer consonant sounds than this (see [`phonetic_key`]) are
+/// only replaced when a listed misrecognition matches, unless the term sets
+/// a distance, as short words such as "notion" for "Notion" or "nation"
+/// collide too easily.
+const MIN_FUZZY_SOUNDS: usize = 4;
+
+/// Edits per letter of the term allowed for words that sound like it.
+const MAX_PHONETIC_EDIT_RATIO: f32 = 0.4;
+
+/// Edits per letter of the term allowed for words that only look like it.
+const MAX_EDIT_RATIO: f32 = 0.15;
+
+/// A term and the ways Whisper has been heard to misspell it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    /// The term as it should be written.
+    pub text: String,
+    pub misrecognitions: Vec<String>,
+    pub matching: Matching,
+}
+
+/// Which words besides its listed misrecognitions a term replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Matching {
+    /// Words that sound like the term, or one of its misrecognitions, and
+    /// are spelled close to it, if it is long enough for that to be safe.
+    #[default]
+    Fuzzy,
+    /// Only the listed misrecognitions, e.g. for a term that is also an
+    /// ordinary word (`| exact`).
+    Exact,
+    /// Words at most this many letters different from the term or one of
+    /// its misrecognitions, however long it is (`| distance 2`). 0 only
+    /// fixes the case and punctuation of the term.
+    Distance(usize),
+}
+
+impl Matching {
+    fn parse(options: &str) -> Result<Self> {
+        let words: Vec<&str> = options.split_whitespace().collect();
+        match words[..] {
+            [] => Ok(Matching::Fuzzy),
+            ["exact"] => Ok(Matching::Exact),
+            ["distance", distance] => distance
+                .parse()
+                .map(Matching::Distance)
+                .with_context(|| format!("Invalid distance: {}", distance)),
+            _ => bail!("Unknown matching '{}', expected 'exact' or 'distance <letters>'", options.trim()),
+        }
+    }
+
+    // Edits per letter between `heard` and `key`, one of a term's keys, if
+    // they are close enough to be the same word
+    fn similarity(self, heard: &str, key: &str) -> Option<f32> {
+        let letters = key.chars().count();
+        let limit = match self {
+            Matching::Exact => return None,
+            Matching::Distance(distance) => distance as f32,
+            Matching::Fuzzy => {
+                let sounds = phonetic_key(key);
+                if sounds.chars().count() < MIN_FUZZY_SOUNDS {
+                    return None;
+                }
+                let ratio = if phonetic_key(heard) == sounds {
+                    MAX_PHONETIC_EDIT_RATIO
+                } else {
+                    MAX_EDIT_RATIO
+                };
+                ratio * letters as f32
+            }
+        };
+        let distance = edit_distance(heard, key);
+        (distance as f32 <= limit).then_some(distance as f32 / letters as f32)
+    }
+}
+
+impl Term {
+    // The term and its misrecognitions as lowercase letters and digits
+    fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        std::iter::once(&self.text)
+            .chain(&self.misrecognitions)
+            .map(|text| key(text))
+    }
+}
+
+/// A glossary of terms to spell right.
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    terms: Vec<Term>,
+}
+
+impl Vocabulary {
+    pub fn new(terms: Vec<Term>) -> Self {
+        let mut vocabulary = Self::default();
+        for term in terms {
+            vocabulary.add(term);
+        }
+        vocabulary
+    }
+
+    /// Read a vocabulary file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vocabulary file: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid vocabulary file: {}", path.display()))
+    }
+
+    /// Parse the contents of a vocabulary file.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut vocabulary = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (entry, matching) = line.split_once('|').unwrap_or((line, ""));
+            let (term, misrecognitions) = entry.split_once(':').unwrap_or((entry, ""));
+            let term = term.trim();
+            if key(term).is_empty() {
+                bail!("Line {}: no term before ':'", number + 1);
+            }
+            let matching = Matching::parse(matching).with_context(|| format!("Line {}", number + 1))?;
+            vocabulary.add(Term {
+                text: term.to_string(),
+                misrecognitions: misrecognitions
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|text| !key(text).is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                matching,
+            });
+        }
+        Ok(vocabulary)
+    }
+
+    /// Add the terms of `other`, e.g. a project's vocabulary to a shared one.
+    pub fn extend(&mut self, other: Vocabulary) {
+        for term in other.terms {
+            self.add(term);
+        }
+    }
+
+    // A term listed again adds its misrecognitions to the first listing,
+    // and its matching if it sets one
+    fn add(&mut self, term: Term) {
+        match self.terms.iter_mut().find(|existing| existing.text == term.text) {
+            Some(existing) => {
+                if term.matching != Matching::Fuzzy {
+                    existing.matching = term.matching;
+                }
+                for misrecognition in term.misrecognitions {
+                    if !existing.misrecognitions.contains(&misrecognition) {
+                        existing.misrecognitions.push(misrecognition);
+                    }
+                }
+            }
+            None => self.terms.push(term),
+        }
+    }
+
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// A glossary for Whisper's prompt listing as many terms, in order, as
+    /// `fits` accepts, with the number of terms listed.
+    pub fn prompt(&self, mut fits: impl FnMut(&str) -> bool) -> Option<(String, usize)> {
+        let mut prompt = None;
+        let mut listed: Vec<&str> = Vec::new();
+        for term in &self.terms {
+            listed.push(&term.text);
+            let glossary = format!("Glossary: {}.", listed.join(", "));
+            if !fits(&glossary) {
+                break;
+            }
+            prompt = Some((glossary, listed.len()));
+        }
+        prompt
+    }
+
+    /// Like [`prompt`](Self::prompt), for a prompt of at most `budget` tokens
+    /// that also holds `initial_prompt`, counting tokens with `count`
+    /// (`None` for text too long to count).
+    pub fn prompt_within(
+        &self,
+        budget: usize,
+        initial_prompt: Option<&str>,
+        count: impl Fn(&str) -> Option<usize>,
+    ) -> Option<(String, usize)> {
+        // An initial prompt too long to count leaves no room
+        let used = initial_prompt.map_or(Some(0), &count).unwrap_or(budget);
+        let budget = budget.saturating_sub(used);
+        self.prompt(|text| count(text).is_some_and(|tokens| tokens <= budget))
+    }
+
+    /// Replace the words in `segment` and its translation that are
+    /// misrecognitions of a term, keeping their punctuation. Timed words
+    /// replaced together become one word spanning them; tokens are left as
+    /// Whisper decoded them.
+    pub fn correct(&self, mut segment: Segment) -> Segment {
+        if let Some(ref translation) = segment.translation {
+            segment.translation = Some(self.correct_text(translation));
+        }
+        let text_words: Vec<String> = segment.text.split_whitespace().map(str::to_string).collect();
+        let replacements = self.find_replacements(&text_words);
+        if replacements.is_empty() {
+            return segment;
+        }
+        let timed = segment.words.len() == text_words.len();
+
+        let mut text = Vec::with_capacity(text_words.len());
+        let mut words = Vec::new();
+        let mut next = 0;
+        for (start, length, replacement) in replacements {
+            text.extend_from_slice(&text_words[next..start]);
+            if timed {
+                words.extend_from_slice(&segment.words[next..start]);
+                let span = &segment.words[start..start + length];
+                words.push(Word {
+                    start_ms: span[0].start_ms,
+                    end_ms: span[length - 1].end_ms,
+                    text: replacement.clone(),
+                    probability: span.iter().map(|word| word.probability).sum::<f32>() / length as f32,
+                });
+            }
+            text.push(replacement);
+            next = start + length;
+        }
+        text.extend_from_slice(&text_words[next..]);
+        segment.text = text.join(" ");
+        if timed {
+            words.extend_from_slice(&segment.words[next..]);
+            segment.words = words;
+        }
+        segment
+    }
+
+    /// Replace the words in `text` that are misrecognitions of a term.
+    pub fn correct_text(&self, text: &str) -> String {
+        let words: Vec<String> = text.split_whitespace().map(str::to_string).collect();
+        let replacements = self.find_replacements(&words);
+        if replacements.is_empty() {
+            return text.to_string();
+        }
+        let mut corrected = Vec::with_capacity(words.len());
+        let mut next = 0;
+        for (start, length, replacement) in replacements {
+            corrected.extend_from_slice(&words[next..start]);
+            corrected.push(replacement);
+            next = start + length;
+        }
+        corrected.extend_from_slice(&words[next..]);
+        corrected.join(" ")
+    }
+
+    // Runs of `words` to replace, as (first word, words, replacement),
+    // preferring the longest run matching a term at each word
+    fn find_replacements(&self, words: &[String]) -> Vec<(usize, usize, String)> {
+        let keys: Vec<String> = words.iter().map(|word| normalize_word(word)).collect();
+        // A misrecognition may split a term into one more word than listed
+        let max_words = self
+            .terms
+            .iter()
+            .flat_map(|term| std::iter::once(&term.text).chain(&term.misrecognitions))
+            .map(|text| text.split_whitespace().count() + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut replacements = Vec::new();
+        let mut start = 0;
+        while start < words.len() {
+            let found = (1..=max_words.min(words.len() - start)).rev().find_map(|length| {
+                let span = &keys[start..start + length];
+                if span[0].is_empty() || span[length - 1].is_empty() {
+                    return None;
+                }
+                self.matching_term(&span.concat()).map(|term| (length, term))
+            });
+            let Some((length, term)) = found else {
+                start += 1;
+                continue;
+            };
+            let replacement = with_punctuation(&words[start], &words[start + length - 1], &term.text);
+            if replacement != words[start..start + length].join(" ") {
+                replacements.push((start, length, replacement));
+            }
+            start += length;
+        }
+        replacements
+    }
+
+    // The term `heard` (a key) is a listed misrecognition of, otherwise the
+    // term it is closest to sounding or looking like. A short term in other
+    // case is left alone, so listing "Go" doesn't capitalize every "go".
+    fn matching_term(&self, heard: &str) -> Option<&Term> {
+        let listed = |term: &&Term| term.misrecognitions.iter().any(|text| key(text) == heard);
+        if let Some(term) = self.terms.iter().find(listed) {
+            return Some(term);
+        }
+        self.terms
+            .iter()
+            .filter_map(|term| {
+                term.keys()
+                    .filter_map(|key| term.matching.similarity(heard, &key))
+                    .reduce(f32::min)
+                    .map(|ratio| (ratio, term))
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, term)| term)
+    }
+}
+
+/// Levenshtein distance between `a` and `b`, in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// A rough key for how a lowercase word sounds: vowels after the first
+/// letter are dropped, consonants that sound alike are merged and doubled
+/// sounds count once, so "cubernetties" and "kubernetes" are both "kprnts".
+pub fn phonetic_key(word: &str) -> String {
+    let word = word.replace("ph", "f").replace("ck", "k").replace("th", "t").replace('x', "ks");
+    let mut key = String::new();
+    for (i, c) in word.chars().enumerate() {
+        let sound = match c {
+            'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'h' | 'w' if i == 0 => 'a',
+            'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'h' | 'w' => continue,
+            'b' | 'p' => 'p',
+            'c' | 'g' | 'k' | 'q' => 'k',
+            'd' | 't' => 't',
+            'f' | 'v' => 'f',
+            'm' | 'n' => 'n',
+            's' | 'z' => 's',
+            other => other,
+        };
+        if !key.ends_with(sound) {
+            key.push(sound);
+        }
+    }
+    key
+}
+
+// Text as lowercase letters and digits, ignoring spaces between words
+fn key(text: &str) -> String {
+    text.split_whitespace().map(normalize_word).collect()
+}
+
+// `term` with the punctuation before `first` and after `last`
+fn with_punctuation(first: &str, last: &str, term: &str) -> String {
+    let leading = &first[..first.len() - first.trim_start_matches(|c: char| !c.is_alphanumeric()).len()];
+    let trailing = &last[last.trim_end_matches(|c: char| !c.is_alphanumeric()).len()..];
+    format!("{}{}{}", leading, term, trailing)
+}
@@ -0,0 +1,244 @@
+use audio_recorder::audio::WHISPER_SAMPLE_RATE;
+use audio_recorder::capture::{ring_buffer, Timeline};
+use std::f64::consts::PI;
+
+/// A sine tone delivered in irregular interleaved blocks, like a device
+/// callback would.
+struct SyntheticSource {
+    sample_rate: u32,
+    channels: u16,
+    frequency: f64,
+    frame: u64,
+    block_sizes: std::iter::Cycle<std::vec::IntoIter<usize>>,
+}
+
+impl SyntheticSource {
+    fn new(sample_rate: u32, channels: u16, frequency: f64) -> Self {
+        // Odd sizes so blocks regularly end in the middle of a frame
+        let block_sizes = vec![441, 1, 1024, 97, 480, 3, 2048, 255];
+        Self {
+            sample_rate,
+            channels,
+            frequency,
+            frame: 0,
+            block_sizes: block_sizes.into_iter().cycle(),
+        }
+    }
+
+    // In f64 so the phase stays exact over long captures
+    fn value_at(&self, seconds: f64) -> f32 {
+        (0.5 * (2.0 * PI * self.frequency * seconds).sin()) as f32
+    }
+
+    /// The next block of interleaved samples, `None` once `total_frames`
+    /// frames have been produced.
+    fn next_block(&mut self, total_frames: u64, sample_count: &mut u64) -> Option<Vec<f32>> {
+        let total_samples = total_frames * self.channels as u64;
+        if *sample_count >= total_samples {
+            return None;
+        }
+        let size = (self.block_sizes.next().unwrap() as u64).min(total_samples - *sample_count);
+        let mut block = Vec::with_capacity(size as usize);
+        for _ in 0..size {
+            let frame = *sample_count / self.channels as u64;
+            block.push(self.value_at(frame as f64 / self.sample_rate as f64));
+            *sample_count += 1;
+        }
+        self.frame = *sample_count / self.channels as u64;
+        Some(block)
+    }
+
+    /// Feed `seconds` of audio into `timeline`.
+    fn feed(&mut self, timeline: &mut Timeline, seconds: u32) {
+        let total_frames = self.frame + (seconds * self.sample_rate) as u64;
+        let mut sample_count = self.frame * self.channels as u64;
+        while let Some(block) = self.next_block(total_frames, &mut sample_count) {
+            timeline.push_interleaved(&block);
+        }
+    }
+}
+
+/// 16 kHz samples near the start and end of the capture, where the
+/// resampling filter sees the tone switch on or off.
+const EDGE_SAMPLES: u64 = 160;
+
+fn assert_tone(timeline: &Timeline, source: &SyntheticSource, tolerance: f32) {
+    let (start, samples) = timeline.samples_from(0);
+    for (i, &sample) in samples.iter().enumerate() {
+        let position = start + i as u64;
+        if position < EDGE_SAMPLES || position + EDGE_SAMPLES >= timeline.end() {
+            continue;
+        }
+        let seconds = position as f64 / WHISPER_SAMPLE_RATE as f64;
+        let expected = source.value_at(seconds);
+        assert!(
+            (sample - expected).abs() <= tolerance,
+            "sample {} is {}, expected {}",
+            start + i as u64,
+            sample,
+            expected
+        );
+    }
+}
+
+#[test]
+fn ring_buffer_delivers_samples_in_order_across_threads() {
+    let (mut producer, mut consumer) = ring_buffer(1024, 1);
+    let total = 200_000;
+
+    let writer = std::thread::spawn(move || {
+        let samples: Vec<f32> = (0..total).map(|i| i as f32).collect();
+        let mut offset = 0;
+        for size in [7, 300, 1, 64].iter().cycle() {
+            if offset == total {
+                break;
+            }
+            let end = (offset + size).min(total);
+            // Retry whatever didn't fit so nothing is lost
+            let mut pushed = offset;
+            while pushed < end {
+                let n = producer.push(&samples[pushed..end]);
+                pushed += n;
+                if n == 0 {
+                    std::thread::yield_now();
+                }
+            }
+            offset = end;
+        }
+    });
+
+    let mut received = Vec::with_capacity(total);
+    while received.len() < total {
+        if consumer.pop_into(&mut received) == 0 {
+            std::thread::yield_now();
+        }
+    }
+    writer.join().unwrap();
+
+    assert!(received.iter().enumerate().all(|(i, &s)| s == i as f32));
+}
+
+#[test]
+fn ring_buffer_counts_dropped_samples_when_full() {
+    let (mut producer, mut consumer) = ring_buffer(1000, 1);
+    // Capacity is rounded up to 1024
+    let samples = vec![0.25; 1500];
+    assert_eq!(producer.push(&samples), 1024);
+    assert_eq!(producer.push(&samples), 0);
+    assert_eq!(consumer.take_dropped(), 476 + 1500);
+    assert_eq!(consumer.take_dropped(), 0);
+
+    let mut out = Vec::new();
+    assert_eq!(consumer.pop_into(&mut out), 1024);
+    assert_eq!(producer.push(&samples[..10]), 10);
+}
+
+#[test]
+fn ring_buffer_drops_whole_frames_when_full() {
+    // Left channel positive, right negative
+    let stereo = |first: usize, count: usize| -> Vec<f32> {
+        (first..first + count)
+            .flat_map(|frame| [frame as f32 + 1.0, -(frame as f32 + 1.0)])
+            .collect()
+    };
+    let (mut producer, mut consumer) = ring_buffer(15, 2);
+    assert_eq!(producer.push(&stereo(0, 7)), 14);
+    // Room for one of two frames
+    assert_eq!(producer.push(&stereo(7, 2)), 2);
+    assert_eq!(consumer.take_dropped(), 2);
+
+    // Six channels in 16 slots: two frames fit, the four samples left over
+    // are too few for a third
+    let (mut producer_6, mut consumer_6) = ring_buffer(16, 6);
+    let frame: Vec<f32> = (0..6).map(|channel| channel as f32).collect();
+    let samples = frame.repeat(3);
+    assert_eq!(producer_6.push(&samples), 12);
+    assert_eq!(producer_6.push(&frame), 0);
+    assert_eq!(consumer_6.take_dropped(), 12);
+
+    let mut out = Vec::new();
+    assert_eq!(consumer_6.pop_into(&mut out), 12);
+    assert_eq!(producer_6.push(&samples), 12);
+    consumer_6.pop_into(&mut out);
+    assert!(out.chunks(6).all(|f| f == frame.as_slice()));
+
+    // The frames after an overflow keep their channels
+    let mut out = Vec::new();
+    consumer.pop_into(&mut out);
+    assert_eq!(producer.push(&stereo(9, 3)), 6);
+    consumer.pop_into(&mut out);
+    assert_eq!(out.len(), 22);
+    assert!(out.chunks(2).all(|f| f[0] > 0.0 && f[1] == -f[0]));
+}
+
+#[test]
+fn timeline_cursors_track_native_and_16k_positions() {
+    let mut source = SyntheticSource::new(48_000, 2, 440.0);
+    let mut timeline = Timeline::new(48_000, 2, 60);
+    source.feed(&mut timeline, 10);
+
+    assert_eq!(timeline.native_frames(), 480_000);
+    // The last few samples wait for audio after them until the end
+    assert!(timeline.end() < 160_000 && timeline.end() > 159_900);
+    timeline.finish();
+    assert_eq!(timeline.end(), 160_000);
+    assert_eq!(timeline.duration_ms(), 10_000);
+    assert_tone(&timeline, &source, 1e-4);
+}
+
+#[test]
+fn timeline_resamples_non_integer_ratios() {
+    let mut source = SyntheticSource::new(44_100, 1, 300.0);
+    let mut timeline = Timeline::new(44_100, 1, 60);
+    source.feed(&mut timeline, 5);
+
+    assert_eq!(timeline.native_frames(), 220_500);
+    timeline.finish();
+    // One 16 kHz sample for each position within the capture
+    let expected = (220_500 * WHISPER_SAMPLE_RATE as u64).div_ceil(44_100);
+    assert_eq!(timeline.end(), expected);
+    assert_tone(&timeline, &source, 5e-3);
+}
+
+#[test]
+fn timeline_passes_16k_mono_through_unchanged() {
+    let mut source = SyntheticSource::new(16_000, 1, 1000.0);
+    let mut timeline = Timeline::new(16_000, 1, 60);
+    source.feed(&mut timeline, 3);
+
+    assert_eq!(timeline.end(), 48_000);
+    assert_tone(&timeline, &source, 0.0);
+}
+
+#[test]
+fn timeline_memory_is_bounded() {
+    let mut source = SyntheticSource::new(48_000, 1, 440.0);
+    let mut timeline = Timeline::new(48_000, 1, 2);
+    source.feed(&mut timeline, 30);
+    timeline.finish();
+
+    assert_eq!(timeline.end(), 30 * 16_000);
+    assert_eq!(timeline.start(), timeline.end() - 2 * 16_000);
+
+    // Asking for discarded audio gives what is still held
+    let (start, samples) = timeline.samples_from(0);
+    assert_eq!(start, timeline.start());
+    assert_eq!(samples.len(), 2 * 16_000);
+    assert_tone(&timeline, &source, 1e-4);
+
+    let (start, samples) = timeline.samples_from(timeline.end() - 100);
+    assert_eq!(start, timeline.end() - 100);
+    assert_eq!(samples.len(), 100);
+}
+
+#[test]
+fn timeline_silence_keeps_later_audio_in_place() {
+    let mut source = SyntheticSource::new(48_000, 2, 440.0);
+    let mut timeline = Timeline::new(48_000, 2, 60);
+    source.feed(&mut timeline, 1);
+    timeline.push_silence(24_000);
+    assert_eq!(timeline.native_frames(), 72_000);
+
+    let (_, samples) = timeline.samples_from(17_000);
+    assert!(samples.iter().all(|&s| s == 0.0));
+}
@@ -0,0 +1,82 @@
+use audio_recorder::clustering::{cluster_embeddings, ClusteringMethod};
+
+/// Who speaks each embedding, in order. Speaker 1 talks first.
+const SPEAKERS: [usize; 12] = [1, 0, 1, 2, 0, 2, 1, 0, 2, 1, 0, 2];
+
+/// Embeddings scattered a little around one direction per speaker.
+fn embeddings() -> Vec<Vec<f32>> {
+    SPEAKERS
+        .iter()
+        .enumerate()
+        .map(|(i, &speaker)| {
+            (0..8)
+                .map(|d| {
+                    let base = if d == speaker * 2 { 1.0 } else { 0.0 };
+                    base + 0.15 * ((i * 8 + d) as f32 * 1.7).sin()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn cluster(method: ClusteringMethod, threshold: f32, min: Option<usize>, max: Option<usize>) -> Vec<usize> {
+    cluster_embeddings(&embeddings(), method, threshold, min, max)
+}
+
+fn speaker_count(labels: &[usize]) -> usize {
+    labels.iter().max().map_or(0, |&max| max + 1)
+}
+
+// Embeddings of the same speaker always share a label
+fn assert_speakers_kept_together(labels: &[usize]) {
+    for (i, &a) in SPEAKERS.iter().enumerate() {
+        for (j, &b) in SPEAKERS.iter().enumerate() {
+            if a == b {
+                assert_eq!(labels[i], labels[j], "{:?}", labels);
+            }
+        }
+    }
+}
+
+#[test]
+fn speakers_are_numbered_by_first_appearance() {
+    let expected = [0, 1, 0, 2, 1, 2, 0, 1, 2, 0, 1, 2];
+    assert_eq!(cluster(ClusteringMethod::Agglomerative, 0.5, None, None), expected);
+    assert_eq!(cluster(ClusteringMethod::Spectral, 0.5, None, None), expected);
+    assert!(cluster_embeddings(&[], ClusteringMethod::Spectral, 0.5, None, None).is_empty());
+}
+
+#[test]
+fn agglomerative_clustering_stops_at_the_threshold() {
+    // Different speakers are about 1.0 apart in cosine distance
+    assert_eq!(speaker_count(&cluster(ClusteringMethod::Agglomerative, 0.5, None, None)), 3);
+    assert_eq!(cluster(ClusteringMethod::Agglomerative, 1.5, None, None), [0; 12]);
+    // Every embedding is its own speaker below the spread within one voice
+    assert_eq!(speaker_count(&cluster(ClusteringMethod::Agglomerative, 0.0, None, None)), 12);
+}
+
+#[test]
+fn speaker_counts_override_the_threshold() {
+    let labels = cluster(ClusteringMethod::Agglomerative, 0.5, None, Some(2));
+    assert_eq!(speaker_count(&labels), 2);
+    assert_speakers_kept_together(&labels);
+
+    let labels = cluster(ClusteringMethod::Agglomerative, 1.5, Some(3), None);
+    assert_eq!(speaker_count(&labels), 3);
+    assert_speakers_kept_together(&labels);
+
+    assert_eq!(speaker_count(&cluster(ClusteringMethod::Agglomerative, 0.5, Some(4), None)), 4);
+    assert_eq!(speaker_count(&cluster(ClusteringMethod::Agglomerative, 0.5, Some(5), Some(5))), 5);
+}
+
+#[test]
+fn spectral_clustering_respects_speaker_counts() {
+    let labels = cluster(ClusteringMethod::Spectral, 0.5, Some(2), Some(2));
+    assert_eq!(speaker_count(&labels), 2);
+    assert_speakers_kept_together(&labels);
+
+    // The count is chosen within the bounds
+    let labels = cluster(ClusteringMethod::Spectral, 0.5, None, Some(3));
+    assert_eq!(speaker_count(&labels), 3);
+    assert_eq!(cluster(ClusteringMethod::Spectral, 0.5, Some(1), Some(1)), [0; 12]);
+}
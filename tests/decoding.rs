@@ -0,0 +1,42 @@
+use audio_recorder::decoding::DecodingOptions;
+
+fn with_initial_prompt(initial_prompt: Option<&str>) -> DecodingOptions {
+    DecodingOptions { initial_prompt: initial_prompt.map(str::to_string), ..Default::default() }
+}
+
+#[test]
+fn no_prompt_is_empty() {
+    let options = with_initial_prompt(None);
+    assert_eq!(options.prompt(None), "");
+    assert_eq!(options.prompt(Some("")), "");
+    assert_eq!(with_initial_prompt(Some("")).prompt(None), "");
+}
+
+#[test]
+fn the_user_prompt_is_used_alone() {
+    let options = with_initial_prompt(Some("Minutes of the\0 board meeting."));
+    assert_eq!(options.prompt(None), "Minutes of the board meeting.");
+    assert_eq!(options.prompt(Some("")), "Minutes of the board meeting.");
+}
+
+#[test]
+fn the_glossary_comes_before_the_user_prompt() {
+    let glossary = "Glossary: Kubernetes, Grafana.";
+    assert_eq!(
+        with_initial_prompt(Some("Weekly sync.")).prompt(Some(glossary)),
+        "Glossary: Kubernetes, Grafana. Weekly sync."
+    );
+    assert_eq!(with_initial_prompt(None).prompt(Some(glossary)), glossary);
+}
+
+#[test]
+fn thread_count_is_at_least_one() {
+    let options = |threads| DecodingOptions { threads, ..Default::default() };
+    assert_eq!(options(Some(0)).thread_count(), 1);
+    assert_eq!(options(Some(1)).thread_count(), 1);
+    // Asked for explicitly, more than the default cap
+    assert_eq!(options(Some(12)).thread_count(), 12);
+
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    assert_eq!(options(None).thread_count(), cores.min(4));
+}
@@ -0,0 +1,69 @@
+use audio_recorder::devices::{choose_config, convert_samples};
+use cpal::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+
+fn range(channels: u16, min_rate: u32, max_rate: u32, format: SampleFormat) -> SupportedStreamConfigRange {
+    SupportedStreamConfigRange::new(
+        channels,
+        SampleRate(min_rate),
+        SampleRate(max_rate),
+        SupportedBufferSize::Unknown,
+        format,
+    )
+}
+
+fn chosen(ranges: &[SupportedStreamConfigRange]) -> (u16, u32, SampleFormat) {
+    let config = choose_config(ranges).unwrap();
+    (config.channels(), config.sample_rate().0, config.sample_format())
+}
+
+#[test]
+fn mono_at_16_khz_is_preferred() {
+    let ranges = [
+        range(2, 8_000, 48_000, SampleFormat::F32),
+        range(1, 44_100, 48_000, SampleFormat::F32),
+        range(1, 8_000, 48_000, SampleFormat::I16),
+        range(1, 8_000, 48_000, SampleFormat::F32),
+    ];
+    assert_eq!(chosen(&ranges), (1, 16_000, SampleFormat::F32));
+
+    // The nearest rate above 16 kHz wins over fewer channels
+    let ranges = [range(1, 48_000, 48_000, SampleFormat::F32), range(2, 22_050, 44_100, SampleFormat::I16)];
+    assert_eq!(chosen(&ranges), (2, 22_050, SampleFormat::I16));
+}
+
+#[test]
+fn rates_below_16_khz_come_last() {
+    let ranges = [range(1, 8_000, 8_000, SampleFormat::F32), range(2, 96_000, 96_000, SampleFormat::I32)];
+    assert_eq!(chosen(&ranges), (2, 96_000, SampleFormat::I32));
+
+    // With nothing else, the highest of them
+    let ranges = [range(1, 8_000, 8_000, SampleFormat::F32), range(1, 8_000, 11_025, SampleFormat::F32)];
+    assert_eq!(chosen(&ranges), (1, 11_025, SampleFormat::F32));
+}
+
+#[test]
+fn unsupported_formats_are_skipped() {
+    let ranges = [range(1, 16_000, 16_000, SampleFormat::U8), range(2, 48_000, 48_000, SampleFormat::U16)];
+    assert_eq!(chosen(&ranges), (2, 48_000, SampleFormat::U16));
+
+    assert!(choose_config(&[range(1, 16_000, 16_000, SampleFormat::U8)]).is_none());
+    assert!(choose_config(&[]).is_none());
+}
+
+#[test]
+fn samples_are_converted_to_f32() {
+    let mut output = [9.0; 4];
+    convert_samples(&[i16::MIN, -16_384, 0, 16_384], &mut output);
+    assert_eq!(output, [-1.0, -0.5, 0.0, 0.5]);
+
+    // Unsigned samples are centred on half their range
+    convert_samples(&[0u16, 16_384, 32_768, 49_152], &mut output);
+    assert_eq!(output, [-1.0, -0.5, 0.0, 0.5]);
+
+    convert_samples(&[-1.0f32, 0.25, 0.0, 1.0], &mut output);
+    assert_eq!(output, [-1.0, 0.25, 0.0, 1.0]);
+
+    // Only as much as the input holds is written
+    convert_samples(&[i16::MIN], &mut output);
+    assert_eq!(output, [-1.0, 0.25, 0.0, 1.0]);
+}
@@ -0,0 +1,101 @@
+use audio_recorder::diarization::{assign_speakers, speaker_for_interval, SpeakerTurn};
+use audio_recorder::{Segment, Token, Word};
+
+fn turn(start: f64, end: f64, speaker: usize) -> SpeakerTurn {
+    SpeakerTurn { start, end, speaker }
+}
+
+fn word(start_ms: i64, end_ms: i64, text: &str) -> Word {
+    Word {
+        start_ms,
+        end_ms,
+        text: text.to_string(),
+        probability: 0.9,
+    }
+}
+
+fn token(start_ms: i64, text: &str) -> Token {
+    Token {
+        id: 0,
+        text: text.to_string(),
+        probability: 0.9,
+        logprob: -0.1,
+        start_ms: Some(start_ms),
+        end_ms: Some(start_ms + 200),
+    }
+}
+
+#[test]
+fn the_speaker_talking_longest_is_chosen() {
+    let turns = [turn(0.0, 1.0, 0), turn(1.0, 2.5, 1), turn(2.5, 3.0, 0), turn(3.0, 3.4, 0)];
+    assert_eq!(speaker_for_interval(&turns, 0.5, 2.0), Some(1));
+    // Speaker 0's turns add up to more than speaker 1's
+    assert_eq!(speaker_for_interval(&turns, 0.0, 3.4), Some(0));
+    assert_eq!(speaker_for_interval(&turns, 2.2, 3.4), Some(0));
+    assert_eq!(speaker_for_interval(&[], 0.0, 1.0), None);
+}
+
+#[test]
+fn intervals_between_turns_take_the_nearest_speaker() {
+    let turns = [turn(0.0, 1.0, 0), turn(5.0, 6.0, 1)];
+    assert_eq!(speaker_for_interval(&turns, 1.5, 2.0), Some(0));
+    assert_eq!(speaker_for_interval(&turns, 3.5, 4.5), Some(1));
+    assert_eq!(speaker_for_interval(&turns, 7.0, 8.0), Some(1));
+
+    let segments = vec![Segment::new(1_500, 2_000, "Right."), Segment::new(5_000, 5_800, "Yes.")];
+    let speakers: Vec<Option<usize>> = assign_speakers(segments, &turns, false)
+        .iter()
+        .map(|segment| segment.speaker)
+        .collect();
+    assert_eq!(speakers, [Some(0), Some(1)]);
+}
+
+#[test]
+fn segments_are_split_where_the_speaker_changes() {
+    let mut segment = Segment::new(0, 3_000, "Are you ready? Yes I am.")
+        .with_tokens(vec![token(0, " Are"), token(300, " you"), token(1_700, " Yes"), token(2_400, " am.")]);
+    segment.words = vec![
+        word(0, 300, "Are"),
+        word(300, 600, "you"),
+        word(600, 1_200, "ready?"),
+        word(1_700, 2_000, "Yes"),
+        word(2_000, 2_400, "I"),
+        word(2_400, 2_900, "am."),
+    ];
+    segment.no_speech_prob = Some(0.05);
+    segment.translation = Some("Are you ready? Yes I am.".to_string());
+    let turns = [turn(0.0, 1.4, 0), turn(1.5, 3.0, 1)];
+
+    let parts = assign_speakers(vec![segment], &turns, true);
+    // The outer parts keep the segment's start and end
+    assert_eq!(
+        parts.iter().map(|p| (p.start_ms, p.end_ms, p.speaker, p.text.as_str())).collect::<Vec<_>>(),
+        [(0, 1_200, Some(0), "Are you ready?"), (1_700, 3_000, Some(1), "Yes I am.")]
+    );
+    assert_eq!(parts[0].words.len(), 3);
+    assert_eq!(parts[1].words.len(), 3);
+    let tokens: Vec<Vec<&str>> = parts
+        .iter()
+        .map(|part| part.tokens.iter().map(|token| token.text.as_str()).collect())
+        .collect();
+    assert_eq!(tokens, [vec![" Are", " you"], vec![" Yes", " am."]]);
+    assert!(parts.iter().all(|part| part.no_speech_prob == Some(0.05)));
+    assert!(parts[0].translation.is_some() && parts[1].translation.is_none());
+
+    // Without word timings a segment is labelled as a whole
+    let parts = assign_speakers(vec![Segment::new(0, 3_000, "Are you ready? Yes I am.")], &turns, true);
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].speaker, Some(1));
+}
+
+#[test]
+fn split_segments_keep_their_language() {
+    let mut segment = Segment::new(0, 2_000, "Olá. Hello there.");
+    segment.words = vec![word(0, 600, "Olá."), word(1_000, 1_400, "Hello"), word(1_400, 2_000, "there.")];
+    segment.language = Some("pt".to_string());
+    let turns = [turn(0.0, 0.8, 0), turn(0.9, 2.0, 1)];
+
+    let parts = assign_speakers(vec![segment], &turns, true);
+    assert_eq!(parts.len(), 2);
+    assert!(parts.iter().all(|part| part.language.as_deref() == Some("pt")));
+}
@@ -0,0 +1,126 @@
+use audio_recorder::filter::{
+    compression_ratio, find_loops, merge_segments, no_speech_estimate, remove_loops, remove_repeated_segments,
+    FilterOptions,
+};
+use audio_recorder::transcript::group_words;
+use audio_recorder::vad::SpeechRegion;
+use audio_recorder::{Segment, Token};
+
+/// A segment with one timed token per word, 100 ms each from `start_ms`.
+fn timed_segment(start_ms: i64, text: &str, logprob: f32) -> Segment {
+    let tokens: Vec<Token> = text
+        .split_whitespace()
+        .enumerate()
+        .map(|(i, word)| Token {
+            id: i as i32,
+            text: format!(" {}", word),
+            probability: logprob.exp(),
+            logprob,
+            start_ms: Some(start_ms + i as i64 * 100),
+            end_ms: Some(start_ms + (i as i64 + 1) * 100),
+        })
+        .collect();
+    let end_ms = start_ms + tokens.len() as i64 * 100;
+    let mut segment = Segment::new(start_ms, end_ms, text).with_tokens(tokens);
+    segment.words = group_words(&segment.tokens);
+    segment
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|w| w.to_lowercase()).collect()
+}
+
+#[test]
+fn loops_compress_far_better_than_speech() {
+    let speech = "We agreed to move the release to Thursday so the QA team has time to finish testing.";
+    assert!(compression_ratio(speech) < 2.0, "{}", compression_ratio(speech));
+
+    let looping = "I'm going to go ahead and do that. ".repeat(8);
+    assert!(compression_ratio(&looping) > 2.4, "{}", compression_ratio(&looping));
+    assert_eq!(compression_ratio(""), 0.0);
+}
+
+#[test]
+fn phrase_loops_are_found() {
+    assert_eq!(find_loops(&words("thank you thank you thank you thank you so much"), 4), [(0, 2, 4)]);
+    assert_eq!(find_loops(&words("so so so so so we start"), 4), [(0, 1, 5)]);
+    // Emphasis is not a loop
+    assert!(find_loops(&words("no no no that's wrong"), 4).is_empty());
+}
+
+#[test]
+fn loops_are_cut_to_one_occurrence_with_their_timing() {
+    let segment = timed_segment(1_000, "okay thank you, thank you, thank you, thank you, thank you, bye", -0.3);
+    let cleaned = remove_loops(segment, 4);
+
+    assert_eq!(cleaned.text, "okay thank you, bye");
+    assert_eq!(cleaned.tokens.len(), 4);
+    assert_eq!(cleaned.words.len(), 4);
+    assert_eq!(cleaned.words[3].text, "bye");
+    assert_eq!((cleaned.start_ms, cleaned.end_ms), (1_000, 2_200));
+    assert!((cleaned.avg_logprob + 0.3).abs() < 1e-6);
+}
+
+#[test]
+fn repeated_lines_keep_one_copy() {
+    let mut segments = vec![Segment::new(0, 1_000, "Hello.")];
+    for i in 1..=5 {
+        segments.push(Segment::new(i * 1_000, (i + 1) * 1_000, "Subscribe to the channel!"));
+    }
+    segments.push(Segment::new(6_000, 7_000, "Yes."));
+    segments.push(Segment::new(7_000, 8_000, "yes"));
+
+    let texts: Vec<String> = remove_repeated_segments(segments, 4)
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect();
+    assert_eq!(texts, ["Hello.", "Subscribe to the channel!", "Yes.", "yes"]);
+}
+
+#[test]
+fn unsure_text_over_silence_is_dropped() {
+    let options = FilterOptions::default();
+    let phantom = timed_segment(10_000, "Thank you.", -1.4);
+    let mumbled = timed_segment(20_000, "the minutes from last week", -1.4);
+    let regions = [SpeechRegion { start: 19_500 * 16, end: 25_000 * 16 }];
+
+    assert!(options.needs_fallback(&phantom));
+    let no_speech = no_speech_estimate(&phantom, &regions);
+    assert_eq!(no_speech, 1.0);
+    assert!(options.is_silence(&phantom, no_speech));
+
+    let no_speech = no_speech_estimate(&mumbled, &regions);
+    assert_eq!(no_speech, 0.0);
+    assert!(!options.is_silence(&mumbled, no_speech));
+
+    // Confident text is kept whatever the audio sounds like
+    assert!(!options.is_silence(&timed_segment(0, "Thank you.", -0.2), 1.0));
+}
+
+#[test]
+fn fallback_steps_up_to_full_temperature() {
+    let options = FilterOptions::default();
+    let temperatures = options.fallback_temperatures();
+    assert_eq!(temperatures.len(), 5);
+    assert!((temperatures[4] - 1.0).abs() < 1e-6);
+
+    let disabled = FilterOptions {
+        temperature_increment: 0.0,
+        ..Default::default()
+    };
+    assert!(disabled.fallback_temperatures().is_empty());
+}
+
+#[test]
+fn redecoded_segments_merge_into_one() {
+    let merged = merge_segments(vec![
+        timed_segment(0, "first part", -0.2),
+        timed_segment(300, "and the rest", -0.4),
+    ])
+    .unwrap();
+    assert_eq!(merged.text, "first part and the rest");
+    assert_eq!((merged.start_ms, merged.end_ms), (0, 600));
+    assert_eq!(merged.tokens.len(), 5);
+    assert!((merged.avg_logprob + 0.32).abs() < 1e-6);
+    assert!(merge_segments(Vec::new()).is_none());
+}
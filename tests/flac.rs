@@ -0,0 +1,184 @@
+use audio_recorder::flac::FlacWriter;
+use audio_recorder::recording::RecordingWriter;
+use std::f64::consts::PI;
+use std::io::{Cursor, ErrorKind};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+
+/// What symphonia makes of a FLAC stream.
+struct Decoded {
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    /// Length from STREAMINFO, `None` if it wasn't filled in.
+    frames: Option<u64>,
+    samples: Vec<i32>,
+}
+
+fn decode(bytes: Vec<u8>) -> Decoded {
+    let mss = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("flac");
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, mss, &Default::default(), &Default::default())
+        .unwrap()
+        .format;
+    let params = format.default_track().unwrap().codec_params.clone();
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&params, &DecoderOptions::default())
+        .unwrap();
+
+    let bits_per_sample = params.bits_per_sample.unwrap();
+    let mut samples = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => panic!("{}", e),
+        };
+        let block = decoder.decode(&packet).unwrap();
+        let mut buffer = SampleBuffer::<i32>::new(block.capacity() as u64, *block.spec());
+        buffer.copy_interleaved_ref(block);
+        // Scaled up to 32 bits when decoded
+        samples.extend(buffer.samples().iter().map(|&s| s >> (32 - bits_per_sample)));
+    }
+    Decoded {
+        sample_rate: params.sample_rate.unwrap(),
+        channels: params.channels.unwrap().count(),
+        bits_per_sample,
+        frames: params.n_frames.filter(|&frames| frames > 0),
+        samples,
+    }
+}
+
+fn encode(samples: &[i32], sample_rate: u32, channels: u16, bits_per_sample: u32) -> Vec<u8> {
+    let mut writer = FlacWriter::new(Cursor::new(Vec::new()), sample_rate, channels, bits_per_sample).unwrap();
+    // In uneven pieces, as the recorder gets them
+    for piece in samples.chunks(1_000 * channels as usize + 1) {
+        writer.write_samples(piece).unwrap();
+    }
+    writer.finalize().unwrap().into_inner()
+}
+
+// A tone with some noise, so every predictor order has residuals to code
+fn speech_like(frames: usize, channels: usize) -> Vec<i32> {
+    let mut noise = 1u32;
+    (0..frames * channels)
+        .map(|i| {
+            noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let frame = (i / channels) as f64;
+            let tone = 12_000.0 * (2.0 * PI * 220.0 * (i % channels + 1) as f64 * frame / 16_000.0).sin();
+            (tone + (noise >> 16) as f64 % 512.0 - 256.0) as i32
+        })
+        .collect()
+}
+
+fn assert_round_trip(samples: &[i32], sample_rate: u32, channels: u16) {
+    assert_round_trip_at(samples, sample_rate, channels, 16);
+}
+
+fn assert_round_trip_at(samples: &[i32], sample_rate: u32, channels: u16, bits_per_sample: u32) {
+    let decoded = decode(encode(samples, sample_rate, channels, bits_per_sample));
+    assert_eq!(decoded.sample_rate, sample_rate);
+    assert_eq!(decoded.channels, channels as usize);
+    assert_eq!(decoded.bits_per_sample, bits_per_sample);
+    assert_eq!(decoded.frames, Some((samples.len() / channels as usize) as u64));
+    assert!(decoded.samples == samples, "decoded samples differ");
+}
+
+#[test]
+fn mono_and_stereo_round_trip() {
+    assert_round_trip(&speech_like(20_000, 1), 16_000, 1);
+    assert_round_trip(&speech_like(20_000, 2), 48_000, 2);
+    assert_round_trip(&speech_like(5_000, 6), 44_100, 6);
+    assert_round_trip(&[0; 4096 * 2], 16_000, 1);
+}
+
+#[test]
+fn partial_blocks_round_trip() {
+    // One short frame, and full frames followed by a partial one
+    assert_round_trip(&speech_like(1, 1), 16_000, 1);
+    assert_round_trip(&speech_like(100, 2), 16_000, 2);
+    assert_round_trip(&speech_like(4096 * 3 + 17, 2), 16_000, 2);
+}
+
+#[test]
+fn long_streams_number_frames_past_one_byte() {
+    // Frame numbers of 128 and up take more than one byte in the header
+    let frames = 4096 * 300 + 5;
+    let samples: Vec<i32> = (0..frames).map(|i| (i * 7) % 2_000 - 1_000).collect();
+    assert_round_trip(&samples, 16_000, 1);
+}
+
+#[test]
+fn full_scale_samples_round_trip() {
+    let mut samples = Vec::new();
+    for i in 0..9_000 {
+        samples.push(if i % 2 == 0 { i16::MIN } else { i16::MAX } as i32);
+        samples.push(if i % 3 == 0 { i16::MAX } else { i16::MIN } as i32);
+    }
+    assert_round_trip(&samples, 16_000, 2);
+
+    const MIN_24: i32 = -(1 << 23);
+    const MAX_24: i32 = (1 << 23) - 1;
+    let samples: Vec<i32> = (0..9_000).map(|i| if i % 3 == 0 { MAX_24 } else { MIN_24 }).collect();
+    assert_round_trip_at(&samples, 48_000, 1, 24);
+}
+
+#[test]
+fn samples_of_24_bits_round_trip() {
+    // Quiet and loud, so residuals need both short and long Rice codes
+    let quiet = speech_like(20_000, 2);
+    assert_round_trip_at(&quiet, 48_000, 2, 24);
+    let loud: Vec<i32> = quiet.iter().map(|&s| s * 256 + 255).collect();
+    assert_round_trip_at(&loud, 48_000, 2, 24);
+}
+
+#[test]
+fn unsupported_bit_depths_are_refused() {
+    assert!(FlacWriter::new(Cursor::new(Vec::new()), 16_000, 1, 20).is_err());
+    assert!(FlacWriter::new(Cursor::new(Vec::new()), 16_000, 9, 16).is_err());
+}
+
+#[test]
+fn flushed_recordings_play_without_finalizing() {
+    let path = std::env::temp_dir().join(format!("flac-test-{}.flac", std::process::id()));
+    let samples: Vec<f32> = speech_like(4096 * 5 + 100, 2).iter().map(|&s| s as f32 / 32767.0).collect();
+    let mut writer = RecordingWriter::create(&path, 16_000, 2, 16).unwrap();
+    writer.write(&samples).unwrap();
+    writer.flush().unwrap();
+
+    // As if the recorder crashed here: the written frames decode, and the
+    // length is unknown
+    let decoded = decode(std::fs::read(&path).unwrap());
+    drop(writer);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(decoded.frames, None);
+    assert_eq!(decoded.samples.len(), 4096 * 5 * 2);
+    let expected: Vec<i32> = samples[..decoded.samples.len()]
+        .iter()
+        .map(|&s| (s * 32767.0) as i32)
+        .collect();
+    assert!(decoded.samples == expected, "decoded samples differ");
+}
+
+#[test]
+fn float_recordings_are_saved_in_24_bits() {
+    let samples = [0.0, 0.5, -0.5, 1.0, -1.0, 1e-6];
+    let expected = [0, 4_194_303, -4_194_303, 8_388_607, -8_388_607, 8];
+    for extension in ["wav", "flac"] {
+        let path = std::env::temp_dir().join(format!("float-test-{}.{}", std::process::id(), extension));
+        let mut writer = RecordingWriter::create(&path, 48_000, 1, 32).unwrap();
+        writer.write(&samples).unwrap();
+        writer.finalize().unwrap();
+
+        let decoded = decode(std::fs::read(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.bits_per_sample, 24, "{}", extension);
+        assert_eq!(decoded.frames, Some(samples.len() as u64), "{}", extension);
+        assert_eq!(decoded.samples, expected, "{}", extension);
+    }
+}
@@ -0,0 +1,95 @@
+use audio_recorder::output::json::{write_json, write_jsonl_segment, SCHEMA_VERSION};
+use audio_recorder::{Segment, Transcript, Word};
+use serde_json::{json, Value};
+
+fn spoken(start_ms: i64, end_ms: i64, speaker: usize, text: &str) -> Segment {
+    let mut segment = Segment::new(start_ms, end_ms, text);
+    segment.speaker = Some(speaker);
+    segment
+}
+
+fn meeting() -> Transcript {
+    let mut transcript = Transcript::new("meeting.wav");
+    transcript.language = Some("en".into());
+    transcript.audio_duration_ms = 9_000;
+
+    let mut greeting = spoken(0, 2_000, 1, "Hello all.");
+    greeting.words = vec![
+        Word { start_ms: 0, end_ms: 800, text: "Hello".into(), probability: 0.75 },
+        Word { start_ms: 900, end_ms: 2_000, text: "all.".into(), probability: 0.5 },
+    ];
+    transcript.segments = vec![
+        greeting,
+        spoken(2_000, 5_000, 0, "Let's start."),
+        spoken(5_000, 9_000, 1, "First item."),
+    ];
+    transcript.speaker_names = vec!["Alice".into()];
+    transcript
+}
+
+#[test]
+fn the_document_lists_speakers_and_words() {
+    let mut output = Vec::new();
+    write_json(&mut output, &meeting()).unwrap();
+    let document: Value = serde_json::from_slice(&output).unwrap();
+
+    assert_eq!(document["schema_version"], SCHEMA_VERSION);
+    assert_eq!(document["source"], "meeting.wav");
+    assert_eq!(document["language"], "en");
+    assert_eq!(document["audio_duration_ms"], 9_000);
+    // In order of first appearance, named where enrolled
+    assert_eq!(
+        document["speakers"],
+        json!([
+            { "id": 1, "label": "Speaker 2", "segment_count": 2, "speaking_time_ms": 6_000 },
+            { "id": 0, "label": "Alice", "segment_count": 1, "speaking_time_ms": 3_000 },
+        ])
+    );
+
+    let segments = document["segments"].as_array().unwrap();
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments[0]["speaker"], 1);
+    assert_eq!(segments[0]["text"], "Hello all.");
+    assert_eq!(
+        segments[0]["words"],
+        json!([
+            { "start_ms": 0, "end_ms": 800, "text": "Hello", "probability": 0.75 },
+            { "start_ms": 900, "end_ms": 2_000, "text": "all.", "probability": 0.5 },
+        ])
+    );
+}
+
+#[test]
+fn empty_optional_fields_are_left_out() {
+    let mut output = Vec::new();
+    write_json(&mut output, &meeting()).unwrap();
+    let document: Value = serde_json::from_slice(&output).unwrap();
+
+    assert!(document.get("device").is_none());
+    assert!(document.get("language_probabilities").is_none());
+    // Fields without a skip rule are written as null
+    assert_eq!(document["model"], Value::Null);
+    let segment = &document["segments"][1];
+    for field in ["words", "translation", "language"] {
+        assert!(segment.get(field).is_none(), "{} was written", field);
+    }
+    assert_eq!(segment["no_speech_prob"], Value::Null);
+}
+
+#[test]
+fn json_lines_hold_one_segment_each() {
+    let transcript = meeting();
+    let mut output = Vec::new();
+    for segment in &transcript.segments {
+        write_jsonl_segment(&mut output, segment).unwrap();
+    }
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.ends_with('\n'));
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), transcript.segments.len());
+    for (line, segment) in lines.iter().zip(&transcript.segments) {
+        let parsed: Segment = serde_json::from_str(line).unwrap();
+        assert_eq!(&parsed, segment);
+    }
+}
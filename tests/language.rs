@@ -0,0 +1,46 @@
+use audio_recorder::output::text::{format_segment, write_header};
+use audio_recorder::transcript::rank_languages;
+use audio_recorder::{LanguageProbability, Segment, Transcript};
+
+const DETECTED: [(&str, f32); 5] = [("en", 0.30), ("pt", 0.55), ("gl", 0.10), ("es", 0.045), ("de", 0.005)];
+
+fn languages(ranked: &[LanguageProbability]) -> Vec<(&str, u32)> {
+    ranked
+        .iter()
+        .map(|l| (l.language.as_str(), (l.probability * 100.0).round() as u32))
+        .collect()
+}
+
+#[test]
+fn detected_languages_are_ranked() {
+    let ranked = rank_languages(DETECTED, &[]);
+    // Languages under 1% are left out
+    assert_eq!(languages(&ranked), [("pt", 55), ("en", 30), ("gl", 10), ("es", 5)]);
+}
+
+#[test]
+fn candidate_languages_share_the_probability() {
+    let candidates = ["pt".to_string(), "en".to_string()];
+    let ranked = rank_languages(DETECTED, &candidates);
+    assert_eq!(languages(&ranked), [("pt", 65), ("en", 35)]);
+
+    // The most likely candidate is kept however unlikely
+    let ranked = rank_languages(DETECTED, &["de".to_string()]);
+    assert_eq!(languages(&ranked), [("de", 100)]);
+}
+
+#[test]
+fn detection_and_segment_languages_are_shown() {
+    let mut transcript = Transcript::new("reuniao.wav");
+    transcript.language = Some("pt".to_string());
+    transcript.language_probabilities = rank_languages(DETECTED, &["pt".to_string(), "en".to_string()]);
+    let mut header = Vec::new();
+    write_header(&mut header, &transcript).unwrap();
+    let header = String::from_utf8(header).unwrap();
+    assert!(header.contains("Language: pt (detected: pt 65%, en 35%)\n"), "{}", header);
+
+    let mut segment = Segment::new(0, 2_000, "Let's switch to English.");
+    segment.speaker = Some(1);
+    segment.language = Some("en".to_string());
+    assert_eq!(format_segment(&segment, &[]), "[00:00 - 00:02] (en) Speaker 2: Let's switch to English.");
+}
@@ -0,0 +1,71 @@
+use audio_recorder::profiles::{match_profiles, ProfileStore, VoiceProfile};
+use chrono::Local;
+
+fn store(test: &str) -> ProfileStore {
+    let dir = std::env::temp_dir().join(format!("profiles-test-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    ProfileStore::new(dir)
+}
+
+fn profile(name: &str, embedding: Vec<f32>) -> VoiceProfile {
+    VoiceProfile {
+        name: name.to_string(),
+        embedding,
+        clip_count: 1,
+        updated_at: Local::now(),
+    }
+}
+
+#[test]
+fn enrolling_again_averages_the_clips() {
+    let store = store("enroll");
+    store.enroll("Ana", &[1.0, 0.0, 3.0]).unwrap();
+    store.enroll("Ana", &[0.0, 1.0, 0.0]).unwrap();
+    let profile = store.enroll("Ana", &[0.5, 0.5, 0.0]).unwrap();
+    assert_eq!(profile.clip_count, 3);
+    assert_eq!(profile.embedding, [0.5, 0.5, 1.0]);
+
+    store.enroll("Bruno", &[0.0, 0.0, 1.0]).unwrap();
+    let profiles = store.load_all().unwrap();
+    let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["Ana", "Bruno"]);
+    assert_eq!(profiles[0], profile);
+
+    // A clip from another embedding model doesn't fit
+    assert!(store.enroll("Ana", &[1.0, 0.0]).is_err());
+    std::fs::remove_dir_all(store.dir()).unwrap();
+}
+
+#[test]
+fn names_sharing_a_file_are_not_merged() {
+    let store = store("collide");
+    store.enroll("O'Brien", &[1.0, 0.0]).unwrap();
+    assert!(store.enroll("O Brien", &[0.0, 1.0]).is_err());
+    store.enroll("Ana", &[1.0, 0.0]).unwrap();
+    assert!(store.enroll("ana", &[0.0, 1.0]).is_err());
+
+    let profiles = store.load_all().unwrap();
+    assert_eq!(profiles.len(), 2);
+    assert!(profiles.iter().all(|p| p.clip_count == 1 && p.embedding == [1.0, 0.0]));
+    std::fs::remove_dir_all(store.dir()).unwrap();
+}
+
+#[test]
+fn speakers_match_the_most_similar_profiles_once() {
+    let profiles = [
+        profile("Ana", vec![1.0, 0.0, 0.0]),
+        profile("Bruno", vec![0.0, 1.0, 0.0]),
+        profile("Old model", vec![1.0, 0.0]),
+    ];
+    let centroids = [
+        vec![0.1, 0.9, 0.0],
+        vec![0.9, 0.2, 0.0],
+        // Closer to Ana than the speaker above, so it takes her profile
+        vec![1.0, 0.05, 0.0],
+        // Like nobody enrolled
+        vec![0.0, 0.0, 1.0],
+    ];
+    assert_eq!(match_profiles(&centroids, &profiles, 0.5), [Some(1), None, Some(0), None]);
+    // Nobody is similar enough
+    assert_eq!(match_profiles(&centroids[..1], &profiles, 0.999), [None]);
+}
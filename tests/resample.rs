@@ -0,0 +1,143 @@
+use audio_recorder::audio::resample_with_quality;
+use audio_recorder::resample::{ResampleQuality, Resampler};
+use std::f64::consts::PI;
+
+const QUALITIES: [ResampleQuality; 3] = [ResampleQuality::Fast, ResampleQuality::Medium, ResampleQuality::High];
+
+fn tone(frequency: f64, sample_rate: u32, seconds: f64) -> Vec<f32> {
+    let len = (seconds * sample_rate as f64) as usize;
+    (0..len)
+        .map(|i| (0.5 * (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin()) as f32)
+        .collect()
+}
+
+/// The samples away from the start and end, where the filter sees the
+/// signal switch on or off.
+fn steady(samples: &[f32], sample_rate: u32) -> &[f32] {
+    let edge = sample_rate as usize / 20;
+    &samples[edge..samples.len() - edge]
+}
+
+fn rms_db(samples: &[f32], reference: f32) -> f64 {
+    let power = samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64;
+    10.0 * (power / (reference as f64).powi(2)).log10()
+}
+
+/// Largest difference from the ideal tone at the output rate, so gain,
+/// delay and leftover images all count.
+fn max_error(frequency: f64, from_rate: u32, to_rate: u32, quality: ResampleQuality) -> f32 {
+    let input = tone(frequency, from_rate, 1.0);
+    let output = resample_with_quality(&input, from_rate, to_rate, quality);
+    let expected = tone(frequency, to_rate, 1.0);
+    let edge = to_rate as usize / 20;
+    (edge..expected.len() - edge)
+        .map(|i| (output[i] - expected[i]).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn passband_is_flat_and_in_phase() {
+    let rates = [(48_000, 16_000), (44_100, 16_000), (22_050, 16_000), (8_000, 16_000)];
+    for quality in QUALITIES {
+        // Fast only has 60 dB of stopband, which also bounds its ripple
+        let tolerance = if quality == ResampleQuality::Fast { 2e-3 } else { 2e-4 };
+        for (from_rate, to_rate) in rates {
+            // Up to half of the lower Nyquist frequency
+            let nyquist = from_rate.min(to_rate) as f64 / 2.0;
+            for frequency in [0.02 * nyquist, 0.25 * nyquist, 0.5 * nyquist] {
+                let error = max_error(frequency, from_rate, to_rate, quality);
+                assert!(
+                    error < tolerance,
+                    "{:?} {} -> {} Hz at {} Hz: error {}",
+                    quality, from_rate, to_rate, frequency, error
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn better_quality_keeps_more_of_the_band() {
+    // 85% of the 8 kHz Nyquist frequency
+    assert!(max_error(6_800.0, 48_000, 16_000, ResampleQuality::High) < 2e-3);
+    // 75%
+    assert!(max_error(6_000.0, 48_000, 16_000, ResampleQuality::Medium) < 2e-3);
+    assert!(max_error(4_500.0, 48_000, 16_000, ResampleQuality::Fast) < 1e-2);
+}
+
+#[test]
+fn aliases_are_rejected() {
+    let cases = [(ResampleQuality::Fast, -58.0), (ResampleQuality::Medium, -88.0), (ResampleQuality::High, -115.0)];
+    for (quality, limit) in cases {
+        for (from_rate, frequency) in [(48_000, 8_100.0), (48_000, 12_000.0), (48_000, 20_000.0), (44_100, 10_000.0)] {
+            let input = tone(frequency, from_rate, 1.0);
+            let output = resample_with_quality(&input, from_rate, 16_000, quality);
+            let level = rms_db(steady(&output, 16_000), 0.5 / 2f32.sqrt());
+            assert!(
+                level < limit,
+                "{:?} {} Hz from {} Hz leaks at {:.1} dB",
+                quality, frequency, from_rate, level
+            );
+        }
+    }
+}
+
+#[test]
+fn linear_interpolation_would_alias() {
+    // The reason for the filter: a 12 kHz tone at 48 kHz folds down to
+    // 4 kHz when samples are merely picked
+    let input = tone(12_000.0, 48_000, 1.0);
+    let picked: Vec<f32> = input.iter().step_by(3).copied().collect();
+    assert!(rms_db(steady(&picked, 16_000), 0.5 / 2f32.sqrt()) > -1.0);
+}
+
+#[test]
+fn streaming_matches_one_shot() {
+    let input = tone(440.0, 44_100, 2.0);
+    for quality in QUALITIES {
+        let expected = resample_with_quality(&input, 44_100, 16_000, quality);
+
+        let mut resampler = Resampler::new(44_100, 16_000, quality);
+        let mut streamed = Vec::new();
+        let mut offset = 0;
+        for size in [1, 441, 7, 4096, 100, 0, 13].iter().cycle() {
+            if offset == input.len() {
+                break;
+            }
+            let end = (offset + size).min(input.len());
+            resampler.process(&input[offset..end], &mut streamed);
+            // Never more than the filter's reach behind the input
+            let ready = (end as u64 * 16_000 / 44_100) as usize;
+            assert!(streamed.len() + resampler.latency() >= ready);
+            offset = end;
+        }
+        resampler.finish(&mut streamed);
+
+        assert_eq!(streamed, expected);
+    }
+}
+
+#[test]
+fn output_covers_the_input_exactly() {
+    for (from_rate, to_rate, len) in [(48_000, 16_000, 48_001), (44_100, 16_000, 44_100), (8_000, 16_000, 999)] {
+        let output = resample_with_quality(&vec![0.0; len], from_rate, to_rate, ResampleQuality::default());
+        let expected = (len as u64 * to_rate as u64).div_ceil(from_rate as u64) as usize;
+        assert_eq!(output.len(), expected);
+    }
+}
+
+#[test]
+fn constant_level_is_preserved() {
+    let input = vec![0.25; 44_100];
+    for quality in QUALITIES {
+        let output = resample_with_quality(&input, 44_100, 16_000, quality);
+        assert!(steady(&output, 16_000).iter().all(|&s| (s - 0.25).abs() < 1e-6));
+    }
+}
+
+#[test]
+fn unusual_rates_are_supported() {
+    // No small common ratio with 16 kHz, so positions are rounded
+    let error = max_error(1000.0, 44_099, 16_000, ResampleQuality::Medium);
+    assert!(error < 1e-3, "error {}", error);
+}
@@ -0,0 +1,206 @@
+use audio_recorder::audio::{AudioDecoder, WHISPER_SAMPLE_RATE};
+use audio_recorder::capture::Timeline;
+use audio_recorder::source::{
+    channel_names, AudioSource, FileSource, PcmSource, RawPcmSource, RawSampleFormat, Realtime,
+    SelectChannel, SyntheticSource,
+};
+use std::io::{Cursor, Read};
+use std::time::{Duration, Instant};
+
+/// Read a started source to the end, checking that blocks are contiguous.
+fn read_all(source: &mut dyn AudioSource, channels: u16) -> Vec<f32> {
+    let mut samples = Vec::new();
+    let mut next_frame = 0;
+    while let Some(block) = source.next_block().unwrap() {
+        assert_eq!(block.frame, next_frame, "blocks should be contiguous");
+        assert_eq!(block.samples.len() % channels as usize, 0);
+        next_frame = block.end_frame(channels);
+        samples.extend(block.samples);
+    }
+    samples
+}
+
+#[test]
+fn synthetic_tone_fills_the_timeline() {
+    let mut source = SyntheticSource::tone(440.0, 3.0).with_format(48_000, 2);
+    let format = source.start().unwrap();
+    assert_eq!((format.sample_rate, format.channels), (48_000, 2));
+
+    let mut timeline = Timeline::new(format.sample_rate, format.channels, 60);
+    while let Some(block) = source.next_block().unwrap() {
+        timeline.push_interleaved(&block.samples);
+    }
+    timeline.finish();
+    assert_eq!(timeline.end(), 3 * WHISPER_SAMPLE_RATE as u64);
+
+    // Away from where the tone starts and stops
+    let (_, samples) = timeline.samples_from(0);
+    for (i, &sample) in samples.iter().enumerate().take(samples.len() - 160).skip(160) {
+        let seconds = i as f32 / WHISPER_SAMPLE_RATE as f32;
+        assert!((sample - source.value_at(seconds)).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn synthetic_speech_pauses_between_utterances() {
+    let source = SyntheticSource::speech(10.0);
+    let mut source_copy = source.clone();
+    let format = source_copy.start().unwrap();
+    let samples = read_all(&mut source_copy, format.channels);
+    assert_eq!(samples.len(), 10 * 16_000);
+
+    // Loud while speaking, silent while pausing
+    let rms = |from: f32, to: f32| {
+        let range = &samples[(from * 16_000.0) as usize..(to * 16_000.0) as usize];
+        (range.iter().map(|s| s * s).sum::<f32>() / range.len() as f32).sqrt()
+    };
+    assert!(source.is_speech_at(0.5) && rms(0.0, 1.6) > 0.05);
+    assert!(!source.is_speech_at(1.9) && rms(1.6, 2.2) == 0.0);
+    assert!(samples.iter().all(|s| s.abs() <= 1.0));
+}
+
+#[test]
+fn raw_pcm_is_decoded_across_split_reads() {
+    let values: Vec<i16> = (0..10_000).map(|i| ((i % 200) * 100 - 10_000) as i16).collect();
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    // An odd-length prefix so a sample is split between reads
+    let reader = Cursor::new(bytes[..4097].to_vec()).chain(Cursor::new(bytes[4097..].to_vec()));
+
+    let mut source = RawPcmSource::new("pipe", reader, RawSampleFormat::S16le, 8_000, 2);
+    let format = source.start().unwrap();
+    assert_eq!((format.sample_rate, format.channels), (8_000, 2));
+
+    let samples = read_all(&mut source, 2);
+    let expected: Vec<f32> = values.iter().map(|&v| v as f32 / 32768.0).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn raw_f32_is_decoded() {
+    let values = [0.5f32, -0.25, 1.0, 0.0];
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut source = RawPcmSource::new("pipe", Cursor::new(bytes), RawSampleFormat::F32le, 16_000, 1);
+    source.start().unwrap();
+    assert_eq!(read_all(&mut source, 1), values);
+}
+
+#[test]
+fn realtime_paces_blocks_to_the_clock() {
+    let mut source = Realtime::new(SyntheticSource::tone(440.0, 0.3));
+    source.start().unwrap();
+    let started = Instant::now();
+    read_all(&mut source, 1);
+    assert!(started.elapsed() >= Duration::from_millis(290));
+}
+
+#[test]
+fn stopping_ends_the_source() {
+    let mut source = SyntheticSource::tone(440.0, 60.0);
+    source.start().unwrap();
+    assert!(source.next_block().unwrap().is_some());
+    source.stop();
+    assert!(source.next_block().unwrap().is_none());
+}
+
+#[test]
+fn file_source_streams_the_same_audio_it_loads() {
+    let path = std::env::temp_dir().join(format!("sources-test-{}.wav", std::process::id()));
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 16_000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for i in 0..16_000 {
+        writer.write_sample((i % 1000) as i16).unwrap();
+        writer.write_sample(-((i % 1000) as i16)).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let mut source = FileSource::new(&path);
+    let format = source.start().unwrap();
+    assert_eq!((format.sample_rate, format.channels), (16_000, 2));
+    let streamed = read_all(&mut source, 2);
+    assert_eq!(streamed.len(), 16_000 * 2);
+    assert_eq!(streamed[2], 1.0 / 32768.0);
+
+    // Loading mixes the two channels to silence
+    let loaded = FileSource::new(&path).load().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.len(), 16_000);
+    assert!(loaded.iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn pcm_source_streams_and_loads() {
+    let samples: Vec<f32> = (0..8_000).map(|i| (i as f32 / 8_000.0) - 0.5).collect();
+    let mut source = PcmSource::new("memory", samples.clone(), 8_000);
+    source.start().unwrap();
+    assert_eq!(read_all(&mut source, 1), samples);
+
+    let loaded = PcmSource::new("memory", samples, 8_000).load().unwrap();
+    assert_eq!(loaded.len(), 16_000);
+}
+
+#[test]
+fn containers_decode_from_unseekable_streams() {
+    let mut wav = Cursor::new(Vec::new());
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 8_000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+    for i in 0..8_000 {
+        writer.write_sample((i % 100) as i16 * 10).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    // Only `Read`, like a pipe
+    let bytes = wav.into_inner();
+    let reader = Cursor::new(bytes).take(u64::MAX);
+    let mut decoder = AudioDecoder::from_reader(reader).unwrap();
+    assert_eq!((decoder.sample_rate(), decoder.channels()), (8_000, 1));
+
+    let mut samples = Vec::new();
+    while let Some(block) = decoder.next_block().unwrap() {
+        samples.extend(block);
+    }
+    assert_eq!(samples.len(), 8_000);
+    assert_eq!(samples[1], 10.0 / 32768.0);
+}
+
+#[test]
+fn one_channel_is_selected_across_split_frames() {
+    // Left counts up, right counts down
+    let values: Vec<i16> = (0..3_000).flat_map(|i| [i as i16, -(i as i16)]).collect();
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    // Split mid-frame, so the second read starts on the right channel
+    let reader = Cursor::new(bytes[..4098].to_vec()).chain(Cursor::new(bytes[4098..].to_vec()));
+    let raw = RawPcmSource::new("call", reader, RawSampleFormat::S16le, 8_000, 2);
+
+    let mut right = SelectChannel::new(raw, 1);
+    assert_eq!(right.start().unwrap().channels, 1);
+    let samples = read_all(&mut right, 1);
+    let expected: Vec<f32> = (0..3_000).map(|i| -(i as f32) / 32768.0).collect();
+    assert_eq!(samples, expected);
+
+    let mut missing = SelectChannel::new(SyntheticSource::tone(440.0, 0.1), 1);
+    assert!(missing.start().is_err());
+}
+
+#[test]
+fn channels_load_separately() {
+    let values: Vec<i16> = (0..16_000).flat_map(|i| [(i % 100) as i16 * 100, 0]).collect();
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut raw = RawPcmSource::new("call", Cursor::new(bytes), RawSampleFormat::S16le, 16_000, 2);
+
+    let planes = raw.load_channels().unwrap();
+    assert_eq!(planes.len(), 2);
+    assert_eq!(planes[0].len(), 16_000);
+    assert_eq!(planes[0][1], 100.0 / 32768.0);
+    assert!(planes[1].iter().all(|&sample| sample == 0.0));
+    assert_eq!(channel_names(2), ["Left", "Right"]);
+}
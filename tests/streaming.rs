@@ -0,0 +1,109 @@
+use audio_recorder::streaming::SlidingWindow;
+use audio_recorder::Segment;
+
+fn texts(segments: &[Segment]) -> Vec<(i64, i64, &str)> {
+    segments
+        .iter()
+        .map(|segment| (segment.start_ms, segment.end_ms, segment.text.as_str()))
+        .collect()
+}
+
+#[test]
+fn words_repeated_across_the_seam_are_dropped() {
+    let mut window = SlidingWindow::new(2_000, false);
+    let emitted = window.accept(
+        vec![Segment::new(0, 4_000, "Good morning everyone."), Segment::new(4_000, 6_000, "Let's begin")],
+        0,
+        10_000,
+        false,
+    );
+    assert_eq!(emitted.len(), 2);
+    assert_eq!(window.window_start_ms(), 4_000);
+
+    // The next window hears the end of the last one again
+    let emitted = window.accept(
+        vec![
+            Segment::new(4_000, 6_100, "Let's begin"),
+            Segment::new(5_600, 8_000, "begin with the agenda."),
+            Segment::new(8_000, 9_000, "First item."),
+        ],
+        4_000,
+        14_000,
+        false,
+    );
+    assert_eq!(texts(&emitted), [(6_000, 8_000, "with the agenda."), (8_000, 9_000, "First item.")]);
+
+    // A segment of nothing but repeated words is dropped
+    let emitted = window.accept(vec![Segment::new(8_500, 9_600, "first item")], 7_000, 15_000, false);
+    assert!(emitted.is_empty());
+}
+
+#[test]
+fn segments_cut_off_by_the_window_are_held_back() {
+    let mut window = SlidingWindow::new(1_000, false);
+    let emitted = window.accept(
+        vec![Segment::new(0, 2_000, "Hello there."), Segment::new(2_000, 4_900, "How are")],
+        0,
+        5_000,
+        false,
+    );
+    assert_eq!(texts(&emitted), [(0, 2_000, "Hello there.")]);
+    assert_eq!(window.window_start_ms(), 1_000);
+
+    // Heard in full with the next window
+    let emitted = window.accept(
+        vec![
+            Segment::new(1_000, 2_000, "there."),
+            Segment::new(2_000, 5_500, "How are you doing today?"),
+            Segment::new(5_500, 7_950, "Good"),
+        ],
+        1_000,
+        8_000,
+        false,
+    );
+    assert_eq!(texts(&emitted), [(2_000, 5_500, "How are you doing today?")]);
+
+    // The last window holds nothing back
+    let emitted = window.accept(vec![Segment::new(5_500, 8_200, "Good to hear.")], 4_500, 8_200, true);
+    assert_eq!(texts(&emitted), [(5_500, 8_200, "Good to hear.")]);
+}
+
+#[test]
+fn silent_windows_move_the_window_on() {
+    let mut window = SlidingWindow::new(1_000, false);
+    assert!(window.accept(Vec::new(), 0, 5_000, false).is_empty());
+    // Only the overlap is heard again
+    assert_eq!(window.window_start_ms(), 3_000);
+
+    // Blank segments count as silence
+    assert!(window.accept(vec![Segment::new(3_000, 9_000, "")], 3_000, 10_000, false).is_empty());
+    assert_eq!(window.window_start_ms(), 8_000);
+
+    assert!(window.accept(Vec::new(), 8_000, 12_000, true).is_empty());
+    assert_eq!(window.window_start_ms(), 11_000);
+}
+
+#[test]
+fn the_prompt_keeps_the_latest_text() {
+    let mut window = SlidingWindow::new(1_000, true);
+    assert_eq!(window.prompt(), None);
+
+    let sentence = "We went through the open items on the release checklist.";
+    for i in 0..20 {
+        let start = i * 4_000;
+        window.accept(vec![Segment::new(start, start + 3_000, sentence)], start, start + 20_000, true);
+    }
+    window.accept(vec![Segment::new(80_000, 82_000, "Thanks\0 all.")], 80_000, 82_000, true);
+
+    let prompt = window.prompt().unwrap();
+    assert!(prompt.len() <= 600, "{} characters", prompt.len());
+    assert!(prompt.len() > 500, "{} characters", prompt.len());
+    // Cut at a word boundary, with the newest text last
+    assert!(prompt.starts_with(|c: char| c.is_alphanumeric()) && !prompt.starts_with(' '));
+    assert!(sentence.split_whitespace().any(|word| prompt.starts_with(word)));
+    assert!(prompt.ends_with("checklist. Thanks all."), "{}", prompt);
+
+    let mut window = SlidingWindow::new(1_000, false);
+    window.accept(vec![Segment::new(0, 3_000, sentence)], 0, 20_000, true);
+    assert_eq!(window.prompt(), None);
+}
@@ -0,0 +1,89 @@
+use audio_recorder::output::subtitles::{
+    format_srt_timestamp, format_vtt_timestamp, segment_cues, split_into_cues, wrap_text, write_vtt_cues,
+};
+use audio_recorder::output::FormatOptions;
+use audio_recorder::Segment;
+
+fn options(max_line_length: usize, max_lines: usize) -> FormatOptions {
+    FormatOptions {
+        max_line_length,
+        max_lines,
+    }
+}
+
+#[test]
+fn text_wraps_at_word_boundaries() {
+    assert_eq!(
+        wrap_text("the quick brown fox jumps over the lazy dog", 15),
+        ["the quick brown", "fox jumps over", "the lazy dog"]
+    );
+    // Words longer than a line get a line of their own
+    assert_eq!(
+        wrap_text("see https://example.com/a/long/path now", 10),
+        ["see", "https://example.com/a/long/path", "now"]
+    );
+    // Characters, not bytes, count towards the length
+    assert_eq!(wrap_text("ação ação ação", 9), ["ação ação", "ação"]);
+    assert!(wrap_text("   ", 10).is_empty());
+}
+
+#[test]
+fn long_text_is_split_into_cues_sharing_the_time() {
+    // Four lines of 10, 10, 10 and 5 characters
+    let text = "aaaa bbbbb cccc ddddd eeee fffff ggggg";
+    let cues = split_into_cues(1_000, 8_000, text, &options(10, 2));
+    assert_eq!(cues.len(), 2);
+    assert_eq!(cues[0].lines, ["aaaa bbbbb", "cccc ddddd"]);
+    assert_eq!(cues[1].lines, ["eeee fffff", "ggggg"]);
+    // 20 of 35 characters get 4 of the 7 seconds
+    assert_eq!((cues[0].start_ms, cues[0].end_ms), (1_000, 5_000));
+    assert_eq!((cues[1].start_ms, cues[1].end_ms), (5_000, 8_000));
+
+    // The last cue always ends with the segment
+    let cues = split_into_cues(0, 1_001, "one two three", &options(3, 1));
+    assert_eq!(cues.len(), 3);
+    assert_eq!(cues[2].end_ms, 1_001);
+    assert!(cues.windows(2).all(|pair| pair[0].end_ms == pair[1].start_ms));
+
+    assert!(split_into_cues(0, 1_000, "", &options(10, 2)).is_empty());
+}
+
+#[test]
+fn translations_are_divided_between_cues_by_words() {
+    let mut segment = Segment::new(0, 6_000, "uno dos tres cuatro cinco seis");
+    segment.translation = Some("one two three four five six seven eight nine".to_string());
+    let cues = segment_cues(&segment, &segment.text, &options(12, 1));
+    let cue_text: Vec<(String, String)> = cues
+        .iter()
+        .map(|cue| (cue.lines.join(" / "), cue.translation.join(" / ")))
+        .collect();
+    let expected = [
+        ("uno dos tres", "one two / three"),
+        ("cuatro cinco", "four five / six"),
+        ("seis", "seven eight / nine"),
+    ];
+    assert_eq!(cue_text, expected.map(|(text, translation)| (text.to_string(), translation.to_string())));
+}
+
+#[test]
+fn timestamps_run_past_an_hour() {
+    assert_eq!(format_srt_timestamp(0), "00:00:00,000");
+    assert_eq!(format_srt_timestamp(3_723_456), "01:02:03,456");
+    assert_eq!(format_srt_timestamp(36_000_000 + 59_999), "10:00:59,999");
+    assert_eq!(format_vtt_timestamp(3_723_456), "01:02:03.456");
+    // Times before the start clamp to zero
+    assert_eq!(format_vtt_timestamp(-5), "00:00:00.000");
+}
+
+#[test]
+fn vtt_text_is_escaped() {
+    let mut segment = Segment::new(0, 2_000, "if a < b && b > c");
+    segment.speaker = Some(0);
+    let names = ["Ana <host>".to_string()];
+    let mut vtt = Vec::new();
+    write_vtt_cues(&mut vtt, &segment, &names, &options(42, 2)).unwrap();
+    assert_eq!(
+        String::from_utf8(vtt).unwrap(),
+        "00:00:00.000 --> 00:00:02.000\n<v Ana &lt;host&gt;>if a &lt; b &amp;&amp; b &gt; c\n\n"
+    );
+}
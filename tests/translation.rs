@@ -0,0 +1,68 @@
+use audio_recorder::output::subtitles::write_srt_cues;
+use audio_recorder::output::text::format_segment;
+use audio_recorder::output::FormatOptions;
+use audio_recorder::transcript::align_translations;
+use audio_recorder::vocabulary::Vocabulary;
+use audio_recorder::Segment;
+
+#[test]
+fn translations_pair_with_the_segments_they_overlap() {
+    let segments = vec![
+        Segment::new(0, 3_000, "Bom dia a todos."),
+        Segment::new(3_000, 8_000, "Vamos começar pela revisão do sprint."),
+        Segment::new(9_000, 10_000, "Obrigado."),
+    ];
+    let translations = vec![
+        Segment::new(0, 2_800, "Good morning everyone."),
+        Segment::new(2_900, 5_000, "Let's start"),
+        Segment::new(5_000, 8_200, "with the sprint review."),
+        // Past the end, nearest to the last segment
+        Segment::new(10_500, 11_000, "Thank you."),
+    ];
+    let translated: Vec<Option<String>> = align_translations(segments, translations)
+        .into_iter()
+        .map(|segment| segment.translation)
+        .collect();
+    assert_eq!(
+        translated,
+        [
+            Some("Good morning everyone.".to_string()),
+            Some("Let's start with the sprint review.".to_string()),
+            Some("Thank you.".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn bilingual_segments_show_both_texts() {
+    let mut segment = Segment::new(61_000, 64_000, "Bom dia a todos.");
+    segment.speaker = Some(0);
+    segment.translation = Some("Good morning everyone.".to_string());
+
+    assert_eq!(
+        format_segment(&segment, &[]),
+        "[01:01 - 01:04] Speaker 1: Bom dia a todos.\n                EN: Good morning everyone."
+    );
+
+    let mut srt = Vec::new();
+    let options = FormatOptions {
+        max_line_length: 20,
+        max_lines: 1,
+    };
+    let cues = write_srt_cues(&mut srt, &segment, 0, &[], &options).unwrap();
+    assert_eq!(cues, 2);
+    let srt = String::from_utf8(srt).unwrap();
+    assert!(srt.contains("Speaker 1: Bom dia a\n<i>Good</i>\n"), "{}", srt);
+    assert!(srt.contains("todos.\n<i>morning everyone.</i>\n"), "{}", srt);
+}
+
+#[test]
+fn vocabulary_corrects_the_translation_too() {
+    let vocabulary = Vocabulary::parse("Kubernetes: cuber netties").unwrap();
+    let mut segment = Segment::new(0, 3_000, "Migramos para cuber netties.");
+    segment.translation = Some("We moved to cuber netties.".to_string());
+
+    let segment = vocabulary.correct(segment);
+    assert_eq!(segment.text, "Migramos para Kubernetes.");
+    assert_eq!(segment.translation.as_deref(), Some("We moved to Kubernetes."));
+}
@@ -0,0 +1,181 @@
+use audio_recorder::audio::WHISPER_SAMPLE_RATE;
+use audio_recorder::capture::Timeline;
+use audio_recorder::live::push_recorded;
+use audio_recorder::source::{AudioSource, Signal, SyntheticSource};
+use audio_recorder::vad::{
+    detect_speech, split_at_silences, SpeechAudio, SpeechChunker, SpeechRegion, SpeechWindow, VadOptions,
+};
+use audio_recorder::Segment;
+use std::sync::Mutex;
+
+const RATE: f32 = WHISPER_SAMPLE_RATE as f32;
+
+/// Synthetic speech over a faint hiss, as a microphone would pick up.
+fn noisy_speech(seconds: f32) -> (SyntheticSource, Vec<f32>) {
+    let source = SyntheticSource::speech(seconds);
+    let mut samples = source.clone().load().unwrap();
+    add_noise(&mut samples, 0.004);
+    (source, samples)
+}
+
+/// Deterministic uniform noise of up to `amplitude`.
+fn add_noise(samples: &mut [f32], amplitude: f32) {
+    let mut state = 0x2545_f491u32;
+    for sample in samples {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        *sample += amplitude * ((state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0);
+    }
+}
+
+fn seconds(position: u64) -> f32 {
+    position as f32 / RATE
+}
+
+#[test]
+fn energy_detection_finds_each_utterance() {
+    let (source, samples) = noisy_speech(11.0);
+    let regions = detect_speech(&samples, &VadOptions::default()).unwrap();
+
+    // Utterances start every 2.2 s and last 1.6 s
+    assert_eq!(regions.len(), 5, "{:?}", regions);
+    for (i, region) in regions.iter().enumerate() {
+        let utterance_start = i as f32 * 2.2;
+        // Padded by 0.2 s either side, within the pauses
+        let expected_start = (utterance_start - 0.2).max(0.0);
+        assert!((seconds(region.start) - expected_start).abs() < 0.15, "{:?}", region);
+        assert!((seconds(region.end) - (utterance_start + 1.8)).abs() < 0.15, "{:?}", region);
+        assert!(region.start == 0 || !source.is_speech_at(seconds(region.start)));
+        assert!(!source.is_speech_at(seconds(region.end)));
+    }
+}
+
+#[test]
+fn steady_noise_is_not_speech() {
+    let mut samples = SyntheticSource::new(Signal::Silence, 10.0).load().unwrap();
+    add_noise(&mut samples, 0.05);
+    assert!(detect_speech(&samples, &VadOptions::default()).unwrap().is_empty());
+
+    let tone = SyntheticSource::tone(440.0, 10.0).load().unwrap();
+    assert!(detect_speech(&tone, &VadOptions::default()).unwrap().is_empty());
+}
+
+#[test]
+fn speech_timestamps_map_back_to_the_original_timeline() {
+    let samples: Vec<f32> = (0..80_000).map(|i| i as f32).collect();
+    let regions = [
+        SpeechRegion { start: 16_000, end: 32_000 },
+        SpeechRegion { start: 48_000, end: 64_000 },
+    ];
+    let speech = SpeechAudio::extract(&samples[8_000..], 8_000, &regions);
+    assert_eq!(speech.samples().len(), 32_000);
+    assert_eq!(speech.samples()[16_000], 48_000.0);
+    assert_eq!(speech.duration_seconds(), 2.0);
+
+    let segment = speech.map_segment(Segment::new(500, 1_500, "across the gap"));
+    assert_eq!((segment.start_ms, segment.end_ms), (1_500, 3_500));
+    // Where the pieces join, a segment ends in the first and starts in the second
+    let segment = speech.map_segment(Segment::new(1_000, 1_000, ""));
+    assert_eq!((segment.start_ms, segment.end_ms), (3_000, 2_000));
+}
+
+#[test]
+fn short_speech_is_padded_for_whisper() {
+    let samples = vec![0.1; 16_000];
+    let regions = [SpeechRegion { start: 4_000, end: 8_000 }];
+    let speech = SpeechAudio::extract(&samples, 0, &regions);
+    assert!(speech.samples().len() >= 16_000);
+    assert_eq!(speech.duration_seconds(), 0.25);
+    assert!(SpeechAudio::extract(&samples, 0, &[]).samples().is_empty());
+}
+
+/// Feed `samples` to a chunker in 10 ms blocks, collecting its windows.
+fn chunk(chunker: &mut SpeechChunker, samples: &[f32]) -> Vec<SpeechWindow> {
+    let mut windows = Vec::new();
+    for block in samples.chunks(160) {
+        windows.extend(chunker.push(block).unwrap());
+    }
+    windows.extend(chunker.finish());
+    windows
+}
+
+#[test]
+fn live_windows_end_at_pauses() {
+    let (source, samples) = noisy_speech(20.0);
+    let mut chunker = SpeechChunker::new(&VadOptions::default(), 3 * 16_000, 6 * 16_000).unwrap();
+    let windows = chunk(&mut chunker, &samples);
+
+    assert!(windows.len() >= 4, "{:?}", windows);
+    let mut previous_end = 0;
+    for window in &windows {
+        assert!(window.start >= previous_end);
+        assert!(!window.regions.is_empty());
+        assert!(!source.is_speech_at(seconds(window.end)), "{:?}", window);
+        assert!(window.end - window.start <= 6 * 16_000);
+        previous_end = window.end;
+    }
+    // All ten utterances of 1.6 s are in some window
+    let covered: u64 = windows.iter().flat_map(|w| &w.regions).map(SpeechRegion::len).sum();
+    assert!(seconds(covered) >= 16.0, "{}", seconds(covered));
+}
+
+#[test]
+fn speech_without_pauses_is_cut_at_the_maximum() {
+    let (_, samples) = noisy_speech(20.0);
+    // Pauses are never long enough to end speech
+    let options = VadOptions {
+        min_silence_ms: 5_000,
+        ..Default::default()
+    };
+    let mut chunker = SpeechChunker::new(&options, 3 * 16_000, 6 * 16_000).unwrap();
+    let windows = chunk(&mut chunker, &samples);
+
+    assert!(windows.len() >= 3, "{:?}", windows);
+    for window in &windows[..windows.len() - 1] {
+        let length = window.end - window.start;
+        assert!((3 * 16_000..=6 * 16_000 + 512).contains(&length), "{:?}", window);
+    }
+}
+
+#[test]
+fn long_recordings_are_cut_in_the_longest_nearby_pause() {
+    // Speech from 0 to 95 s with a short pause at 50 s and a long one at 62 s
+    let second = |s: u64| s * 16_000;
+    let regions = [
+        SpeechRegion { start: 0, end: second(50) },
+        SpeechRegion { start: second(50) + 8_000, end: second(60) },
+        SpeechRegion { start: second(64), end: second(95) },
+    ];
+    let cuts = split_at_silences(&regions, second(100), second(45));
+    assert_eq!(cuts, [second(62)]);
+
+    // No pauses: cut where the chunks would end
+    let cuts = split_at_silences(&[SpeechRegion { start: 0, end: second(100) }], second(100), second(30));
+    assert_eq!(cuts, [second(30), second(60)]);
+    assert!(split_at_silences(&regions, second(100), second(100)).is_empty());
+}
+
+#[test]
+fn recorded_audio_reaches_the_detector_once() {
+    let (_, samples) = noisy_speech(8.0);
+    let options = VadOptions::default();
+    let mut expected = SpeechChunker::new(&options, 3 * 16_000, 6 * 16_000).unwrap();
+    let mut expected_windows = chunk(&mut expected, &samples);
+    expected_windows.extend(expected.finish());
+
+    // Recorded in pieces shorter than a detector frame, judged after each
+    let timeline = Mutex::new(Timeline::new(WHISPER_SAMPLE_RATE, 1, 60));
+    let mut chunker = SpeechChunker::new(&options, 3 * 16_000, 6 * 16_000).unwrap();
+    let mut windows = Vec::new();
+    for piece in samples.chunks(70) {
+        timeline.lock().unwrap().push_interleaved(piece);
+        windows.extend(push_recorded(&mut chunker, &timeline).unwrap());
+        assert_eq!(chunker.fed(), timeline.lock().unwrap().end());
+        // Polling again with nothing new feeds nothing
+        windows.extend(push_recorded(&mut chunker, &timeline).unwrap());
+        assert_eq!(chunker.fed(), timeline.lock().unwrap().end());
+    }
+    windows.extend(chunker.finish());
+
+    assert_eq!(chunker.fed(), samples.len() as u64);
+    assert_eq!(windows, expected_windows);
+}
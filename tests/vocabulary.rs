@@ -0,0 +1,145 @@
+use audio_recorder::vocabulary::{edit_distance, phonetic_key, Matching, Vocabulary};
+use audio_recorder::{Segment, Word};
+
+const GLOSSARY: &str = "
+# Platform team
+Kubernetes: cuber netties
+Grafana
+SRE: s are e
+Go
+";
+
+fn vocabulary() -> Vocabulary {
+    Vocabulary::parse(GLOSSARY).unwrap()
+}
+
+fn corrected(text: &str) -> String {
+    vocabulary().correct(Segment::new(0, 1_000, text)).text
+}
+
+#[test]
+fn vocabulary_files_list_terms_and_misrecognitions() {
+    let vocabulary = vocabulary();
+    let terms = vocabulary.terms();
+    assert_eq!(terms.len(), 4);
+    assert_eq!(terms[0].text, "Kubernetes");
+    assert_eq!(terms[0].misrecognitions, ["cuber netties"]);
+    assert!(terms[1].misrecognitions.is_empty());
+
+    // A project file adds to the shared one
+    let mut combined = vocabulary.clone();
+    combined.extend(Vocabulary::parse("Kubernetes: cooper nettis\nTerraform").unwrap());
+    assert_eq!(combined.terms().len(), 5);
+    assert_eq!(combined.terms()[0].misrecognitions, ["cuber netties", "cooper nettis"]);
+
+    assert!(Vocabulary::parse(": no term").is_err());
+}
+
+#[test]
+fn terms_can_limit_what_they_match() {
+    let vocabulary = Vocabulary::parse("Linear | exact\nKubectl: cube cuttle | distance 2\nGrafana").unwrap();
+    let matching: Vec<Matching> = vocabulary.terms().iter().map(|term| term.matching).collect();
+    assert_eq!(matching, [Matching::Exact, Matching::Distance(2), Matching::Fuzzy]);
+    assert_eq!(vocabulary.terms()[1].misrecognitions, ["cube cuttle"]);
+
+    let corrected = |text: &str| vocabulary.correct(Segment::new(0, 1_000, text)).text;
+    assert_eq!(corrected("linear time"), "linear time");
+    assert_eq!(corrected("run cube cuttle and kubectel"), "run Kubectl and Kubectl");
+    assert_eq!(corrected("kubecutler"), "kubecutler");
+
+    // Listing a term again can change its matching
+    let mut combined = vocabulary.clone();
+    combined.extend(Vocabulary::parse("Grafana | exact").unwrap());
+    assert_eq!(combined.terms()[2].matching, Matching::Exact);
+
+    assert!(Vocabulary::parse("Linear | loose").is_err());
+    assert!(Vocabulary::parse("Linear | distance two").is_err());
+}
+
+#[test]
+fn glossary_prompt_stays_within_budget() {
+    let vocabulary = vocabulary();
+    let (prompt, listed) = vocabulary.prompt(|_| true).unwrap();
+    assert_eq!(prompt, "Glossary: Kubernetes, Grafana, SRE, Go.");
+    assert_eq!(listed, 4);
+
+    // Counting words as tokens, with room for four
+    let (prompt, listed) = vocabulary.prompt(|text| text.split_whitespace().count() <= 4).unwrap();
+    assert_eq!(prompt, "Glossary: Kubernetes, Grafana, SRE.");
+    assert_eq!(listed, 3);
+    assert!(vocabulary.prompt(|_| false).is_none());
+}
+
+#[test]
+fn the_initial_prompt_takes_from_the_glossary_budget() {
+    let vocabulary = vocabulary();
+    let words = |text: &str| Some(text.split_whitespace().count());
+    let (prompt, listed) = vocabulary.prompt_within(6, Some("Weekly sync."), words).unwrap();
+    assert_eq!(prompt, "Glossary: Kubernetes, Grafana, SRE.");
+    assert_eq!(listed, 3);
+    assert_eq!(vocabulary.prompt_within(6, None, words).unwrap().1, 4);
+
+    // No room is left when the initial prompt fills the budget, or is too
+    // long to count
+    assert!(vocabulary.prompt_within(6, Some("One two three four five six."), words).is_none());
+    let too_long = |text: &str| Some(text.split_whitespace().count()).filter(|&tokens| tokens <= 6);
+    assert!(vocabulary.prompt_within(6, Some("One two three four five six seven."), too_long).is_none());
+}
+
+#[test]
+fn misrecognitions_are_replaced_keeping_punctuation() {
+    assert_eq!(corrected("We moved to cuber netties, finally."), "We moved to Kubernetes, finally.");
+    assert_eq!(corrected("Ask the s are e team"), "Ask the SRE team");
+    // Not listed, but sounds and looks like a term
+    assert_eq!(corrected("It runs on cubernetties and graphana."), "It runs on Kubernetes and Grafana.");
+    assert_eq!(corrected("the grafana dashboard"), "the Grafana dashboard");
+    // Short terms and unrelated words are left alone
+    assert_eq!(corrected("Let's go over the cabinets"), "Let's go over the cabinets");
+}
+
+#[test]
+fn common_words_are_left_alone() {
+    let vocabulary = Vocabulary::parse("Notion\nLinear\nMaria\nSlack\nFigma\nAsana\nPostman | exact").unwrap();
+    for text in [
+        "a nation wide issue",
+        "the mario kart",
+        "a notion of",
+        "linear time",
+        "cut us some slack",
+        "a sigma rule",
+        "after a sauna",
+        "the postman rang",
+    ] {
+        assert_eq!(vocabulary.correct(Segment::new(0, 1_000, text)).text, text);
+    }
+}
+
+#[test]
+fn replaced_words_keep_their_timing() {
+    let word = |start_ms, text: &str| Word {
+        start_ms,
+        end_ms: start_ms + 300,
+        text: text.to_string(),
+        probability: 0.5,
+    };
+    let mut segment = Segment::new(0, 1_200, "deploy cuber netties today");
+    segment.words = vec![word(0, "deploy"), word(300, "cuber"), word(600, "netties"), word(900, "today")];
+
+    let segment = vocabulary().correct(segment);
+    assert_eq!(segment.text, "deploy Kubernetes today");
+    let words: Vec<(i64, i64, &str)> = segment
+        .words
+        .iter()
+        .map(|word| (word.start_ms, word.end_ms, word.text.as_str()))
+        .collect();
+    assert_eq!(words, [(0, 300, "deploy"), (300, 900, "Kubernetes"), (900, 1_200, "today")]);
+}
+
+#[test]
+fn similar_sounding_words_share_a_key() {
+    assert_eq!(phonetic_key("cubernetties"), phonetic_key("kubernetes"));
+    assert_eq!(phonetic_key("graphana"), phonetic_key("grafana"));
+    assert_ne!(phonetic_key("figma"), phonetic_key("sigma"));
+    assert_eq!(edit_distance("kubernetes", "cubernetties"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
@@ -0,0 +1,66 @@
+use audio_recorder::transcript::group_words;
+use audio_recorder::{Token, Word};
+
+fn token(text: &str, start_ms: i64, end_ms: i64, probability: f32) -> Token {
+    Token {
+        id: 0,
+        text: text.into(),
+        probability,
+        logprob: probability.ln(),
+        start_ms: Some(start_ms),
+        end_ms: Some(end_ms),
+    }
+}
+
+fn word(text: &str, start_ms: i64, end_ms: i64, probability: f32) -> Word {
+    Word { start_ms, end_ms, text: text.into(), probability }
+}
+
+#[test]
+fn word_pieces_are_joined() {
+    let tokens = [
+        token(" trans", 0, 200, 1.0),
+        token("cri", 200, 300, 0.5),
+        token("ption", 300, 500, 0.75),
+        token(" works", 500, 900, 0.8),
+    ];
+    assert_eq!(
+        group_words(&tokens),
+        [word("transcription", 0, 500, 0.75), word("works", 500, 900, 0.8)]
+    );
+}
+
+#[test]
+fn leading_spaces_start_words_and_punctuation_stays_attached() {
+    let tokens = [
+        token("Hello", 0, 400, 1.0),
+        token(",", 400, 450, 0.5),
+        token(" world", 500, 900, 1.0),
+        token(".", 900, 950, 1.0),
+        token(" -", 1_000, 1_050, 1.0),
+        token(" ok", 1_100, 1_300, 1.0),
+    ];
+    assert_eq!(
+        group_words(&tokens),
+        [
+            word("Hello,", 0, 450, 0.75),
+            word("world.", 500, 950, 1.0),
+            word("-", 1_000, 1_050, 1.0),
+            word("ok", 1_100, 1_300, 1.0),
+        ]
+    );
+}
+
+#[test]
+fn untimed_and_blank_tokens_are_skipped() {
+    let mut untimed = token(" lost", 0, 0, 1.0);
+    untimed.start_ms = None;
+    let tokens = [
+        untimed,
+        token(" ", 0, 100, 0.1),
+        token(" yes", 100, 300, 0.5),
+        token(" ", 300, 350, 0.1),
+    ];
+    assert_eq!(group_words(&tokens), [word("yes", 100, 300, 0.5)]);
+    assert!(group_words(&[]).is_empty());
+}